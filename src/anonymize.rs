@@ -0,0 +1,140 @@
+//! Redaction of the instance names, ids and IPs for sharing reports publicly
+
+use std::collections::HashMap;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+
+use crate::InstanceResults;
+
+/// Aliases of the instance names, kept for the whole run so that an instance
+/// keeps its alias from one cycle to the next
+#[derive(Debug, Default)]
+pub struct Anonymizer {
+    aliases: HashMap<String, String>,
+}
+
+impl Anonymizer {
+    /// Alias of an instance name, `instance-1`, `instance-2`, ... in order of
+    /// first appearance
+    pub fn alias(&mut self, name: &str) -> String {
+        let next = self.aliases.len() + 1;
+        self.aliases
+            .entry(name.to_string())
+            .or_insert_with(|| format!("instance-{next}"))
+            .clone()
+    }
+
+    /// Copy of the results of an instance without its name, id and IPs
+    pub fn anonymize(&mut self, result: &InstanceResults) -> InstanceResults {
+        let mut tags = result.tags.clone();
+        tags.remove("Name");
+        InstanceResults {
+            name: self.alias(&result.name),
+            instance_id: hash_instance_id(&result.instance_id),
+            public_ip: None,
+            endpoint: result.endpoint.clone().map(|mut endpoint| {
+                endpoint.ip = String::new();
+                endpoint
+            }),
+            tags,
+            // The raw command outputs name the case directories
+            raw_probe: None,
+            connection_error: result.connection_error.as_deref().map(error_kind),
+            ..result.clone()
+        }
+    }
+
+    /// Copy of a map keyed by instance name, keyed by the aliases instead
+    pub fn alias_keys<V: Clone>(&mut self, map: &HashMap<String, V>) -> HashMap<String, V> {
+        map.iter()
+            .map(|(name, value)| (self.alias(name), value.clone()))
+            .collect()
+    }
+}
+
+/// Replace an instance id by a hash of it, the same for the same id
+pub fn hash_instance_id(instance_id: &str) -> String {
+    let mut hasher = DefaultHasher::new();
+    instance_id.hash(&mut hasher);
+    format!("i-{:016x}", hasher.finish())
+}
+
+/// Kind of a connection error, e.g. `Processing error`, without the details
+/// following it, which may name the host
+fn error_kind(error: &str) -> String {
+    error
+        .split(':')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{Endpoint, IpKind, RawProbe};
+
+    fn instance(name: &str, instance_id: &str, ip: &str) -> InstanceResults {
+        InstanceResults {
+            name: name.to_string(),
+            instance_id: instance_id.to_string(),
+            public_ip: Some(ip.to_string()),
+            endpoint: Some(Endpoint {
+                kind: IpKind::Public,
+                ip: ip.to_string(),
+                fallback: false,
+                other_reachable: None,
+            }),
+            tags: HashMap::from([
+                ("Name".to_string(), name.to_string()),
+                ("Mesh".to_string(), "fine".to_string()),
+            ]),
+            raw_probe: Some(RawProbe::default()),
+            ..InstanceResults::default()
+        }
+    }
+
+    #[test]
+    fn anonymization_is_stable_and_removes_the_sensitive_fields() {
+        let mut anonymizer = Anonymizer::default();
+        let wake = instance("wake_2ms", "i-0abc", "54.1.2.3");
+        let hull = InstanceResults {
+            connection_error: Some(
+                "Processing error: Failed to connect to 54.1.2.4:22 via 10.0.0.5".to_string(),
+            ),
+            ..instance("hull_7ms", "i-0def", "54.1.2.4")
+        };
+
+        let first = anonymizer.anonymize(&wake);
+        assert_eq!(first.name, "instance-1");
+        let second = anonymizer.anonymize(&hull);
+        assert_eq!(second.name, "instance-2");
+        assert_eq!(second.connection_error.as_deref(), Some("Processing error"));
+        assert_eq!(first.public_ip, None);
+        assert_eq!(first.endpoint.as_ref().unwrap().ip, "");
+        assert!(first.raw_probe.is_none());
+        assert_eq!(
+            first.tags,
+            HashMap::from([("Mesh".to_string(), "fine".to_string())])
+        );
+        assert!(first.instance_id.starts_with("i-"));
+        assert_ne!(first.instance_id, wake.instance_id);
+        let text = format!("{first:?} {second:?}");
+        for secret in [
+            "wake_2ms", "i-0abc", "54.1.2.3", "hull_7ms", "54.1.2.4", "10.0.0.5",
+        ] {
+            assert!(!text.contains(secret), "{secret} in {text}");
+        }
+
+        // The next cycle keeps the aliases and hashes
+        let again = anonymizer.anonymize(&wake);
+        assert_eq!(again.name, first.name);
+        assert_eq!(again.instance_id, first.instance_id);
+        let etas = HashMap::from([("hull_7ms".to_string(), vec![30.0])]);
+        assert_eq!(
+            anonymizer.alias_keys(&etas),
+            HashMap::from([("instance-2".to_string(), vec![30.0])])
+        );
+    }
+}
@@ -1,60 +1,430 @@
 //! AWS EC2 operations
 
-use aws_sdk_ec2::{Client, types::Filter};
-use crate::{MonitorError, InstanceInfo};
+use crate::{InstanceInfo, MonitorConfig, MonitorError};
+use aws_sdk_ec2::Client;
+use aws_sdk_ec2::types::{Filter, Instance, Reservation};
+use tracing::warn;
 
-/// Find all target instances (c8g.48xlarge and c6g.4xlarge) in running state
-pub async fn find_target_instances(client: &Client) -> Result<Vec<InstanceInfo>, MonitorError> {
+/// EC2 client of one of the monitored regions
+#[derive(Debug, Clone)]
+pub struct RegionClient {
+    pub region: String,
+    pub client: Client,
+}
+
+impl RegionClient {
+    /// Create the EC2 client of the region of an AWS configuration
+    pub fn new(sdk_config: &aws_config::SdkConfig) -> Self {
+        Self {
+            region: sdk_config
+                .region()
+                .map(|region| region.to_string())
+                .unwrap_or_default(),
+            client: Client::new(sdk_config),
+        }
+    }
+}
+
+/// EC2 client of a region, `None` if the region is not monitored
+pub fn client_in<'a>(clients: &'a [RegionClient], region: &str) -> Option<&'a Client> {
+    clients
+        .iter()
+        .find(|client| client.region == region)
+        .map(|client| &client.client)
+}
+
+/// Instance types searched when none are given
+pub const DEFAULT_INSTANCE_TYPES: [&str; 2] = ["c8g.48xlarge", "c6g.4xlarge"];
+
+/// Names of the EC2 instance states
+pub const INSTANCE_STATES: [&str; 6] = [
+    "pending",
+    "running",
+    "shutting-down",
+    "terminated",
+    "stopping",
+    "stopped",
+];
+
+/// Build the instance state filter, rejecting unknown state names
+pub fn state_filter(states: &[&str]) -> Result<Filter, MonitorError> {
+    if let Some(state) = states.iter().find(|state| !INSTANCE_STATES.contains(state)) {
+        return Err(MonitorError::InvalidInstanceState {
+            state: state.to_string(),
+        });
+    }
+    Ok(Filter::builder()
+        .name("instance-state-name")
+        .set_values(Some(states.iter().map(|state| state.to_string()).collect()))
+        .build())
+}
+
+/// Find all instances of the given types, [`DEFAULT_INSTANCE_TYPES`] when
+/// none are given, in one of the given states
+pub async fn find_target_instances(
+    client: &Client,
+    instance_types: &[String],
+    states: &[&str],
+) -> Result<Vec<InstanceInfo>, MonitorError> {
     let mut instances = Vec::new();
 
-    // Create filters for instance type and running state
+    // Create filters for instance type and state
     let filters = vec![
         Filter::builder()
             .name("instance-type")
-            .values("c8g.48xlarge")
-            .values("c6g.4xlarge")
-            .build(),
-        Filter::builder()
-            .name("instance-state-name")
-            .values("running")
+            .set_values(Some(searched_types(instance_types)))
             .build(),
+        state_filter(states)?,
     ];
 
-    let resp = client
+    // Collect the reservations of every page, large fleets spanning several
+    let mut pages = client
         .describe_instances()
         .set_filters(Some(filters))
-        .send()
-        .await
-        .map_err(|e| MonitorError::AwsSdk(e.to_string()))?;
+        .into_paginator()
+        .send();
+    while let Some(page) = pages.next().await {
+        let resp = page.map_err(|e| MonitorError::AwsSdk(e.to_string()))?;
+        instances.extend(instances_from_reservations(resp.reservations()));
+    }
+
+    Ok(instances)
+}
 
-    for reservation in resp.reservations() {
+/// Monitoring details of the instances of reservations, skipping with a
+/// warning the instances without an instance id
+pub fn instances_from_reservations(reservations: &[Reservation]) -> Vec<InstanceInfo> {
+    let mut instances = Vec::new();
+    for reservation in reservations {
         for instance in reservation.instances() {
-            let instance_id = instance.instance_id().unwrap_or("unknown").to_string();
+            match instance_info_from(instance) {
+                Some(info) => instances.push(info),
+                None => warn!("Skipping an instance without instance id"),
+            }
+        }
+    }
+    instances
+}
 
-            // Extract instance name from tags
-            let name = instance
-                .tags()
+/// Instance types searched for the given ones, the default types when empty
+pub fn searched_types(instance_types: &[String]) -> Vec<String> {
+    if instance_types.is_empty() {
+        DEFAULT_INSTANCE_TYPES.map(str::to_string).to_vec()
+    } else {
+        instance_types.to_vec()
+    }
+}
+
+/// Message of a discovery without any instance, telling what was searched
+pub fn no_instances_message(config: &MonitorConfig) -> String {
+    let regions = config.aws.regions.join(", ");
+    if config.instance_ids.is_empty() {
+        format!(
+            "No target instances found in {regions}: no {} instance in state {}",
+            searched_types(&config.instance_types).join(", "),
+            config.instance_states.join(", ")
+        )
+    } else {
+        format!(
+            "No target instances found in {regions}: no instance with id {}",
+            config.instance_ids.join(", ")
+        )
+    }
+}
+
+/// Find the instances of the given ids, regardless of their type and state,
+/// warning about the ids not found
+pub async fn find_instances_by_id(
+    client: &Client,
+    instance_ids: &[String],
+) -> Result<Vec<InstanceInfo>, MonitorError> {
+    let mut instances = Vec::new();
+    let mut pages = client
+        .describe_instances()
+        .filters(id_filter(instance_ids))
+        .into_paginator()
+        .send();
+    while let Some(page) = pages.next().await {
+        let resp = page.map_err(|e| MonitorError::AwsSdk(e.to_string()))?;
+        instances.extend(instances_from_reservations(resp.reservations()));
+    }
+    for id in missing_ids(instance_ids, &instances) {
+        warn!("Instance {id} not found");
+    }
+    Ok(instances)
+}
+
+/// Build the instance id filter, rather than `InstanceIds`, which fails the
+/// whole call on an unknown id
+pub fn id_filter(instance_ids: &[String]) -> Filter {
+    Filter::builder()
+        .name("instance-id")
+        .set_values(Some(instance_ids.to_vec()))
+        .build()
+}
+
+/// Requested instance ids without a discovered instance, in the requested order
+pub fn missing_ids<'a>(instance_ids: &'a [String], instances: &[InstanceInfo]) -> Vec<&'a str> {
+    instance_ids
+        .iter()
+        .filter(|id| {
+            !instances
                 .iter()
-                .find(|tag| tag.key().unwrap_or("") == "Name")
-                .and_then(|tag| tag.value())
-                .unwrap_or(&instance_id)
-                .to_string();
-
-            let instance_type = instance.instance_type()
-                .map(|t| t.as_str().to_string())
-                .unwrap_or_else(|| "unknown".to_string());
-            let public_ip = instance.public_ip_address().map(|ip| ip.to_string());
-            let private_ip = instance.private_ip_address().map(|ip| ip.to_string());
-
-            instances.push(InstanceInfo {
-                instance_id,
-                name,
-                instance_type,
-                public_ip,
-                private_ip,
-            });
+                .any(|instance| &instance.instance_id == *id)
+        })
+        .map(String::as_str)
+        .collect()
+}
+
+/// Find the monitored instances: the explicit instance ids if any, otherwise
+/// the instances of the target types and states
+pub async fn discover_instances(
+    clients: &[RegionClient],
+    config: &MonitorConfig,
+) -> Result<Vec<InstanceInfo>, MonitorError> {
+    let mut instances = Vec::new();
+    for RegionClient { region, client } in clients {
+        let found = if config.instance_ids.is_empty() {
+            find_target_instances(client, &config.instance_types, &config.instance_states()).await?
+        } else {
+            find_instances_by_id(client, &config.instance_ids).await?
+        };
+        instances.extend(found.into_iter().map(|instance| InstanceInfo {
+            region: region.clone(),
+            ..instance
+        }));
+    }
+    Ok(instances)
+}
+
+/// Id of the AWS account of the credentials, from STS `GetCallerIdentity`,
+/// or `None` with a warning if the call fails
+pub async fn caller_account(sdk_config: &aws_config::SdkConfig) -> Option<String> {
+    match aws_sdk_sts::Client::new(sdk_config)
+        .get_caller_identity()
+        .send()
+        .await
+    {
+        Ok(identity) => identity.account().map(str::to_string),
+        Err(e) => {
+            warn!("Failed to read the AWS account: {e}");
+            None
         }
     }
+}
 
-    Ok(instances)
-}
\ No newline at end of file
+/// Extract the monitoring details of an EC2 instance, `None` if it has no instance id
+pub fn instance_info_from(instance: &Instance) -> Option<InstanceInfo> {
+    let instance_id = instance.instance_id()?.to_string();
+
+    // Extract instance name from tags
+    let tag = |key: &str| {
+        instance
+            .tags()
+            .iter()
+            .find(|tag| tag.key().unwrap_or("") == key)
+            .and_then(|tag| tag.value())
+    };
+    let name = tag("Name").unwrap_or(&instance_id).to_string();
+    let tags = instance
+        .tags()
+        .iter()
+        .filter_map(|tag| Some((tag.key()?.to_string(), tag.value()?.to_string())))
+        .collect();
+    let total_steps_tag = tag("TotalSteps").and_then(|value| {
+        let steps = parse_total_steps_tag(value);
+        if steps.is_none() {
+            warn!("Ignoring invalid TotalSteps tag {value:?} of {name}");
+        }
+        steps
+    });
+
+    let instance_type = instance
+        .instance_type()
+        .map(|t| t.as_str().to_string())
+        .unwrap_or_else(|| "unknown".to_string());
+    let public_ip = instance.public_ip_address().map(|ip| ip.to_string());
+    let private_ip = instance.private_ip_address().map(|ip| ip.to_string());
+    let launch_time = instance
+        .launch_time()
+        .and_then(|t| chrono::DateTime::from_timestamp(t.secs(), t.subsec_nanos()));
+
+    Some(InstanceInfo {
+        instance_id,
+        name,
+        instance_type,
+        public_ip,
+        private_ip,
+        launch_time,
+        total_steps_tag,
+        tags,
+        region: String::new(),
+    })
+}
+
+/// Parse the value of a `TotalSteps` tag, `None` unless it is a positive integer
+pub fn parse_total_steps_tag(value: &str) -> Option<usize> {
+    value.trim().parse().ok().filter(|&steps| steps > 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use aws_sdk_ec2::types::Tag;
+
+    fn instance(id: Option<&str>, name: &str) -> Instance {
+        Instance::builder()
+            .set_instance_id(id.map(str::to_string))
+            .tags(Tag::builder().key("Name").value(name).build())
+            .build()
+    }
+
+    #[test]
+    fn instances_without_ids_are_excluded() {
+        let reservations = [
+            Reservation::builder()
+                .instances(instance(Some("i-1"), "node-1"))
+                .instances(instance(None, "orphan-1"))
+                .build(),
+            Reservation::builder()
+                .instances(instance(None, "orphan-2"))
+                .instances(instance(Some("i-2"), "node-2"))
+                .build(),
+        ];
+        let ids: Vec<_> = instances_from_reservations(&reservations)
+            .into_iter()
+            .map(|info| (info.instance_id, info.name))
+            .collect();
+        assert_eq!(
+            ids,
+            [
+                ("i-1".to_string(), "node-1".to_string()),
+                ("i-2".to_string(), "node-2".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    fn state_filter_is_built_from_the_states() {
+        let filter = state_filter(&["running", "pending"]).unwrap();
+        assert_eq!(filter.name(), Some("instance-state-name"));
+        assert_eq!(filter.values(), ["running", "pending"]);
+    }
+
+    #[test]
+    fn unknown_states_are_rejected() {
+        let err = state_filter(&["running", "sleeping"]).unwrap_err();
+        assert!(matches!(err, MonitorError::InvalidInstanceState { state } if state == "sleeping"));
+    }
+
+    #[test]
+    fn requested_ids_are_filtered_and_the_unknown_ones_missing() {
+        let instance_ids = ["i-3", "i-1", "i-9"].map(str::to_string);
+        let filter = id_filter(&instance_ids);
+        assert_eq!(filter.name(), Some("instance-id"));
+        assert_eq!(filter.values(), instance_ids);
+
+        let reservations = [Reservation::builder()
+            .instances(instance(Some("i-1"), "node-1"))
+            .instances(instance(Some("i-3"), "node-3"))
+            .build()];
+        let instances = instances_from_reservations(&reservations);
+        assert_eq!(missing_ids(&instance_ids, &instances), ["i-9"]);
+        assert_eq!(missing_ids(&instance_ids, &[]), ["i-3", "i-1", "i-9"]);
+    }
+
+    /// `DescribeInstances` response page of one instance, with the token of
+    /// the next page if any
+    fn describe_page(instance_id: &str, next_token: Option<&str>) -> String {
+        let next_token = next_token
+            .map(|token| format!("<nextToken>{token}</nextToken>"))
+            .unwrap_or_default();
+        format!(
+            r#"<DescribeInstancesResponse xmlns="http://ec2.amazonaws.com/doc/2016-11-15/">
+<requestId>request-1</requestId>
+<reservationSet><item><reservationId>r-{instance_id}</reservationId><instancesSet><item>
+<instanceId>{instance_id}</instanceId><instanceType>c6g.4xlarge</instanceType>
+<tagSet><item><key>Name</key><value>node-{instance_id}</value></item></tagSet>
+</item></instancesSet></item></reservationSet>{next_token}
+</DescribeInstancesResponse>"#
+        )
+    }
+
+    /// Serve the pages in turn over HTTP, one per connection, returning the request bodies
+    async fn serve_pages(listener: tokio::net::TcpListener, pages: Vec<String>) -> Vec<String> {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let mut bodies = Vec::new();
+        for page in pages {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut request = Vec::new();
+            let mut buf = [0u8; 4096];
+            let body = loop {
+                let n = stream.read(&mut buf).await.unwrap();
+                assert!(n > 0, "connection closed before the end of the request");
+                request.extend_from_slice(&buf[..n]);
+                let text = String::from_utf8_lossy(&request).to_string();
+                let Some((head, body)) = text.split_once("\r\n\r\n") else {
+                    continue;
+                };
+                let length = head
+                    .lines()
+                    .find_map(|line| {
+                        let (name, value) = line.split_once(':')?;
+                        name.eq_ignore_ascii_case("content-length")
+                            .then(|| value.trim().parse::<usize>().ok())?
+                    })
+                    .unwrap_or(0);
+                if body.len() >= length {
+                    break body.to_string();
+                }
+            };
+            bodies.push(body);
+            let response = format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: text/xml\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{page}",
+                page.len()
+            );
+            stream.write_all(response.as_bytes()).await.unwrap();
+        }
+        bodies
+    }
+
+    #[tokio::test]
+    async fn instances_of_every_page_are_collected() {
+        use aws_sdk_ec2::config::{BehaviorVersion, Credentials, Region};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let endpoint = format!("http://{}", listener.local_addr().unwrap());
+        let pages = vec![
+            describe_page("i-1", Some("page-2")),
+            describe_page("i-2", Some("page-3")),
+            describe_page("i-3", None),
+        ];
+        let server = tokio::spawn(serve_pages(listener, pages));
+
+        let config = aws_sdk_ec2::Config::builder()
+            .behavior_version(BehaviorVersion::latest())
+            .region(Region::new("us-east-1"))
+            .credentials_provider(Credentials::new(
+                "AKIDEXAMPLE",
+                "secret",
+                None,
+                None,
+                "test",
+            ))
+            .endpoint_url(endpoint)
+            .build();
+        let client = Client::from_conf(config);
+
+        let instances = find_target_instances(&client, &[], &["running"])
+            .await
+            .unwrap();
+        let ids: Vec<_> = instances.iter().map(|i| i.instance_id.as_str()).collect();
+        assert_eq!(ids, ["i-1", "i-2", "i-3"]);
+
+        let bodies = server.await.unwrap();
+        assert!(!bodies[0].contains("NextToken"), "{}", bodies[0]);
+        assert!(bodies[1].contains("NextToken=page-2"), "{}", bodies[1]);
+        assert!(bodies[2].contains("NextToken=page-3"), "{}", bodies[2]);
+    }
+}
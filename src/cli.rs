@@ -0,0 +1,662 @@
+//! Command line interface
+
+use chrono_tz::Tz;
+use clap::error::ErrorKind;
+use clap::parser::ValueSource;
+use clap::{Args, CommandFactory, FromArgMatches, Parser, Subcommand};
+use std::ffi::OsString;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use crate::MonitorConfig;
+use crate::idle::IdleAction;
+use crate::report::BorderStyle;
+use crate::types::{IpPreference, WindSpeedSteps};
+
+/// Monitor AWS EC2 instances running computational simulation jobs
+#[derive(Debug, Parser)]
+#[command(name = "ec2_monitor", version)]
+pub struct Cli {
+    #[command(flatten)]
+    pub global: GlobalArgs,
+    /// Options of the default `run` command
+    #[command(flatten)]
+    pub run: RunArgs,
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+/// Options shared by every command
+#[derive(Debug, Default, Args)]
+pub struct GlobalArgs {
+    /// AWS regions of the instances, comma separated
+    /// (default: AWS_REGION, else the profile region, else sa-east-1)
+    #[arg(
+        long = "region",
+        global = true,
+        value_name = "REGIONS",
+        value_delimiter = ','
+    )]
+    pub regions: Vec<String>,
+    /// Instance types to monitor, comma separated (default: c8g.48xlarge,c6g.4xlarge)
+    #[arg(long, global = true, value_name = "TYPES", value_delimiter = ',')]
+    pub types: Vec<String>,
+    /// Instance states to monitor, comma separated (default: running)
+    #[arg(long, global = true, value_name = "STATES", value_delimiter = ',', value_parser = crate::aws::INSTANCE_STATES)]
+    pub states: Vec<String>,
+    /// Monitor these instance ids, comma separated, regardless of their type and state
+    #[arg(long, global = true, value_name = "IDS", value_delimiter = ',', conflicts_with_all = ["types", "states"])]
+    pub ids: Vec<String>,
+    /// Seconds between the starts of two monitoring cycles, also the period of
+    /// the ETA rate when it can't be measured (default: MONITOR_INTERVAL, else 360)
+    #[arg(long, global = true, value_name = "SECONDS", value_parser = clap::value_parser!(u64).range(1..))]
+    pub interval: Option<u64>,
+    /// Log more status messages, e.g. every instance being processed, unless
+    /// RUST_LOG is set
+    #[arg(short, long, global = true, action = clap::ArgAction::Count)]
+    pub verbose: u8,
+    /// SSH private key of the instances, repeatable, each tried in turn
+    /// (default: the colon-separated paths of AWS_KEYPAIR)
+    #[arg(long = "key", global = true, value_name = "PATH")]
+    pub keys: Vec<PathBuf>,
+    /// SSH usernames of the instances, comma separated, tried in turn
+    /// (default: SSH_USERNAME, else ubuntu,ec2-user,admin)
+    #[arg(
+        long = "ssh-user",
+        global = true,
+        value_name = "USERS",
+        value_delimiter = ','
+    )]
+    pub ssh_users: Vec<String>,
+    /// Port of the sshd of the instances (default: SSH_PORT, else 22)
+    #[arg(long, global = true, value_name = "PORT", value_parser = clap::value_parser!(u16).range(1..))]
+    pub ssh_port: Option<u16>,
+    /// Run the remote commands with SHELL -c, e.g. bash where the login shell is
+    /// sh or dash (default: the login shell)
+    #[arg(long, global = true, value_name = "SHELL")]
+    pub remote_shell: Option<String>,
+}
+
+/// Commands of the monitor
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Monitor the fleet continuously (default)
+    Run(RunArgs),
+    /// Run a single monitoring cycle and exit
+    Once(RunArgs),
+    /// List the instances matched by the filters and exit
+    List,
+    /// Probe the fleet twice, one interval apart, and print what changed
+    Diff(RunArgs),
+    /// Run a shell command on every instance and print its output
+    Exec {
+        /// Command to run
+        #[arg(required = true, trailing_var_arg = true, allow_hyphen_values = true)]
+        command: Vec<String>,
+    },
+    /// Summarize the campaign from the history of a state file and exit
+    CampaignReport {
+        /// State file written with --state-file
+        #[arg(value_name = "STATE_FILE")]
+        state_file: PathBuf,
+    },
+    /// Print the version and exit
+    Version,
+}
+
+/// Options of the monitoring cycles
+#[derive(Debug, Default, Clone, Args)]
+pub struct RunArgs {
+    /// Interactive table with per-instance details
+    #[arg(long)]
+    pub interactive: bool,
+    /// Only print instances that changed since the last cycle
+    #[arg(long)]
+    pub changes_only: bool,
+    /// Print each cycle's report as one JSON object with the instances and
+    /// the fleet aggregates
+    #[arg(long, conflicts_with = "changes_only")]
+    pub json: bool,
+    /// Page each cycle's report through $PAGER (default: less)
+    #[arg(long, conflicts_with_all = ["changes_only", "json", "interactive"])]
+    pub pager: bool,
+    /// Fail instances whose name has an unknown wind speed
+    #[arg(long)]
+    pub strict_wind_speed: bool,
+    /// Truncate instance names longer than CHARS (default: 20)
+    #[arg(long, value_name = "CHARS", value_parser = clap::value_parser!(u16).range(4..))]
+    pub name_width: Option<u16>,
+    /// Show instance names and connection errors in full, growing the columns to fit them
+    #[arg(long)]
+    pub wide: bool,
+    /// Show large step and CSV counts with K/M/G suffixes, e.g. 1.2M
+    #[arg(long)]
+    pub human: bool,
+    /// Group the report rows by the value of the instance tag KEY, with ETA subtotals
+    #[arg(long, value_name = "KEY")]
+    pub group_by: Option<String>,
+    /// Show only the N most relevant rows of the report, failed, stalled or
+    /// diverged instances first then by ETA, with a count of the others
+    #[arg(long, visible_alias = "page", value_name = "N", value_parser = clap::value_parser!(u32).range(1..))]
+    pub top: Option<u32>,
+    /// Redraw the report at most every SECONDS, drawing the latest results
+    /// at the end of it (default: every cycle)
+    #[arg(long, value_name = "SECONDS")]
+    pub render_interval: Option<u64>,
+    /// Show the report times in the IANA timezone TZ, e.g. Europe/Paris (default: local)
+    #[arg(long, value_name = "TZ")]
+    pub timezone: Option<Tz>,
+    /// Replace instance names by instance-1..N, hash instance ids and hide IPs
+    /// and the account in the reports and the history export
+    #[arg(long)]
+    pub anonymize: bool,
+    /// Measure the average step rate from the solver start time instead of the
+    /// first observation
+    #[arg(long)]
+    pub solver_start_time: bool,
+    /// Flag solvers running fewer threads than the node has processors
+    #[arg(long)]
+    pub check_threads: bool,
+    /// Process whose threads are counted, implies --check-threads (default: zcsvs)
+    #[arg(long, value_name = "NAME")]
+    pub solver_process: Option<String>,
+    /// Path of the solver log on the instances, {name} standing for the
+    /// instance name (default: {name}/solve.out)
+    #[arg(long, value_name = "TEMPLATE")]
+    pub log_path: Option<String>,
+    /// Text marking the log lines of the solver steps (default: TimeStep)
+    #[arg(long, value_name = "MARKER")]
+    pub timestep_marker: Option<String>,
+    /// Show the resident memory of the solver processes in a Mem column
+    #[arg(long)]
+    pub check_memory: bool,
+    /// Flag solvers using more than FRACTION of the instance memory,
+    /// implies --check-memory (default: 0.9)
+    #[arg(long, value_name = "FRACTION")]
+    pub memory_threshold: Option<f64>,
+    /// Show the network throughput of the instances running an s3 sync in a Net column
+    #[arg(long)]
+    pub check_network: bool,
+    /// Show an ETA for the s3 sync, and for the finalize with --output-target-gb,
+    /// from the rate of their output bytes
+    #[arg(long)]
+    pub output_eta: bool,
+    /// Size in GB of a finalized case directory, implies --output-eta
+    #[arg(long, value_name = "GB", value_parser = clap::value_parser!(u64).range(1..))]
+    pub output_target_gb: Option<u64>,
+    /// Show FS read-only instead of the ETA when the case directory is on a read-only file system
+    #[arg(long)]
+    pub check_writable: bool,
+    /// Flag solvers whose log tail has nan or inf residuals or fields
+    #[arg(long)]
+    pub check_divergence: bool,
+    /// Count solver warnings and errors in the log tail
+    #[arg(long)]
+    pub check_warnings: bool,
+    /// Warning pattern, repeatable, replacing the defaults (FOAM Warning,
+    /// FOAM FATAL ERROR, Courant number exceeds), implies --check-warnings
+    #[arg(long = "warning-pattern", value_name = "PATTERN")]
+    pub warning_patterns: Vec<String>,
+    /// Flag instances with more warnings than COUNT (default: 0)
+    #[arg(long, value_name = "COUNT")]
+    pub warning_threshold: Option<usize>,
+    /// Table rules: ascii, unicode or none (default: ascii)
+    #[arg(long, value_name = "STYLE")]
+    pub border: Option<BorderStyle>,
+    /// Keep terminated complete instances in the report (default: 10)
+    #[arg(long, value_name = "CYCLES")]
+    pub keep_complete: Option<usize>,
+    /// Also show the idle complete simulations in the ETA, those running a
+    /// post-process always showing it
+    #[arg(long)]
+    pub phases: bool,
+    /// Total steps of every instance, ignoring the controlDict and wind speed
+    #[arg(long, value_name = "STEPS", value_parser = clap::value_parser!(u64).range(1..))]
+    pub total_steps: Option<u64>,
+    /// Total steps assumed for the cases whose name has no known wind speed, e.g. 18000
+    #[arg(long, value_name = "STEPS", value_parser = clap::value_parser!(u64).range(1..))]
+    pub default_total_steps: Option<u64>,
+    /// Total steps of the cases by wind speed, e.g. 2ms=36000,22ms=18000, adding
+    /// to or replacing the defaults 2ms=24000,7ms=18000,12ms=18000,17ms=18000
+    #[arg(long, value_name = "SPEED=STEPS")]
+    pub wind_steps: Option<WindSpeedSteps>,
+    /// Read total steps from each case's system/controlDict
+    #[arg(long)]
+    pub control_dict: bool,
+    /// Verify host keys against ~/.ssh/known_hosts
+    #[arg(long)]
+    pub verify_host_keys: bool,
+    /// Also refresh host keys of replaced instances, implies --verify-host-keys
+    #[arg(long)]
+    pub refresh_host_keys: bool,
+    /// Tunnel SSH through a SOCKS5 proxy (default: socks5:// ALL_PROXY)
+    #[arg(long, value_name = "HOST:PORT")]
+    pub socks_proxy: Option<String>,
+    /// Open at most N connections at once through the SOCKS5 proxy (default: 16)
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u64).range(1..))]
+    pub proxy_connections: Option<u64>,
+    /// Verify host keys against PATH instead of ~/.ssh/known_hosts
+    #[arg(long, value_name = "PATH")]
+    pub known_hosts: Option<PathBuf>,
+    /// Plain output without terminal clearing or emoji
+    #[arg(long, conflicts_with = "fancy")]
+    pub plain: bool,
+    /// Decorated output even when not writing to a terminal
+    #[arg(long)]
+    pub fancy: bool,
+    /// Report failures of just-launched instances as starting up (default: 180)
+    #[arg(long, value_name = "SECONDS")]
+    pub startup_grace: Option<u64>,
+    /// IPs to try: public, private, public-private or private-public (default: public)
+    #[arg(long, value_name = "ORDER")]
+    pub ip_preference: Option<IpPreference>,
+    /// Same as --ip-preference private-public
+    #[arg(long, conflicts_with = "ip_preference")]
+    pub prefer_private_ip: bool,
+    /// Show the IP used per instance and whether the other is reachable
+    #[arg(long)]
+    pub show_endpoints: bool,
+    /// Show the current and lifetime average steps per minute
+    #[arg(long)]
+    pub show_rates: bool,
+    /// Show the connection and SSH handshake time of each instance
+    #[arg(long)]
+    pub show_latency: bool,
+    /// Do not record ETA samples before this step (default: 0)
+    #[arg(long, value_name = "STEPS")]
+    pub warmup_steps: Option<usize>,
+    /// Do not record ETA samples of a job's first cycles (default: 0)
+    #[arg(long, value_name = "CYCLES")]
+    pub warmup_cycles: Option<usize>,
+    /// Leave the first ETA sample of each job out of its median, once it has 3 samples
+    #[arg(long)]
+    pub skip_first_eta: bool,
+    /// Fit the ETA to the last SAMPLES steps instead of the last cycle's rate
+    #[arg(long, value_name = "SAMPLES", value_parser = clap::value_parser!(u64).range(2..))]
+    pub regression_eta: Option<u64>,
+    /// Alert when an instance disappears from the discovery before completing
+    #[arg(long)]
+    pub alert_vanished: bool,
+    /// Retries allowed across the fleet per cycle (default: 10)
+    #[arg(long, value_name = "RETRIES")]
+    pub retry_budget: Option<usize>,
+    /// Retries of the transient SSH failures of an instance, e.g. connection
+    /// refused while sshd starts (default: 3)
+    #[arg(long, value_name = "RETRIES")]
+    pub retries: Option<usize>,
+    /// Seconds before the first retry, doubled for every further retry (default: 2)
+    #[arg(long, value_name = "SECONDS")]
+    pub retry_delay: Option<u64>,
+    /// Probe the instances one at a time in name order, for debugging SSH issues
+    #[arg(long)]
+    pub sequential: bool,
+    /// Random delay of up to SECONDS before probing each instance (default: 0)
+    #[arg(long, value_name = "SECONDS")]
+    pub jitter: Option<u64>,
+    /// SSH keepalive interval, 0 to disable (default: 15)
+    #[arg(long, value_name = "SECONDS")]
+    pub keepalive: Option<u64>,
+    /// Give up on the TCP connection to an instance, e.g. a port blocked by its
+    /// security group, after SECONDS (default: 10)
+    #[arg(long, value_name = "SECONDS", value_parser = clap::value_parser!(u64).range(1..))]
+    pub connect_timeout: Option<u64>,
+    /// Give up on an SSH handshake, e.g. a delayed server banner, after SECONDS (default: 20)
+    #[arg(long, value_name = "SECONDS", value_parser = clap::value_parser!(u64).range(1..))]
+    pub handshake_timeout: Option<u64>,
+    /// Give up on an SSH command output silent for SECONDS (default: 60)
+    #[arg(long, value_name = "SECONDS", value_parser = clap::value_parser!(u64).range(1..))]
+    pub read_timeout: Option<u64>,
+    /// Report the instances still being probed after SECONDS as timed out
+    /// (default: the refresh interval)
+    #[arg(long, value_name = "SECONDS", value_parser = clap::value_parser!(u64).range(1..))]
+    pub cycle_timeout: Option<u64>,
+    /// Serve the fleet summary as JSON on ADDR/summary
+    #[arg(long, value_name = "ADDR")]
+    pub serve: Option<SocketAddr>,
+    /// Export the progress history to a CSV file
+    #[arg(long, value_name = "PATH")]
+    pub history_csv: Option<PathBuf>,
+    /// Append each cycle's step and ETA to DIR/<instance>.csv
+    #[arg(long, value_name = "DIR")]
+    pub eta_log_dir: Option<PathBuf>,
+    /// Keep the ETA state across restarts in PATH, gzip-compressed if it ends in .gz
+    #[arg(long, value_name = "PATH")]
+    pub state_file: Option<PathBuf>,
+    /// Rotate the state file once it reaches KB kilobytes, 0 never rotating it (default: 1024)
+    #[arg(long, value_name = "KB")]
+    pub state_rotate_kb: Option<u64>,
+    /// Number of state files kept, including the current one (default: 3)
+    #[arg(long, value_name = "FILES", value_parser = clap::value_parser!(u64).range(1..))]
+    pub state_keep: Option<u64>,
+    /// Append the discovered instances and raw command outputs of every cycle
+    /// to PATH, one JSON line per cycle
+    #[arg(long, value_name = "PATH")]
+    pub record: Option<PathBuf>,
+    /// Keep one history sample per interval (default: 30)
+    #[arg(long, value_name = "MINUTES", value_parser = clap::value_parser!(u64).range(1..))]
+    pub history_interval: Option<u64>,
+    /// Warn when an s3 sync has been running for longer than MINUTES (default: 120)
+    #[arg(long, value_name = "MINUTES", value_parser = clap::value_parser!(u64).range(1..))]
+    pub stuck_sync_minutes: Option<u64>,
+    /// Warn when the CSV count stays flat for CYCLES cycles while the steps advance (default: 3)
+    #[arg(long, value_name = "CYCLES", value_parser = clap::value_parser!(u32).range(1..))]
+    pub csv_stall_cycles: Option<u32>,
+    /// Stop or terminate the finished instances left idle: stop or terminate
+    #[arg(long, value_name = "ACTION")]
+    pub idle_action: Option<IdleAction>,
+    /// Idle time after which the idle action is taken (default: 30)
+    #[arg(long, value_name = "MINUTES", requires = "idle_action", value_parser = clap::value_parser!(u64).range(1..))]
+    pub idle_timeout: Option<u64>,
+    /// Log the idle actions without taking them
+    #[arg(long, requires = "idle_action")]
+    pub dry_run: bool,
+}
+
+impl Cli {
+    /// Parse the command line, exiting with the usage on error
+    pub fn parse_args() -> Self {
+        Self::try_parse_args(std::env::args_os()).unwrap_or_else(|e| e.exit())
+    }
+
+    /// Parse a command line, rejecting the options of the default `run` command
+    /// given before a subcommand, which would otherwise be silently ignored
+    pub fn try_parse_args<I, T>(args: I) -> Result<Self, clap::Error>
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<OsString> + Clone,
+    {
+        let mut command = Self::command();
+        let matches = command.try_get_matches_from_mut(args)?;
+        if let Some((subcommand, _)) = matches.subcommand() {
+            let run_args = RunArgs::augment_args(clap::Command::new("run"));
+            if let Some(arg) = run_args.get_arguments().find(|arg| {
+                matches.value_source(arg.get_id().as_str()) == Some(ValueSource::CommandLine)
+            }) {
+                let name = arg.get_long().unwrap_or(arg.get_id().as_str());
+                return Err(command.error(
+                    ErrorKind::ArgumentConflict,
+                    format!("--{name} must be given after the `{subcommand}` subcommand"),
+                ));
+            }
+        }
+        Self::from_arg_matches(&matches)
+    }
+
+    /// Resolve the command to run, `run` by default, and its configuration,
+    /// the command line options overriding the environment
+    pub fn into_command(self) -> (Command, MonitorConfig) {
+        let mut config = MonitorConfig::from_env();
+        self.global.apply(&mut config);
+        let command = self.command.unwrap_or(Command::Run(self.run));
+        if let Command::Run(args) | Command::Once(args) | Command::Diff(args) = &command {
+            args.clone().apply(&mut config);
+        }
+        (command, config)
+    }
+}
+
+impl GlobalArgs {
+    /// Override the configuration with the given global options
+    pub fn apply(self, config: &mut MonitorConfig) {
+        if !self.regions.is_empty() {
+            config.aws.regions = self.regions;
+        }
+        if !self.types.is_empty() {
+            config.instance_types = self.types;
+        }
+        if !self.states.is_empty() {
+            config.instance_states = self.states;
+        }
+        if !self.ids.is_empty() {
+            config.instance_ids = self.ids;
+        }
+        if let Some(secs) = self.interval {
+            config.refresh_interval = Duration::from_secs(secs);
+        }
+        config.verbosity = self.verbose;
+        if !self.keys.is_empty() {
+            config.key_paths = self.keys;
+        }
+        if !self.ssh_users.is_empty() {
+            config.ssh_usernames = self.ssh_users;
+        }
+        if let Some(port) = self.ssh_port {
+            config.ssh_port = port;
+        }
+        if let Some(shell) = self.remote_shell {
+            config.remote_shell = Some(shell);
+        }
+    }
+}
+
+impl RunArgs {
+    /// Override the configuration with the given options
+    pub fn apply(self, config: &mut MonitorConfig) {
+        config.interactive |= self.interactive;
+        config.changes_only |= self.changes_only;
+        config.json_output |= self.json;
+        config.pager |= self.pager;
+        config.strict_wind_speed |= self.strict_wind_speed;
+        if let Some(width) = self.name_width {
+            config.name_width = width.into();
+        }
+        config.wide_report |= self.wide;
+        config.human_counts |= self.human;
+        if let Some(key) = self.group_by {
+            config.group_by = Some(key);
+        }
+        if let Some(rows) = self.top {
+            config.top_rows = Some(rows as usize);
+        }
+        if let Some(secs) = self.render_interval {
+            config.render_interval = Duration::from_secs(secs);
+        }
+        if let Some(timezone) = self.timezone {
+            config.display_timezone = Some(timezone);
+        }
+        config.anonymize |= self.anonymize;
+        config.read_solver_start |= self.solver_start_time;
+        config.check_threads |= self.check_threads;
+        if let Some(name) = self.solver_process {
+            config.check_threads = true;
+            config.solver_process = name;
+        }
+        if let Some(template) = self.log_path {
+            config.log_path_template = template;
+        }
+        if let Some(marker) = self.timestep_marker {
+            config.timestep_marker = marker;
+        }
+        config.check_memory |= self.check_memory;
+        if let Some(fraction) = self.memory_threshold {
+            config.check_memory = true;
+            config.memory_threshold = fraction;
+        }
+        config.check_network |= self.check_network;
+        config.output_eta |= self.output_eta;
+        if let Some(gb) = self.output_target_gb {
+            config.output_eta = true;
+            config.output_target_bytes = Some(gb * 1_000_000_000);
+        }
+        config.check_writable |= self.check_writable;
+        config.check_divergence |= self.check_divergence;
+        config.check_warnings |= self.check_warnings;
+        if !self.warning_patterns.is_empty() {
+            config.check_warnings = true;
+            config.warning_patterns = self.warning_patterns;
+        }
+        if let Some(count) = self.warning_threshold {
+            config.warning_threshold = count;
+        }
+        if let Some(style) = self.border {
+            config.border_style = style;
+        }
+        if let Some(cycles) = self.keep_complete {
+            config.complete_retention_cycles = cycles;
+        }
+        config.post_completion_phases |= self.phases;
+        if let Some(steps) = self.total_steps {
+            config.total_steps = Some(steps as usize);
+        }
+        if let Some(steps) = self.default_total_steps {
+            config.default_total_step = Some(steps as usize);
+        }
+        if let Some(steps) = self.wind_steps {
+            config.wind_speed_steps.extend(steps);
+        }
+        config.read_control_dict |= self.control_dict;
+        config.verify_host_keys |= self.verify_host_keys || self.refresh_host_keys;
+        config.refresh_replaced_host_keys |= self.refresh_host_keys;
+        if let Some(proxy) = self.socks_proxy {
+            config.socks_proxy = Some(proxy);
+        }
+        if let Some(connections) = self.proxy_connections {
+            config.proxy_connections = connections as usize;
+        }
+        if let Some(path) = self.known_hosts {
+            config.verify_host_keys = true;
+            config.known_hosts_path = Some(path);
+        }
+        if self.plain {
+            config.plain_output = Some(true);
+        } else if self.fancy {
+            config.plain_output = Some(false);
+        }
+        if let Some(secs) = self.startup_grace {
+            config.startup_grace = Duration::from_secs(secs);
+        }
+        if let Some(preference) = self.ip_preference {
+            config.ip_preference = preference;
+        } else if self.prefer_private_ip {
+            config.ip_preference = IpPreference::PrivateThenPublic;
+        }
+        config.show_endpoints |= self.show_endpoints;
+        config.show_rates |= self.show_rates;
+        config.show_latency |= self.show_latency;
+        if let Some(steps) = self.warmup_steps {
+            config.warmup_steps = steps;
+        }
+        if let Some(cycles) = self.warmup_cycles {
+            config.warmup_cycles = cycles;
+        }
+        config.skip_first_eta_sample |= self.skip_first_eta;
+        if let Some(samples) = self.regression_eta {
+            config.regression_samples = samples as usize;
+        }
+        if let Some(retries) = self.retry_budget {
+            config.retry_budget = retries;
+        }
+        if let Some(retries) = self.retries {
+            config.instance_retries = retries;
+        }
+        if let Some(secs) = self.retry_delay {
+            config.retry_delay = Duration::from_secs(secs);
+        }
+        config.alert_vanished |= self.alert_vanished;
+        config.sequential |= self.sequential;
+        if let Some(secs) = self.jitter {
+            config.startup_jitter = Duration::from_secs(secs);
+        }
+        if let Some(secs) = self.keepalive {
+            config.keepalive_interval = Duration::from_secs(secs);
+        }
+        if let Some(secs) = self.connect_timeout {
+            config.connect_timeout = Duration::from_secs(secs);
+        }
+        if let Some(secs) = self.handshake_timeout {
+            config.handshake_timeout = Duration::from_secs(secs);
+        }
+        if let Some(secs) = self.read_timeout {
+            config.read_timeout = Duration::from_secs(secs);
+        }
+        if let Some(secs) = self.cycle_timeout {
+            config.cycle_timeout = Some(Duration::from_secs(secs));
+        }
+        if let Some(addr) = self.serve {
+            config.summary_addr = Some(addr);
+        }
+        if let Some(path) = self.history_csv {
+            config.history_csv = Some(path);
+        }
+        if let Some(dir) = self.eta_log_dir {
+            config.eta_log_dir = Some(dir);
+        }
+        if let Some(path) = self.state_file {
+            config.state_file = Some(path);
+        }
+        if let Some(kb) = self.state_rotate_kb {
+            config.state_rotate_bytes = kb * 1024;
+        }
+        if let Some(files) = self.state_keep {
+            config.state_keep = files as usize;
+        }
+        if let Some(path) = self.record {
+            config.record_path = Some(path);
+        }
+        if let Some(minutes) = self.history_interval {
+            config.history_interval = Duration::from_secs(minutes * 60);
+        }
+        if let Some(minutes) = self.stuck_sync_minutes {
+            config.stuck_sync_limit = Duration::from_secs(minutes * 60);
+        }
+        if let Some(cycles) = self.csv_stall_cycles {
+            config.csv_stall_cycles = cycles as usize;
+        }
+        if let Some(action) = self.idle_action {
+            config.idle_action = Some(action);
+        }
+        if let Some(minutes) = self.idle_timeout {
+            config.idle_timeout = Duration::from_secs(minutes * 60);
+        }
+        config.dry_run |= self.dry_run;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Cli, clap::Error> {
+        Cli::try_parse_args(std::iter::once("ec2_monitor").chain(args.iter().copied()))
+    }
+
+    #[test]
+    fn no_subcommand_runs_with_the_top_level_options() {
+        let cli = parse(&["--json", "--wide"]).unwrap();
+        assert!(cli.command.is_none());
+        assert!(cli.run.json && cli.run.wide);
+    }
+
+    #[test]
+    fn subcommands_take_their_own_and_the_global_options() {
+        let cli = parse(&[
+            "--interval",
+            "60",
+            "once",
+            "--json",
+            "--region",
+            "eu-west-1",
+        ])
+        .unwrap();
+        assert_eq!(cli.global.interval, Some(60));
+        assert_eq!(cli.global.regions, ["eu-west-1"]);
+        assert!(matches!(cli.command, Some(Command::Once(ref args)) if args.json));
+
+        let cli = parse(&["exec", "df", "-h", "/"]).unwrap();
+        assert!(
+            matches!(cli.command, Some(Command::Exec { ref command }) if command == &["df", "-h", "/"])
+        );
+        assert!(matches!(
+            parse(&["list"]).unwrap().command,
+            Some(Command::List)
+        ));
+    }
+
+    #[test]
+    fn run_options_before_a_subcommand_are_rejected() {
+        let err = parse(&["--json", "once"]).unwrap_err();
+        assert_eq!(err.kind(), clap::error::ErrorKind::ArgumentConflict);
+        assert!(parse(&["--wide", "list"]).is_err());
+    }
+}
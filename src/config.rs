@@ -0,0 +1,445 @@
+//! Runtime configuration for EC2 Monitor
+
+use chrono_tz::Tz;
+use std::env;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
+use tracing::warn;
+
+use crate::MonitorError;
+use crate::idle::IdleAction;
+use crate::report::BorderStyle;
+use crate::types::{IpPreference, WindSpeedSteps};
+use crate::{aws, memory, proxy, retry, schedule, ssh};
+
+/// AWS SDK timeout and retry settings
+#[derive(Debug, Clone)]
+pub struct AwsOptions {
+    /// Regions of the monitored instances, when empty the region of the SDK
+    /// default chain (`AWS_REGION`, the profile), else [`DEFAULT_REGION`]
+    pub regions: Vec<String>,
+    /// Timeout for establishing a connection to the AWS API
+    pub connect_timeout: Duration,
+    /// Timeout for reading a response from the AWS API
+    pub read_timeout: Duration,
+    /// Maximum number of attempts per request, including the first one
+    pub max_attempts: u32,
+}
+
+/// Region monitored when neither a region is given nor the SDK default chain finds one
+pub const DEFAULT_REGION: &str = "sa-east-1";
+
+impl Default for AwsOptions {
+    fn default() -> Self {
+        Self {
+            regions: Vec::new(),
+            connect_timeout: Duration::from_secs(5),
+            read_timeout: Duration::from_secs(30),
+            max_attempts: 3,
+        }
+    }
+}
+
+impl AwsOptions {
+    /// Build the AWS options from `AWS_CONNECT_TIMEOUT`, `AWS_READ_TIMEOUT` (seconds)
+    /// and `AWS_MAX_ATTEMPTS`, falling back to the defaults
+    pub fn from_env() -> Self {
+        let default = Self::default();
+        Self {
+            regions: default.regions,
+            connect_timeout: env_parse::<u64>("AWS_CONNECT_TIMEOUT")
+                .map(Duration::from_secs)
+                .unwrap_or(default.connect_timeout),
+            read_timeout: env_parse::<u64>("AWS_READ_TIMEOUT")
+                .map(Duration::from_secs)
+                .unwrap_or(default.read_timeout),
+            max_attempts: env_parse("AWS_MAX_ATTEMPTS").unwrap_or(default.max_attempts),
+        }
+    }
+}
+
+/// Monitor configuration
+#[derive(Debug, Clone)]
+pub struct MonitorConfig {
+    /// AWS SDK settings
+    pub aws: AwsOptions,
+    /// AWS account shown in the report header, resolved at startup
+    pub account: Option<String>,
+    /// Types of the monitored instances
+    pub instance_types: Vec<String>,
+    /// States of the monitored instances
+    pub instance_states: Vec<String>,
+    /// Ids of the monitored instances, replacing the type and state filters when not empty
+    pub instance_ids: Vec<String>,
+    /// Time between the starts of two monitoring cycles
+    pub refresh_interval: Duration,
+    /// Minimum time between two redraws of the summary report, the latest
+    /// results being drawn at the end of it
+    pub render_interval: Duration,
+    /// SSH private keys of the instances, tried in turn, defaulting to the
+    /// `AWS_KEYPAIR` paths
+    pub key_paths: Vec<PathBuf>,
+    /// SSH usernames of the instances, tried in turn until one authenticates
+    pub ssh_usernames: Vec<String>,
+    /// Port of the sshd of the instances
+    pub ssh_port: u16,
+    /// Shell wrapping the remote commands, e.g. `bash`, or `None` to run them
+    /// in the login shell of the instances
+    pub remote_shell: Option<String>,
+    /// TOML file with per-instance notes
+    pub notes_path: PathBuf,
+    /// Print the report of every cycle as a JSON object instead of a table
+    pub json_output: bool,
+    /// Page the summary report through `$PAGER`, falling back to stdout
+    pub pager: bool,
+    /// Only print the instances that changed since the previous cycle
+    pub changes_only: bool,
+    /// Total number of steps of every instance, taking precedence over the
+    /// controlDict and the wind speed of the case names
+    pub total_steps: Option<usize>,
+    /// Total number of steps of the cases without a known wind speed, shown as
+    /// assumed, instead of an unknown total
+    pub default_total_step: Option<usize>,
+    /// Total number of steps of the cases by the wind speed suffix of their name
+    pub wind_speed_steps: WindSpeedSteps,
+    /// Read the total number of steps from the remote `system/controlDict`
+    pub read_control_dict: bool,
+    /// Verify instance host keys against `~/.ssh/known_hosts`
+    pub verify_host_keys: bool,
+    /// `host:port` of a SOCKS5 proxy the SSH connections are tunneled through
+    pub socks_proxy: Option<String>,
+    /// Maximum number of concurrent connections tunneled through each proxy
+    pub proxy_connections: usize,
+    /// known_hosts file, defaulting to `~/.ssh/known_hosts`
+    pub known_hosts_path: Option<PathBuf>,
+    /// Replace the known host key of instances replaced behind the same IP
+    pub refresh_replaced_host_keys: bool,
+    /// Address of the `/summary` JSON endpoint, disabled when `None`
+    pub summary_addr: Option<SocketAddr>,
+    /// CSV file the progress history is exported to every cycle
+    pub history_csv: Option<PathBuf>,
+    /// Directory of the per-instance ETA logs, appended to every cycle
+    pub eta_log_dir: Option<PathBuf>,
+    /// File the ETA state is kept in across restarts, gzip-compressed for a `.gz` path
+    pub state_file: Option<PathBuf>,
+    /// Size in bytes from which the state file is rotated, zero never rotating it
+    pub state_rotate_bytes: u64,
+    /// Number of state files kept, including the current one
+    pub state_keep: usize,
+    /// Replay log the raw inputs of every cycle are appended to
+    pub record_path: Option<PathBuf>,
+    /// Time bucket of the history export, keeping one sample per bucket
+    pub history_interval: Duration,
+    /// Running time of an s3 sync after which it is reported as stuck
+    pub stuck_sync_limit: Duration,
+    /// Consecutive cycles of advancing steps without new CSV files after which
+    /// the output pipeline of an instance is reported as broken
+    pub csv_stall_cycles: usize,
+    /// Action taken on the finished instances idle for longer than `idle_timeout`
+    pub idle_action: Option<IdleAction>,
+    /// Idle time of a finished instance after which the idle action is taken
+    pub idle_timeout: Duration,
+    /// Only log the idle actions instead of taking them
+    pub dry_run: bool,
+    /// Force plain (`Some(true)`) or decorated (`Some(false)`) output,
+    /// `None` picks plain output when standard output is not a terminal
+    pub plain_output: Option<bool>,
+    /// Time after launch during which connection failures are reported as starting up
+    pub startup_grace: Duration,
+    /// Order in which the public and private IPs are tried
+    pub ip_preference: IpPreference,
+    /// Show the endpoint used for each instance and check the other IP's reachability
+    pub show_endpoints: bool,
+    /// Show the instantaneous and lifetime average step rates
+    pub show_rates: bool,
+    /// Show the time taken by the connection and SSH handshake of each instance
+    pub show_latency: bool,
+    /// Steps at the start of a job during which ETA samples are not recorded
+    pub warmup_steps: usize,
+    /// Cycles at the start of a job during which ETA samples are not recorded
+    pub warmup_cycles: usize,
+    /// Leave the first ETA sample of each instance out of its median once enough samples exist
+    pub skip_first_eta_sample: bool,
+    /// Number of latest step samples fitted by least squares for the ETA,
+    /// below two using the rate since the previous cycle
+    pub regression_samples: usize,
+    /// Retries of transient failures allowed per instance and cycle
+    pub instance_retries: usize,
+    /// Retries allowed across the whole fleet per cycle
+    pub retry_budget: usize,
+    /// Delay before the first retry of an instance, doubled for every further retry
+    pub retry_delay: Duration,
+    /// Fail instances whose case name has an unknown wind speed instead of
+    /// reporting them without ETA
+    pub strict_wind_speed: bool,
+    /// Interval of the SSH keepalive messages, zero disabling them
+    pub keepalive_interval: Duration,
+    /// Time allowed for the TCP connection to an instance
+    pub connect_timeout: Duration,
+    /// Time allowed for the SSH handshake, including the server banner
+    pub handshake_timeout: Duration,
+    /// Time allowed for each blocking read of an SSH session, e.g. a command output
+    pub read_timeout: Duration,
+    /// Time allowed for the instance probes of a cycle, the refresh interval
+    /// when `None`, after which the unfinished probes are reported as timed out
+    pub cycle_timeout: Option<Duration>,
+    /// Maximum number of characters of the instance names in the reports
+    pub name_width: usize,
+    /// Grow the report columns to fit the longest names and errors instead of truncating them
+    pub wide_report: bool,
+    /// Show the step and CSV counts of the reports with K/M/G suffixes
+    pub human_counts: bool,
+    /// Tag key grouping the rows of the summary report, with a subtotal per group
+    pub group_by: Option<String>,
+    /// Number of rows of the summary report, keeping very large fleets on
+    /// screen, all rows when `None`
+    pub top_rows: Option<usize>,
+    /// Timezone of the rendered timestamps, the local timezone when `None`
+    pub display_timezone: Option<Tz>,
+    /// Replace the instance names, ids and IPs of the reports, e.g. for sharing them publicly
+    pub anonymize: bool,
+    /// Characters of the rules framing the report table
+    pub border_style: BorderStyle,
+    /// Read the start time of the solver process, measuring the lifetime average
+    /// rate from it instead of from the first observation
+    pub read_solver_start: bool,
+    /// Compare the thread count of the solver processes with the number of processors
+    pub check_threads: bool,
+    /// Name of the solver processes whose threads are counted
+    pub solver_process: String,
+    /// Path of the solver log on the instances, with `{name}` standing for the
+    /// instance name
+    pub log_path_template: String,
+    /// Text marking the log lines of the solver steps, the last of which is read
+    pub timestep_marker: String,
+    /// Read the resident memory of the solver processes and the instance memory
+    pub check_memory: bool,
+    /// Fraction of the instance memory above which the solver memory is flagged
+    pub memory_threshold: f64,
+    /// Sample the network throughput of the instances running an s3 sync
+    pub check_network: bool,
+    /// Estimate the end of the finalize and s3 sync phases from the rate of
+    /// their output bytes
+    pub output_eta: bool,
+    /// Bytes of the case directory once finalized, the target of the finalize ETA
+    pub output_target_bytes: Option<u64>,
+    /// Check that the case directory can be written to, detecting read-only volumes
+    pub check_writable: bool,
+    /// Flag solvers whose log tail shows `nan` or `inf` residuals or fields
+    pub check_divergence: bool,
+    /// Count the lines of the solver log tail matching the warning patterns
+    pub check_warnings: bool,
+    /// Patterns of the solver warnings and errors
+    pub warning_patterns: Vec<String>,
+    /// Number of warnings above which an instance is flagged in the report
+    pub warning_threshold: usize,
+    /// Cycles during which completed instances stay in the report after termination
+    pub complete_retention_cycles: usize,
+    /// Show the idle complete simulations as such in the ETA column, those
+    /// running a post-process always showing it
+    pub post_completion_phases: bool,
+    /// Alert when an incomplete instance is no longer discovered
+    pub alert_vanished: bool,
+    /// Probe the instances one at a time in name order instead of in parallel
+    pub sequential: bool,
+    /// Maximum random delay before probing each instance, spreading the connections
+    pub startup_jitter: Duration,
+    /// Interactive terminal mode instead of printed reports
+    pub interactive: bool,
+    /// Run the cycles without printing the report
+    pub quiet: bool,
+    /// Level of the status logs when `RUST_LOG` is unset, 1 logging every
+    /// processed instance
+    pub verbosity: u8,
+}
+
+impl MonitorConfig {
+    /// States of the monitored instances, as expected by the discovery
+    pub fn instance_states(&self) -> Vec<&str> {
+        self.instance_states.iter().map(String::as_str).collect()
+    }
+
+    /// Path of the solver log of an instance, from the log path template
+    pub fn log_path(&self, instance_name: &str) -> String {
+        self.log_path_template.replace("{name}", instance_name)
+    }
+
+    /// SSH private keys of the instances: the configured key pairs, else the
+    /// colon-separated ones of `AWS_KEYPAIR`
+    pub fn resolve_key_paths(&self) -> Result<Vec<PathBuf>, MonitorError> {
+        if !self.key_paths.is_empty() {
+            return Ok(self.key_paths.clone());
+        }
+        Ok(env::split_paths(&env::var("AWS_KEYPAIR")?)
+            .filter(|path| !path.as_os_str().is_empty())
+            .collect())
+    }
+}
+
+impl Default for MonitorConfig {
+    fn default() -> Self {
+        Self {
+            aws: AwsOptions::default(),
+            account: None,
+            instance_types: aws::DEFAULT_INSTANCE_TYPES.map(str::to_string).to_vec(),
+            instance_states: vec!["running".to_string()],
+            instance_ids: Vec::new(),
+            refresh_interval: schedule::REFRESH_INTERVAL,
+            render_interval: Duration::ZERO,
+            key_paths: Vec::new(),
+            ssh_usernames: ssh::DEFAULT_SSH_USERNAMES.map(str::to_string).to_vec(),
+            ssh_port: 22,
+            remote_shell: None,
+            notes_path: PathBuf::from("notes.toml"),
+            json_output: false,
+            pager: false,
+            changes_only: false,
+            total_steps: None,
+            default_total_step: None,
+            wind_speed_steps: WindSpeedSteps::default(),
+            read_control_dict: false,
+            verify_host_keys: false,
+            socks_proxy: None,
+            proxy_connections: 16,
+            known_hosts_path: None,
+            refresh_replaced_host_keys: false,
+            summary_addr: None,
+            history_csv: None,
+            eta_log_dir: None,
+            state_file: None,
+            state_rotate_bytes: 1024 * 1024,
+            state_keep: 3,
+            record_path: None,
+            history_interval: Duration::from_secs(30 * 60),
+            stuck_sync_limit: Duration::from_secs(2 * 60 * 60),
+            csv_stall_cycles: 3,
+            idle_action: None,
+            idle_timeout: Duration::from_secs(30 * 60),
+            dry_run: false,
+            plain_output: None,
+            startup_grace: Duration::from_secs(180),
+            ip_preference: IpPreference::default(),
+            show_endpoints: false,
+            show_rates: false,
+            show_latency: false,
+            warmup_steps: 0,
+            warmup_cycles: 0,
+            skip_first_eta_sample: false,
+            regression_samples: 0,
+            instance_retries: 3,
+            retry_budget: 10,
+            retry_delay: retry::DEFAULT_RETRY_DELAY,
+            strict_wind_speed: false,
+            keepalive_interval: Duration::from_secs(15),
+            connect_timeout: Duration::from_secs(10),
+            handshake_timeout: Duration::from_secs(20),
+            read_timeout: Duration::from_secs(60),
+            cycle_timeout: None,
+            name_width: 20,
+            wide_report: false,
+            human_counts: false,
+            group_by: None,
+            top_rows: None,
+            display_timezone: None,
+            anonymize: false,
+            border_style: BorderStyle::default(),
+            read_solver_start: false,
+            check_threads: false,
+            solver_process: "zcsvs".to_string(),
+            log_path_template: "{name}/solve.out".to_string(),
+            timestep_marker: "TimeStep".to_string(),
+            check_memory: false,
+            check_network: false,
+            output_eta: false,
+            output_target_bytes: None,
+            memory_threshold: memory::DEFAULT_MEMORY_THRESHOLD,
+            check_writable: false,
+            check_divergence: false,
+            check_warnings: false,
+            warning_patterns: vec![
+                "FOAM Warning".to_string(),
+                "FOAM FATAL ERROR".to_string(),
+                "Courant number exceeds".to_string(),
+            ],
+            warning_threshold: 0,
+            complete_retention_cycles: 10,
+            post_completion_phases: false,
+            alert_vanished: false,
+            sequential: false,
+            startup_jitter: Duration::ZERO,
+            interactive: false,
+            quiet: false,
+            verbosity: 0,
+        }
+    }
+}
+
+impl MonitorConfig {
+    /// Build the configuration from the environment: `MONITOR_NOTES`, `MONITOR_KNOWN_HOSTS`,
+    /// a `socks5://` proxy in `ALL_PROXY`,
+    /// `MONITOR_INTERVAL` in seconds, `MONITOR_WARMUP_STEPS`, `MONITOR_WARMUP_CYCLES`,
+    /// `MONITOR_INSTANCE_RETRIES`, `MONITOR_RETRY_BUDGET`, `MONITOR_RETRY_DELAY` in
+    /// seconds, the comma-separated usernames of `SSH_USERNAME`, `SSH_PORT` and the
+    /// `SPEED=STEPS` entries of `MONITOR_WIND_STEPS`, adding to the default total
+    /// steps by wind speed
+    pub fn from_env() -> Self {
+        let default = Self::default();
+        Self {
+            aws: AwsOptions::from_env(),
+            notes_path: env::var("MONITOR_NOTES")
+                .map(PathBuf::from)
+                .unwrap_or(default.notes_path),
+            socks_proxy: env::var("ALL_PROXY")
+                .or_else(|_| env::var("all_proxy"))
+                .ok()
+                .and_then(|value| proxy::socks5_proxy_address(&value)),
+            known_hosts_path: env::var("MONITOR_KNOWN_HOSTS").ok().map(PathBuf::from),
+            refresh_interval: env_parse("MONITOR_INTERVAL")
+                .map(Duration::from_secs)
+                .unwrap_or(default.refresh_interval),
+            warmup_steps: env_parse("MONITOR_WARMUP_STEPS").unwrap_or(default.warmup_steps),
+            warmup_cycles: env_parse("MONITOR_WARMUP_CYCLES").unwrap_or(default.warmup_cycles),
+            instance_retries: env_parse("MONITOR_INSTANCE_RETRIES")
+                .unwrap_or(default.instance_retries),
+            retry_budget: env_parse("MONITOR_RETRY_BUDGET").unwrap_or(default.retry_budget),
+            ssh_usernames: env::var("SSH_USERNAME")
+                .ok()
+                .map(|value| {
+                    value
+                        .split(',')
+                        .map(str::trim)
+                        .filter(|username| !username.is_empty())
+                        .map(str::to_string)
+                        .collect::<Vec<_>>()
+                })
+                .filter(|usernames| !usernames.is_empty())
+                .unwrap_or(default.ssh_usernames),
+            ssh_port: env_parse("SSH_PORT").unwrap_or(default.ssh_port),
+            wind_speed_steps: {
+                let mut steps = default.wind_speed_steps.clone();
+                if let Some(entries) = env_parse("MONITOR_WIND_STEPS") {
+                    steps.extend(entries);
+                }
+                steps
+            },
+            retry_delay: env_parse("MONITOR_RETRY_DELAY")
+                .map(Duration::from_secs)
+                .unwrap_or(default.retry_delay),
+            ..default
+        }
+    }
+}
+
+/// Parse an environment variable, ignoring it with a warning if it is invalid
+fn env_parse<T: FromStr>(name: &str) -> Option<T> {
+    let value = env::var(name).ok()?;
+    match value.trim().parse() {
+        Ok(parsed) => Some(parsed),
+        Err(_) => {
+            warn!("Ignoring invalid value for {name}: {value:?}");
+            None
+        }
+    }
+}
@@ -0,0 +1,56 @@
+//! Processor utilization of the solver
+
+/// Fraction of the processors below which a running solver is underutilizing its node
+pub const UNDERUTILIZATION_RATIO: f64 = 0.9;
+
+/// Use of the processors of a node by its solver
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CpuUtilization {
+    /// The solver runs at least one thread per processor, or nearly so
+    Full,
+    /// The solver runs noticeably fewer threads than there are processors
+    Under { threads: usize, nproc: usize },
+    /// The solver is not running
+    Idle,
+}
+
+/// Parse the output of `nproc` or of a thread count summed over processes
+pub fn parse_count(output: &str) -> Option<usize> {
+    output.trim().parse().ok()
+}
+
+/// Classify the solver thread count against the number of processors
+pub fn classify_utilization(threads: usize, nproc: usize) -> CpuUtilization {
+    if threads == 0 {
+        CpuUtilization::Idle
+    } else if (threads as f64) < nproc as f64 * UNDERUTILIZATION_RATIO {
+        CpuUtilization::Under { threads, nproc }
+    } else {
+        CpuUtilization::Full
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn thread_counts_are_classified_against_the_processors() {
+        assert_eq!(classify_utilization(192, 192), CpuUtilization::Full);
+        assert_eq!(classify_utilization(180, 192), CpuUtilization::Full);
+        assert_eq!(
+            classify_utilization(96, 192),
+            CpuUtilization::Under {
+                threads: 96,
+                nproc: 192
+            }
+        );
+        assert_eq!(classify_utilization(0, 192), CpuUtilization::Idle);
+    }
+
+    #[test]
+    fn counts_are_parsed_from_the_command_outputs() {
+        assert_eq!(parse_count("192\n"), Some(192));
+        assert_eq!(parse_count(""), None);
+    }
+}
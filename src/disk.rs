@@ -0,0 +1,174 @@
+//! Disk space parsing utilities
+
+use crate::solver;
+
+/// Output of the writability probe when the case directory accepts writes
+pub const WRITABLE: &str = "writable";
+
+/// Free space (in GB) below which a disk is considered full
+pub const DISK_FULL_THRESHOLD_GB: f64 = 1.0;
+
+/// Number of latest free disk space samples of an instance fitted for its trend
+pub const DISK_TREND_SAMPLES: usize = 10;
+
+/// Minutes until the disk is full, extrapolating a least-squares fit of the free
+/// space over `(minutes, free GB)` samples from the last sample
+///
+/// Returns `None` with fewer than two distinct sample times or when the free
+/// space is not decreasing.
+pub fn time_to_full_minutes(samples: &[(f64, f64)]) -> Option<f64> {
+    let &(_, last_gb) = samples.last()?;
+    let n = samples.len() as f64;
+    let mean_x = samples.iter().map(|(x, _)| x).sum::<f64>() / n;
+    let mean_y = samples.iter().map(|(_, y)| y).sum::<f64>() / n;
+    let (covariance, variance) = samples.iter().fold((0.0, 0.0), |(cov, var), (x, y)| {
+        let dx = x - mean_x;
+        (cov + dx * (y - mean_y), var + dx * dx)
+    });
+    if samples.len() < 2 || variance <= 0.0 {
+        return None;
+    }
+    let slope = covariance / variance;
+    (slope < 0.0).then(|| (last_gb / -slope).max(0.0))
+}
+
+/// Parse a `df -h` size such as "12G", "512M" or "1.5T" into gigabytes
+pub fn parse_disk_space_gb(space: &str) -> Option<f64> {
+    let space = space.trim();
+    if space.is_empty() {
+        return None;
+    }
+
+    let (number, unit) = match space.char_indices().find(|(_, c)| c.is_ascii_alphabetic()) {
+        Some((i, _)) => space.split_at(i),
+        None => (space, ""),
+    };
+    let value = number.trim().replace(',', ".").parse::<f64>().ok()?;
+
+    // df -h uses powers of 1024 with single-letter suffixes
+    let gb = match unit.chars().next().map(|c| c.to_ascii_uppercase()) {
+        None => value / (1024.0 * 1024.0 * 1024.0),
+        Some('B') => value / (1024.0 * 1024.0 * 1024.0),
+        Some('K') => value / (1024.0 * 1024.0),
+        Some('M') => value / 1024.0,
+        Some('G') => value,
+        Some('T') => value * 1024.0,
+        Some('P') => value * 1024.0 * 1024.0,
+        Some(_) => return None,
+    };
+    Some(gb)
+}
+
+/// Read the available space of the filesystem from the output of `df -hP`
+///
+/// The value is taken from the column under the `Avail` header, and the lines
+/// following the header are joined so that a filesystem line wrapped after a
+/// long device name is still read correctly.
+pub fn parse_df_available(output: &str) -> Option<String> {
+    let mut lines = output.lines().filter(|line| !line.trim().is_empty());
+    let header = lines.next()?;
+    let column = header
+        .split_whitespace()
+        .position(|name| name == "Avail" || name == "Available")?;
+    lines
+        .flat_map(str::split_whitespace)
+        .nth(column)
+        .map(str::to_string)
+}
+
+/// Check whether the reported free disk space is at or near zero
+pub fn is_disk_full(free_disk_space: &str) -> bool {
+    parse_disk_space_gb(free_disk_space).is_some_and(|gb| gb < DISK_FULL_THRESHOLD_GB)
+}
+
+/// Shell command testing whether a probe file can be created in `dir`, printing
+/// [`WRITABLE`] or the error of `touch`, and removing the probe file
+pub fn writability_probe(dir: &str) -> String {
+    let probe = solver::shell_quote(&format!("{dir}/.monitor_probe"));
+    format!("touch {probe} 2>&1 && rm -f {probe} && echo {WRITABLE} || true")
+}
+
+/// Error of `touch` on a read-only file system (`EROFS`)
+const READ_ONLY_ERROR: &str = "Read-only file system";
+
+/// Interpret the output of the writability probe: `Some(true)` when the write
+/// failed on a read-only file system, `Some(false)` when it succeeded, and
+/// `None` for any other failure, e.g. a missing directory or a denied permission
+pub fn is_read_only(probe_output: &str) -> Option<bool> {
+    if probe_output.trim() == WRITABLE {
+        Some(false)
+    } else if probe_output.contains(READ_ONLY_ERROR) {
+        Some(true)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn writability_probe_quotes_the_case_directory() {
+        assert_eq!(
+            writability_probe("wake 2ms"),
+            "touch 'wake 2ms/.monitor_probe' 2>&1 && rm -f 'wake 2ms/.monitor_probe' && echo writable || true"
+        );
+    }
+
+    #[test]
+    fn only_a_read_only_file_system_is_read_only() {
+        assert_eq!(is_read_only("writable\n"), Some(false));
+        assert_eq!(
+            is_read_only("touch: cannot touch 'case/.monitor_probe': Read-only file system\n"),
+            Some(true)
+        );
+        for output in [
+            "touch: cannot touch 'case/.monitor_probe': Permission denied\n",
+            "touch: cannot touch 'case/.monitor_probe': No such file or directory\n",
+            "",
+        ] {
+            assert_eq!(is_read_only(output), None, "{output:?}");
+        }
+    }
+
+    #[test]
+    fn available_space_is_read_from_unwrapped_and_wrapped_df() {
+        let unwrapped = "\
+Filesystem      Size  Used Avail Use% Mounted on
+/dev/root        97G   77G   20G  80% /
+";
+        let wrapped = "\
+Filesystem                                          Size  Used Avail Use% Mounted on
+/dev/mapper/very-long-logical-volume-name-of-the-root
+                                                     97G   77G   20G  80% /
+";
+        assert_eq!(parse_df_available(unwrapped).as_deref(), Some("20G"));
+        assert_eq!(parse_df_available(wrapped).as_deref(), Some("20G"));
+        assert_eq!(parse_df_available(""), None);
+    }
+
+    #[test]
+    fn disk_sizes_are_converted_to_gigabytes() {
+        assert_eq!(parse_disk_space_gb("12G"), Some(12.0));
+        assert_eq!(parse_disk_space_gb("512M"), Some(0.5));
+        assert_eq!(parse_disk_space_gb("1,5T"), Some(1536.0));
+        assert_eq!(parse_disk_space_gb("lots"), None);
+    }
+
+    #[test]
+    fn declining_free_space_is_projected_to_full() {
+        let samples = [(0.0, 100.0), (10.0, 90.0), (20.0, 80.0), (30.0, 70.0)];
+        let minutes = time_to_full_minutes(&samples).unwrap();
+        assert!((minutes - 70.0).abs() < 1e-9, "{minutes}");
+
+        let noisy = [(0.0, 50.0), (5.0, 52.0), (10.0, 44.0), (15.0, 43.0)];
+        assert!(time_to_full_minutes(&noisy).is_some_and(|m| m > 0.0));
+
+        assert_eq!(time_to_full_minutes(&[(0.0, 70.0), (10.0, 80.0)]), None);
+        assert_eq!(time_to_full_minutes(&[(0.0, 70.0), (10.0, 70.0)]), None);
+        assert_eq!(time_to_full_minutes(&[(5.0, 70.0), (5.0, 60.0)]), None);
+        assert_eq!(time_to_full_minutes(&[(0.0, 70.0)]), None);
+        assert_eq!(time_to_full_minutes(&[]), None);
+    }
+}
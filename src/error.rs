@@ -1,5 +1,6 @@
 //! Error types for EC2 Monitor
 
+use std::time::Duration;
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -13,6 +14,19 @@ pub enum MonitorError {
     #[error("IO error: {0}")]
     Io(#[from] std::io::Error),
 
+    #[error("Direct connection to {target} failed: {source}")]
+    DirectConnect {
+        target: String,
+        source: std::io::Error,
+    },
+
+    #[error("SOCKS5 proxy {proxy} failed to connect to {target}: {source}")]
+    ProxyConnect {
+        proxy: String,
+        target: String,
+        source: std::io::Error,
+    },
+
     #[error("Environment variable error: {0}")]
     Env(#[from] std::env::VarError),
 
@@ -25,14 +39,47 @@ pub enum MonitorError {
     #[error("No public IP available for instance")]
     NoPublicIp,
 
+    #[error("No private IP available for instance")]
+    NoPrivateIp,
+
+    #[error(
+        "Unknown instance state {state}, expected pending, running, shutting-down, terminated, stopping or stopped"
+    )]
+    InvalidInstanceState { state: String },
+
     #[error("SSH key file not found: {path}")]
     KeyFileNotFound { path: String },
 
+    #[error("No readable state file at {path}")]
+    StateFileNotFound { path: String },
+
+    #[error("{failures} preflight check(s) failed")]
+    PreflightFailed { failures: usize },
+
+    #[error("SSH handshake timeout after {secs}s")]
+    HandshakeTimeout { secs: u64 },
+
+    #[error("Connection to {ip}:{port} timed out after {:.1}s", elapsed.as_secs_f64())]
+    ConnectionTimeout {
+        ip: String,
+        port: u16,
+        elapsed: Duration,
+    },
+
+    #[error("{source} (after {attempts} attempts)")]
+    AfterAttempts {
+        attempts: usize,
+        source: Box<MonitorError>,
+    },
+
+    #[error("Cycle deadline exceeded after {secs}s")]
+    CycleTimeout { secs: u64 },
+
     #[error("SSH authentication failed")]
     AuthenticationFailed,
 
-    #[error("Invalid wind speed, valid speeds are 2m/s, 7m/s, 12m/s, or 17m/s")]
-    InvalidWindSpeed,
+    #[error("Invalid wind speed, known wind speed suffixes are {known}")]
+    InvalidWindSpeed { known: String },
 
     #[error("SSH command failed with exit code {code}: {stderr}")]
     SshCommandFailed { code: i32, stderr: String },
@@ -45,4 +92,15 @@ pub enum MonitorError {
 
     #[error("Tmux session launch failed: {reason}")]
     TmuxLaunchFailed { reason: String },
+
+    #[error(
+        "Host key for {host} changed; remove the stale known_hosts entry or enable host key refresh for replaced instances"
+    )]
+    HostKeyMismatch { host: String },
+
+    #[error("Notes file error: {reason}")]
+    Notes { reason: String },
+
+    #[error("JSON serialization error: {reason}")]
+    Json { reason: String },
 }
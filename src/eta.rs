@@ -1,9 +1,144 @@
 //! ETA parsing and median calculation utilities
 
+use chrono::{DateTime, Utc};
+
+use crate::disk;
+
+/// ETA status shown when a simulation reached its total number of steps
+pub const COMPLETE: &str = "Complete";
+
+/// ETA status shown for a completed instance that has been terminated
+pub const COMPLETE_TERMINATED: &str = "Complete (terminated)";
+
+/// ETA status shown when the solver log shows `nan` or `inf` residuals or fields
+pub const DIVERGED: &str = "Diverged";
+
+/// ETA status shown when the case directory cannot be written to
+pub const READ_ONLY_FS: &str = "FS read-only";
+
+/// ETA status shown when a simulation makes no progress
+pub const STALLED: &str = "Stalled";
+
+/// ETA status shown when a stalled simulation has run out of disk space
+pub const STALLED_DISK_FULL: &str = "Stalled: disk full";
+
+/// ETA shown when less than half a minute is left, which would round to "0m"
+pub const UNDER_A_MINUTE: &str = "<1m";
+
+/// ETA status shown when the total number of steps of a case is unknown
+pub const UNKNOWN_CASE: &str = "No ETA (unknown case)";
+
+/// Correlate a stalled simulation with the free disk space of its instance
+pub fn stall_status(free_disk_space: Option<&str>) -> String {
+    match free_disk_space {
+        Some(space) if disk::is_disk_full(space) => STALLED_DISK_FULL.to_string(),
+        _ => STALLED.to_string(),
+    }
+}
+
+/// ETA of a job, telling a stall caused by a full disk from other stalls
+pub fn with_stall_cause(eta: String, free_disk_space: Option<&str>) -> String {
+    if eta == STALLED {
+        stall_status(free_disk_space)
+    } else {
+        eta
+    }
+}
+
+/// Check whether a job is still warming up, i.e. its step rate is not yet
+/// representative and its ETA samples should not be recorded
+pub fn in_warmup(
+    step: usize,
+    observed_cycles: usize,
+    warmup_steps: usize,
+    warmup_cycles: usize,
+) -> bool {
+    step < warmup_steps || observed_cycles < warmup_cycles
+}
+
+/// Record the ETA of a cycle into the ETA samples of an instance: a complete
+/// job resets its samples to 0 minutes, and the ETA of a job still warming up
+/// is shown but not recorded
+pub fn record_sample(
+    samples: &mut Vec<f64>,
+    eta: &str,
+    eta_minutes: Option<f64>,
+    warming_up: bool,
+) {
+    if eta == COMPLETE {
+        samples.clear();
+        samples.push(0.0);
+    } else if !warming_up && let Some(eta_minutes) = eta_minutes {
+        samples.push(eta_minutes);
+    }
+}
+
+/// Steps per minute between two `(step, observed at)` observations, `None` if no
+/// time elapsed or the step went backwards
+pub fn step_rate(first: (usize, DateTime<Utc>), current: (usize, DateTime<Utc>)) -> Option<f64> {
+    let steps = current.0.checked_sub(first.0)?;
+    let minutes = (current.1 - first.1).num_milliseconds() as f64 / 60_000.0;
+    (minutes > 0.0).then(|| steps as f64 / minutes)
+}
+
+/// ETA from a least-squares fit of the step against the elapsed minutes of
+/// `(minutes, step)` samples, projected from the last sample to `total_step`
+///
+/// Returns `None` with fewer than two distinct sample times, and [`STALLED`]
+/// when the fitted rate is not positive.
+pub fn regression_eta(samples: &[(f64, usize)], total_step: usize) -> Option<String> {
+    let &(_, last_step) = samples.last()?;
+    if total_step > 0 && last_step >= total_step {
+        return Some(COMPLETE.to_string());
+    }
+    let (slope, _) = fit_line(samples)?;
+    if slope <= 0.0 {
+        return Some(STALLED.to_string());
+    }
+    regression_minutes(samples, total_step).map(format_remaining)
+}
+
+/// Minutes left of the [`regression_eta`], 0 once complete, or `None` when
+/// the fitted rate is not positive
+pub fn regression_minutes(samples: &[(f64, usize)], total_step: usize) -> Option<f64> {
+    let &(last_minutes, last_step) = samples.last()?;
+    if total_step > 0 && last_step >= total_step {
+        return Some(0.0);
+    }
+    let (slope, intercept) = fit_line(samples).filter(|(slope, _)| *slope > 0.0)?;
+
+    // Time at which the fitted line reaches the total, counted from the last sample
+    let completion_minutes = (total_step as f64 - intercept) / slope;
+    Some((completion_minutes - last_minutes).max(0.0))
+}
+
+/// Slope and intercept of the least-squares line through `(minutes, step)`
+/// samples, `None` with fewer than two distinct sample times
+fn fit_line(samples: &[(f64, usize)]) -> Option<(f64, f64)> {
+    let n = samples.len() as f64;
+    let mean_x = samples.iter().map(|(x, _)| x).sum::<f64>() / n;
+    let mean_y = samples.iter().map(|(_, y)| *y as f64).sum::<f64>() / n;
+    let (covariance, variance) = samples.iter().fold((0.0, 0.0), |(cov, var), (x, y)| {
+        let dx = x - mean_x;
+        (cov + dx * (*y as f64 - mean_y), var + dx * dx)
+    });
+    if samples.len() < 2 || variance <= 0.0 {
+        return None;
+    }
+    let slope = covariance / variance;
+    Some((slope, mean_y - slope * mean_x))
+}
+
 /// Parse ETA string back to total minutes for calculation
 pub fn parse_eta_to_minutes(eta_str: &str) -> Option<f64> {
     // Skip special cases
-    if eta_str == "Complete" || eta_str == "Stalled" || eta_str == "Calculating..." {
+    if eta_str == COMPLETE
+        || eta_str.starts_with(STALLED)
+        || eta_str == UNKNOWN_CASE
+        || eta_str == DIVERGED
+        || eta_str == READ_ONLY_FS
+        || eta_str == "Calculating..."
+    {
         return None;
     }
 
@@ -19,10 +154,10 @@ pub fn parse_eta_to_minutes(eta_str: &str) -> Option<f64> {
             if let Ok(hours) = stripped.parse::<f64>() {
                 total_minutes += hours * 60.0;
             }
-        } else if let Some(stripped) = part.strip_suffix('m') {
-            if let Ok(minutes) = stripped.parse::<f64>() {
-                total_minutes += minutes;
-            }
+        } else if let Some(stripped) = part.strip_suffix('m')
+            && let Ok(minutes) = stripped.parse::<f64>()
+        {
+            total_minutes += minutes;
         }
     }
 
@@ -33,8 +168,8 @@ pub fn parse_eta_to_minutes(eta_str: &str) -> Option<f64> {
     }
 }
 
-/// Calculate median ETA from a collection of ETA values in minutes
-pub fn calculate_median_eta(etas: &[f64]) -> Option<String> {
+/// Calculate the median of a collection of ETA values in minutes
+pub fn median_minutes(etas: &[f64]) -> Option<f64> {
     if etas.is_empty() {
         return None;
     }
@@ -42,7 +177,7 @@ pub fn calculate_median_eta(etas: &[f64]) -> Option<String> {
     let mut sorted_etas = etas.to_vec();
     sorted_etas.sort_by(|a, b| a.partial_cmp(b).unwrap());
 
-    let median_minutes = if sorted_etas.len() % 2 == 0 {
+    let median = if sorted_etas.len().is_multiple_of(2) {
         // Even number of elements - average of two middle values
         let mid = sorted_etas.len() / 2;
         (sorted_etas[mid - 1] + sorted_etas[mid]) / 2.0
@@ -50,22 +185,148 @@ pub fn calculate_median_eta(etas: &[f64]) -> Option<String> {
         // Odd number of elements - middle value
         sorted_etas[sorted_etas.len() / 2]
     };
+    Some(median)
+}
+
+/// Minimum number of ETA samples of an instance before its first one is dropped
+pub const SKIP_FIRST_MIN_SAMPLES: usize = 3;
+
+/// ETA samples of an instance entering its median, without the first one when
+/// `skip_first` is set and at least `SKIP_FIRST_MIN_SAMPLES` samples exist
+///
+/// The first sample comes from the second cycle of a job, still close to its
+/// warmup, and tends to overestimate the ETA.
+pub fn median_samples(etas: &[f64], skip_first: bool) -> &[f64] {
+    if skip_first && etas.len() >= SKIP_FIRST_MIN_SAMPLES {
+        &etas[1..]
+    } else {
+        etas
+    }
+}
+
+/// Calculate median ETA from a collection of ETA values in minutes
+pub fn calculate_median_eta(etas: &[f64]) -> Option<String> {
+    median_minutes(etas).map(format_minutes)
+}
 
-    // Convert median minutes to days, hours, minutes format
-    let total_hours = median_minutes / 60.0;
+/// Format the minutes left of an incomplete job, showing [`UNDER_A_MINUTE`]
+/// instead of a "0m" that reads as done
+pub fn format_remaining(minutes: f64) -> String {
+    if minutes > 0.0 && minutes.round() == 0.0 {
+        UNDER_A_MINUTE.to_string()
+    } else {
+        format_minutes(minutes)
+    }
+}
+
+/// Format minutes as days, hours and minutes, e.g. "2d 5h 30m", "8h 15m" or "45m"
+pub fn format_minutes(total_minutes: f64) -> String {
+    let total_hours = total_minutes / 60.0;
     let days = (total_hours / 24.0).floor() as u64;
     let hours = (total_hours % 24.0).floor() as u64;
-    let minutes = (median_minutes % 60.0).round() as u64;
+    let minutes = (total_minutes % 60.0).round() as u64;
 
     if days > 0 {
         if hours > 0 {
-            Some(format!("{}d {}h {}m", days, hours, minutes))
+            format!("{}d {}h {}m", days, hours, minutes)
         } else {
-            Some(format!("{}d {}m", days, minutes))
+            format!("{}d {}m", days, minutes)
         }
     } else if hours > 0 {
-        Some(format!("{}h {}m", hours, minutes))
+        format!("{}h {}m", hours, minutes)
     } else {
-        Some(format!("{}m", minutes))
+        format!("{}m", minutes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn stall_with_low_disk_is_blamed_on_the_disk() {
+        assert_eq!(stall_status(Some("512M")), STALLED_DISK_FULL);
+    }
+
+    #[test]
+    fn stall_with_ample_disk_stays_a_plain_stall() {
+        assert_eq!(stall_status(Some("120G")), STALLED);
+        assert_eq!(stall_status(None), STALLED);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn samples_during_warmup_are_shown_but_not_recorded() {
+        let mut samples = Vec::new();
+        for (step, cycle) in [(50, 0), (150, 1), (250, 2)] {
+            let warming_up = in_warmup(step, cycle, 200, 0);
+            record_sample(&mut samples, "1h 0m", Some(60.0), warming_up);
+        }
+        assert_eq!(samples, [60.0]);
+
+        let warming_up = in_warmup(5000, 1, 0, 2);
+        record_sample(&mut samples, "50m", Some(50.0), warming_up);
+        assert_eq!(samples, [60.0]);
+    }
+
+    #[test]
+    fn completion_resets_the_samples_even_during_warmup() {
+        let mut samples = vec![60.0, 50.0];
+        record_sample(&mut samples, COMPLETE, None, true);
+        assert_eq!(samples, [0.0]);
+    }
+
+    #[test]
+    fn lifetime_rate_is_the_steps_over_the_minutes_since_first_observed() {
+        let first_seen = Utc.with_ymd_and_hms(2024, 5, 1, 0, 0, 0).unwrap();
+        let now = first_seen + chrono::Duration::minutes(90);
+        assert_eq!(step_rate((1000, first_seen), (5500, now)), Some(50.0));
+        assert_eq!(step_rate((1000, now), (5500, now)), None);
+        assert_eq!(step_rate((5500, first_seen), (1000, now)), None);
+    }
+
+    #[test]
+    fn a_linear_trend_is_projected_to_the_total() {
+        // 100 steps per minute, 6000 steps left
+        let samples = [(0.0, 1000), (10.0, 2000), (20.0, 3000), (30.0, 4000)];
+        assert_eq!(regression_minutes(&samples, 10_000), Some(60.0));
+        assert_eq!(regression_eta(&samples, 10_000).as_deref(), Some("1h 0m"));
+        assert_eq!(regression_eta(&samples, 4000).as_deref(), Some(COMPLETE));
+    }
+
+    #[test]
+    fn a_flat_trend_is_stalled() {
+        let samples = [(0.0, 2000), (10.0, 2000), (20.0, 2000)];
+        assert_eq!(regression_eta(&samples, 10_000).as_deref(), Some(STALLED));
+        assert_eq!(regression_minutes(&samples, 10_000), None);
+        assert_eq!(regression_eta(&[(0.0, 2000)], 10_000), None);
+    }
+
+    #[test]
+    fn first_sample_is_excluded_from_the_median_when_asked() {
+        let etas = [600.0, 100.0, 120.0, 110.0];
+        assert_eq!(median_minutes(median_samples(&etas, false)), Some(115.0));
+        assert_eq!(median_minutes(median_samples(&etas, true)), Some(110.0));
+
+        let few = [600.0, 100.0];
+        assert_eq!(median_samples(&few, true), few);
+    }
+
+    #[test]
+    fn sub_minute_remaining_time_is_not_shown_as_zero() {
+        assert_eq!(format_remaining(0.2), UNDER_A_MINUTE);
+        assert_eq!(format_remaining(0.49), UNDER_A_MINUTE);
+        assert_eq!(format_remaining(0.5), "1m");
+        assert_eq!(format_remaining(0.0), "0m");
+
+        let ts = |step| crate::TimeStep {
+            step,
+            total_step: 1000,
+            step_increase: Some(100),
+            interval_minutes: Some(1.0),
+            ..crate::TimeStep::default()
+        };
+        assert_eq!(ts(990).calculate_eta().as_deref(), Some(UNDER_A_MINUTE));
+        assert_eq!(ts(1000).calculate_eta().as_deref(), Some(COMPLETE));
+    }
+}
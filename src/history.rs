@@ -0,0 +1,220 @@
+//! Per-instance progress history and its CSV export
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::time::Duration;
+
+use crate::MonitorError;
+
+/// Progress of an instance at the end of a monitoring cycle
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistorySample {
+    pub timestamp: DateTime<Utc>,
+    pub step: usize,
+    pub total_step: usize,
+    pub time: f64,
+    pub eta_minutes: Option<f64>,
+}
+
+/// Keep the last sample of every `interval`-long time bucket
+///
+/// Samples are expected in chronological order, as recorded each cycle
+pub fn downsample(samples: &[HistorySample], interval: Duration) -> Vec<&HistorySample> {
+    let bucket_secs = interval.as_secs().max(1) as i64;
+    let mut kept: Vec<&HistorySample> = Vec::new();
+    let mut current_bucket = None;
+    for sample in samples {
+        let bucket = sample.timestamp.timestamp().div_euclid(bucket_secs);
+        if current_bucket == Some(bucket) {
+            // A later sample in the same bucket replaces the previous one
+            kept.pop();
+        }
+        kept.push(sample);
+        current_bucket = Some(bucket);
+    }
+    kept
+}
+
+/// Number of latest samples of an instance kept at full resolution in memory
+pub const RECENT_SAMPLES: usize = 20;
+
+/// Bound the in-memory history of an instance: keep the latest `recent` samples
+/// and downsample the older ones to one per `interval`-long time bucket
+pub fn compact(samples: &mut Vec<HistorySample>, interval: Duration, recent: usize) {
+    let split = samples.len().saturating_sub(recent);
+    if split == 0 {
+        return;
+    }
+    let older: Vec<HistorySample> = downsample(&samples[..split], interval)
+        .into_iter()
+        .cloned()
+        .collect();
+    samples.splice(..split, older);
+}
+
+/// Write the downsampled history of all instances as CSV, sorted by instance name
+pub fn write_history_csv<W: Write>(
+    mut writer: W,
+    history: &HashMap<String, Vec<HistorySample>>,
+    interval: Duration,
+) -> io::Result<()> {
+    writeln!(
+        writer,
+        "instance,timestamp,step,total_step,time,eta_minutes"
+    )?;
+
+    let mut names: Vec<_> = history.keys().collect();
+    names.sort();
+    for name in names {
+        for sample in downsample(&history[name], interval) {
+            writeln!(
+                writer,
+                "{},{},{},{},{},{}",
+                name,
+                sample.timestamp.to_rfc3339(),
+                sample.step,
+                sample.total_step,
+                sample.time,
+                sample
+                    .eta_minutes
+                    .map(|minutes| format!("{minutes:.1}"))
+                    .unwrap_or_default()
+            )?;
+        }
+    }
+    writer.flush()
+}
+
+/// Export the downsampled history to a CSV file, replacing its previous contents
+pub fn export_history_csv(
+    path: &Path,
+    history: &HashMap<String, Vec<HistorySample>>,
+    interval: Duration,
+) -> Result<(), MonitorError> {
+    let file = File::create(path)?;
+    write_history_csv(BufWriter::new(file), history, interval)?;
+    Ok(())
+}
+
+/// Turn an instance name into a safe file name, replacing every character other
+/// than ASCII letters, digits, `-` and `_` by `_`
+pub fn sanitize_file_name(name: &str) -> String {
+    let sanitized: String = name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    if sanitized.is_empty() {
+        "_".to_string()
+    } else {
+        sanitized
+    }
+}
+
+/// Write one ETA log row, preceded by the header for a new file
+pub fn write_eta_row<W: Write>(
+    mut writer: W,
+    sample: &HistorySample,
+    with_header: bool,
+) -> io::Result<()> {
+    if with_header {
+        writeln!(writer, "timestamp,step,eta_minutes")?;
+    }
+    writeln!(
+        writer,
+        "{},{},{}",
+        sample.timestamp.to_rfc3339(),
+        sample.step,
+        sample
+            .eta_minutes
+            .map(|minutes| format!("{minutes:.1}"))
+            .unwrap_or_default()
+    )?;
+    writer.flush()
+}
+
+/// Append the sample of a cycle to the `{instance_name}.csv` ETA log of each
+/// instance in `dir`, creating the directory and files as needed
+pub fn append_eta_logs<'a>(
+    dir: &Path,
+    samples: impl IntoIterator<Item = (&'a str, &'a HistorySample)>,
+) -> Result<(), MonitorError> {
+    fs::create_dir_all(dir)?;
+    for (name, sample) in samples {
+        let path = dir.join(format!("{}.csv", sanitize_file_name(name)));
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let is_new = file.metadata()?.len() == 0;
+        write_eta_row(BufWriter::new(file), sample, is_new)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    /// One sample every 5 minutes from midnight, the step counting the minutes
+    fn every_five_minutes(count: usize) -> Vec<HistorySample> {
+        let midnight = Utc.with_ymd_and_hms(2024, 5, 1, 0, 0, 0).unwrap();
+        (0..count)
+            .map(|i| HistorySample {
+                timestamp: midnight + chrono::Duration::minutes(5 * i as i64),
+                step: 5 * i,
+                total_step: 1000,
+                time: 0.0,
+                eta_minutes: None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn dense_samples_keep_the_last_of_every_bucket() {
+        let samples = every_five_minutes(24);
+        let kept: Vec<_> = downsample(&samples, Duration::from_secs(30 * 60))
+            .iter()
+            .map(|sample| sample.step)
+            .collect();
+        assert_eq!(kept, [25, 55, 85, 115]);
+    }
+
+    #[test]
+    fn compacting_downsamples_only_the_older_samples() {
+        let mut samples = every_five_minutes(24);
+        compact(&mut samples, Duration::from_secs(30 * 60), 4);
+        let steps: Vec<_> = samples.iter().map(|sample| sample.step).collect();
+        assert_eq!(steps, [25, 55, 85, 95, 100, 105, 110, 115]);
+
+        // Compacting again with no new samples changes nothing
+        compact(&mut samples, Duration::from_secs(30 * 60), 4);
+        assert_eq!(samples.len(), steps.len());
+    }
+
+    #[test]
+    fn two_cycles_append_two_rows_to_the_instance_file() {
+        let dir = std::env::temp_dir().join(format!("ec2_monitor_eta_logs_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let samples = every_five_minutes(2);
+        for sample in &samples {
+            append_eta_logs(&dir, [("wake/2ms #1", sample)]).unwrap();
+        }
+
+        let contents = fs::read_to_string(dir.join("wake_2ms__1.csv")).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(
+            contents,
+            "timestamp,step,eta_minutes\n\
+             2024-05-01T00:00:00+00:00,0,\n\
+             2024-05-01T00:05:00+00:00,5,\n"
+        );
+    }
+}
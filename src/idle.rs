@@ -0,0 +1,152 @@
+//! Stopping or terminating the finished instances left idle, saving their cost
+
+use aws_sdk_ec2::Client;
+use chrono::{DateTime, Utc};
+use std::fmt::Display;
+use std::str::FromStr;
+use std::time::Duration;
+
+use crate::{InstanceResults, MonitorError};
+
+/// Action taken on a finished instance idle for longer than the idle timeout
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IdleAction {
+    Stop,
+    Terminate,
+}
+
+impl IdleAction {
+    /// Past tense of the action, for the log lines
+    pub fn past_tense(self) -> &'static str {
+        match self {
+            IdleAction::Stop => "Stopped",
+            IdleAction::Terminate => "Terminated",
+        }
+    }
+}
+
+impl Display for IdleAction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            IdleAction::Stop => write!(f, "stop"),
+            IdleAction::Terminate => write!(f, "terminate"),
+        }
+    }
+}
+
+impl FromStr for IdleAction {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "stop" => Ok(IdleAction::Stop),
+            "terminate" => Ok(IdleAction::Terminate),
+            _ => Err(format!(
+                "unknown idle action {s:?}, expected stop or terminate"
+            )),
+        }
+    }
+}
+
+/// Minutes a finished instance has been idle, `None` unless its steps are
+/// complete and no process is running
+pub fn idle_minutes(result: &InstanceResults, now: DateTime<Utc>) -> Option<i64> {
+    let complete = result
+        .timestep_result
+        .as_ref()
+        .is_some_and(|ts| ts.is_complete());
+    if !complete || result.terminated || result.current_process.as_deref() != Some("none") {
+        return None;
+    }
+    result
+        .process_since
+        .map(|since| (now - since).num_minutes())
+}
+
+/// Check whether a finished instance has been idle for at least `timeout`
+pub fn is_idle_action_due(result: &InstanceResults, now: DateTime<Utc>, timeout: Duration) -> bool {
+    result
+        .process_since
+        .and_then(|since| (now - since).to_std().ok())
+        .is_some_and(|idle| idle_minutes(result, now).is_some() && idle >= timeout)
+}
+
+/// Stop or terminate an instance
+pub async fn apply(
+    client: &Client,
+    action: IdleAction,
+    instance_id: &str,
+) -> Result<(), MonitorError> {
+    match action {
+        IdleAction::Stop => client
+            .stop_instances()
+            .instance_ids(instance_id)
+            .send()
+            .await
+            .map(|_| ())
+            .map_err(|e| MonitorError::AwsSdk(e.to_string())),
+        IdleAction::Terminate => client
+            .terminate_instances()
+            .instance_ids(instance_id)
+            .send()
+            .await
+            .map(|_| ())
+            .map_err(|e| MonitorError::AwsSdk(e.to_string())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TimeStep;
+    use chrono::TimeZone;
+
+    /// Instance at `step` of 1000 running `process` since 12:00
+    fn instance(step: usize, process: &str) -> InstanceResults {
+        InstanceResults {
+            name: "node-1".to_string(),
+            timestep_result: Some(TimeStep {
+                step,
+                total_step: 1000,
+                ..TimeStep::default()
+            }),
+            current_process: Some(process.to_string()),
+            process_since: Some(Utc.with_ymd_and_hms(2024, 5, 1, 12, 0, 0).unwrap()),
+            ..InstanceResults::default()
+        }
+    }
+
+    #[test]
+    fn action_is_due_only_for_complete_instances_idle_past_the_timeout() {
+        let timeout = Duration::from_secs(30 * 60);
+        let at = |minute| Utc.with_ymd_and_hms(2024, 5, 1, 12, minute, 0).unwrap();
+
+        let idle = instance(1000, "none");
+        assert_eq!(idle_minutes(&idle, at(29)), Some(29));
+        assert!(!is_idle_action_due(&idle, at(29), timeout));
+        assert!(is_idle_action_due(&idle, at(30), timeout));
+
+        for busy in [
+            instance(1000, "s3 sync"),
+            instance(999, "none"),
+            InstanceResults {
+                terminated: true,
+                ..instance(1000, "none")
+            },
+            InstanceResults {
+                process_since: None,
+                ..instance(1000, "none")
+            },
+        ] {
+            assert!(!is_idle_action_due(&busy, at(59), timeout), "{busy:?}");
+        }
+    }
+
+    #[test]
+    fn idle_actions_round_trip_their_names() {
+        for action in [IdleAction::Stop, IdleAction::Terminate] {
+            assert_eq!(action.to_string().parse(), Ok(action));
+        }
+        assert!("hibernate".parse::<IdleAction>().is_err());
+    }
+}
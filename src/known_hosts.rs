@@ -0,0 +1,207 @@
+//! SSH host key verification against `known_hosts`
+//!
+//! Spot instances replaced behind the same IP come back with a new host key.
+//! When the instance id behind an IP changed since the previous cycle, the
+//! stale key can optionally be replaced instead of failing every cycle.
+//! Only plain entries are replaced: hashed entries (`|1|…`, written with
+//! `HashKnownHosts yes`) cannot be matched to a host name, so a changed key
+//! stored in a hashed entry is still rejected and must be removed by hand.
+
+use ssh2::{CheckResult, KnownHostFileKind, Session};
+use std::collections::HashMap;
+use std::env;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use tracing::info;
+
+use crate::{MonitorConfig, MonitorError};
+
+/// Serializes read-modify-write cycles of the known_hosts file across instances
+static KNOWN_HOSTS_LOCK: Mutex<()> = Mutex::new(());
+
+/// What to do with the host key presented by an instance
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HostKeyAction {
+    /// The key matches the known_hosts entry
+    Accept,
+    /// The host is unknown, trust and record its key
+    Add,
+    /// The key changed because the instance was replaced, record the new key
+    Refresh,
+    /// The key changed unexpectedly or could not be checked
+    Reject,
+}
+
+/// Decide how to handle the result of a known_hosts check
+pub fn host_key_action(check: CheckResult, replaced: bool, auto_refresh: bool) -> HostKeyAction {
+    match check {
+        CheckResult::Match => HostKeyAction::Accept,
+        CheckResult::NotFound => HostKeyAction::Add,
+        CheckResult::Mismatch if replaced && auto_refresh => HostKeyAction::Refresh,
+        CheckResult::Mismatch | CheckResult::Failure => HostKeyAction::Reject,
+    }
+}
+
+/// Check whether the instance behind an IP differs from the one seen previously
+pub fn is_replaced(
+    host_instance_ids: &HashMap<String, String>,
+    ip: &str,
+    instance_id: &str,
+) -> bool {
+    host_instance_ids
+        .get(ip)
+        .is_some_and(|previous_id| previous_id != instance_id)
+}
+
+/// Location of the known_hosts file: the configured path, else `~/.ssh/known_hosts`,
+/// or `None` if no path is configured and the home directory is unknown
+pub fn resolve_known_hosts_path(configured: Option<&Path>, home: Option<&str>) -> Option<PathBuf> {
+    match (configured, home) {
+        (Some(path), _) => Some(path.to_path_buf()),
+        (None, Some(home)) if !home.is_empty() => {
+            Some(PathBuf::from(home).join(".ssh").join("known_hosts"))
+        }
+        _ => None,
+    }
+}
+
+/// Location of the known_hosts file for the configuration and environment
+pub fn known_hosts_path(config: &MonitorConfig) -> Option<PathBuf> {
+    resolve_known_hosts_path(
+        config.known_hosts_path.as_deref(),
+        env::var("HOME").ok().as_deref(),
+    )
+}
+
+/// Verify the host key of an established session, updating known_hosts as needed
+pub fn verify_host_key(
+    sess: &Session,
+    host: &str,
+    port: u16,
+    replaced: bool,
+    config: &MonitorConfig,
+) -> Result<(), MonitorError> {
+    // Verification is disabled at startup when there is no known_hosts file to use
+    let Some(path) = known_hosts_path(config) else {
+        return Ok(());
+    };
+    let (key, key_type) = sess
+        .host_key()
+        .ok_or_else(|| MonitorError::HostKeyMismatch {
+            host: host.to_string(),
+        })?;
+
+    let _guard = KNOWN_HOSTS_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let mut known_hosts = sess.known_hosts()?;
+    if path.exists() {
+        known_hosts.read_file(&path, KnownHostFileKind::OpenSSH)?;
+    }
+
+    let entry = if port == 22 {
+        host.to_string()
+    } else {
+        format!("[{host}]:{port}")
+    };
+    match host_key_action(
+        known_hosts.check_port(host, port, key),
+        replaced,
+        config.refresh_replaced_host_keys,
+    ) {
+        HostKeyAction::Accept => return Ok(()),
+        HostKeyAction::Add => {}
+        HostKeyAction::Refresh => {
+            info!("Refreshing host key of replaced instance at {entry}");
+            for stale in known_hosts.hosts()? {
+                if stale.name() == Some(entry.as_str()) {
+                    known_hosts.remove(&stale)?;
+                }
+            }
+            // A remaining mismatch comes from a hashed entry that cannot be replaced
+            if let CheckResult::Mismatch = known_hosts.check_port(host, port, key) {
+                return Err(MonitorError::HostKeyMismatch {
+                    host: entry.clone(),
+                });
+            }
+        }
+        HostKeyAction::Reject => {
+            return Err(MonitorError::HostKeyMismatch {
+                host: entry.clone(),
+            });
+        }
+    }
+
+    known_hosts.add(&entry, key, "added by ec2_monitor", key_type.into())?;
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    known_hosts.write_file(&path, KnownHostFileKind::OpenSSH)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn changed_key_is_refreshed_only_for_a_replaced_instance_with_auto_refresh() {
+        assert_eq!(
+            host_key_action(CheckResult::Mismatch, true, true),
+            HostKeyAction::Refresh
+        );
+        assert_eq!(
+            host_key_action(CheckResult::Mismatch, true, false),
+            HostKeyAction::Reject
+        );
+        assert_eq!(
+            host_key_action(CheckResult::Mismatch, false, true),
+            HostKeyAction::Reject
+        );
+    }
+
+    #[test]
+    fn matching_unknown_and_failed_checks_ignore_the_replacement() {
+        for replaced in [false, true] {
+            assert_eq!(
+                host_key_action(CheckResult::Match, replaced, true),
+                HostKeyAction::Accept
+            );
+            assert_eq!(
+                host_key_action(CheckResult::NotFound, replaced, true),
+                HostKeyAction::Add
+            );
+            assert_eq!(
+                host_key_action(CheckResult::Failure, replaced, true),
+                HostKeyAction::Reject
+            );
+        }
+    }
+
+    #[test]
+    fn instance_is_replaced_when_the_id_behind_the_ip_changed() {
+        let seen = HashMap::from([("10.0.0.1".to_string(), "i-old".to_string())]);
+        assert!(is_replaced(&seen, "10.0.0.1", "i-new"));
+        assert!(!is_replaced(&seen, "10.0.0.1", "i-old"));
+        assert!(!is_replaced(&seen, "10.0.0.2", "i-new"));
+    }
+
+    #[test]
+    fn known_hosts_path_defaults_to_the_home_directory() {
+        assert_eq!(
+            resolve_known_hosts_path(None, Some("/home/cfd")),
+            Some(PathBuf::from("/home/cfd/.ssh/known_hosts"))
+        );
+        assert_eq!(resolve_known_hosts_path(None, None), None);
+        assert_eq!(resolve_known_hosts_path(None, Some("")), None);
+    }
+
+    #[test]
+    fn configured_known_hosts_path_needs_no_home() {
+        let configured = Path::new("/etc/ssh/ssh_known_hosts");
+        for home in [None, Some("/home/cfd")] {
+            assert_eq!(
+                resolve_known_hosts_path(Some(configured), home),
+                Some(configured.to_path_buf())
+            );
+        }
+    }
+}
@@ -0,0 +1,146 @@
+//! Ledger of completed instances, keeping them visible after termination
+
+use chrono::{DateTime, Utc};
+use std::collections::HashMap;
+
+use crate::InstanceResults;
+
+/// Last results of a completed instance
+#[derive(Debug, Clone)]
+pub struct LedgerEntry {
+    pub result: InstanceResults,
+    /// When the instance was first seen complete
+    pub completed_at: DateTime<Utc>,
+    /// Number of cycles since the instance was last discovered
+    pub absent_cycles: usize,
+}
+
+/// Completed instances by name
+#[derive(Debug, Clone, Default)]
+pub struct CompletionLedger {
+    entries: HashMap<String, LedgerEntry>,
+}
+
+impl CompletionLedger {
+    /// Record the complete instances of a cycle, age the ones that were not
+    /// discovered and forget those absent for more than `retention_cycles`
+    pub fn update(
+        &mut self,
+        results: &[InstanceResults],
+        retention_cycles: usize,
+        now: DateTime<Utc>,
+    ) {
+        let mut seen = Vec::with_capacity(results.len());
+        for result in results {
+            seen.push(result.name.as_str());
+            if is_complete(result) {
+                let completed_at = self
+                    .entries
+                    .get(&result.name)
+                    .map_or(now, |entry| entry.completed_at);
+                self.entries.insert(
+                    result.name.clone(),
+                    LedgerEntry {
+                        result: result.clone(),
+                        completed_at,
+                        absent_cycles: 0,
+                    },
+                );
+            } else if result.connection_error.is_none() {
+                // The name is running a new job
+                self.entries.remove(&result.name);
+            }
+        }
+
+        for (name, entry) in &mut self.entries {
+            if !seen.contains(&name.as_str()) {
+                entry.absent_cycles += 1;
+            }
+        }
+        self.entries
+            .retain(|_, entry| entry.absent_cycles <= retention_cycles);
+    }
+
+    /// Results of the completed instances missing from the current cycle,
+    /// marked as terminated and sorted by name
+    pub fn retained(&self) -> Vec<InstanceResults> {
+        let mut retained: Vec<_> = self
+            .entries
+            .values()
+            .filter(|entry| entry.absent_cycles > 0)
+            .map(|entry| InstanceResults {
+                terminated: true,
+                ..entry.result.clone()
+            })
+            .collect();
+        retained.sort_by(|a, b| a.name.cmp(&b.name));
+        retained
+    }
+}
+
+fn is_complete(result: &InstanceResults) -> bool {
+    result
+        .timestep_result
+        .as_ref()
+        .is_some_and(|ts| ts.is_complete())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TimeStep;
+
+    fn complete(name: &str) -> InstanceResults {
+        InstanceResults {
+            name: name.to_string(),
+            timestep_result: Some(TimeStep {
+                step: 1000,
+                total_step: 1000,
+                ..TimeStep::default()
+            }),
+            ..InstanceResults::default()
+        }
+    }
+
+    fn retained_names(ledger: &CompletionLedger) -> Vec<String> {
+        ledger.retained().into_iter().map(|r| r.name).collect()
+    }
+
+    #[test]
+    fn terminated_instances_stay_within_the_retention_window() {
+        let mut ledger = CompletionLedger::default();
+        let now = Utc::now();
+        ledger.update(&[complete("node-1"), complete("node-2")], 2, now);
+        assert!(ledger.retained().is_empty());
+
+        // node-1 was terminated after completing
+        for _ in 0..2 {
+            ledger.update(&[complete("node-2")], 2, now);
+            let retained = ledger.retained();
+            assert_eq!(retained.len(), 1);
+            assert_eq!(retained[0].name, "node-1");
+            assert!(retained[0].terminated);
+        }
+
+        ledger.update(&[complete("node-2")], 2, now);
+        assert!(retained_names(&ledger).is_empty());
+    }
+
+    #[test]
+    fn a_new_job_under_the_same_name_leaves_the_ledger() {
+        let mut ledger = CompletionLedger::default();
+        let now = Utc::now();
+        ledger.update(&[complete("node-1")], 2, now);
+        let restarted = InstanceResults {
+            timestep_result: Some(TimeStep {
+                step: 10,
+                total_step: 1000,
+                ..TimeStep::default()
+            }),
+            ..complete("node-1")
+        };
+        ledger.update(&[restarted], 2, now);
+        ledger.update(&[], 2, now);
+        assert!(retained_names(&ledger).is_empty());
+    }
+}
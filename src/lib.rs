@@ -4,28 +4,97 @@
 //! Provides functionality for parallel instance monitoring, SSH command execution,
 //! ETA tracking and median calculation, and formatted reporting.
 
-use aws_config::BehaviorVersion;
+use aws_config::meta::region::RegionProviderChain;
+use aws_config::retry::RetryConfig;
+use aws_config::timeout::TimeoutConfig;
+use aws_config::{BehaviorVersion, Region};
 use aws_sdk_ec2::Client;
+use std::borrow::Cow;
 use std::collections::HashMap;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::task::{JoinError, JoinSet};
+use tracing::{Instrument, debug, error, info, info_span, warn};
 
+pub mod anonymize;
 pub mod aws;
+pub mod cli;
+pub mod config;
+pub mod cpu;
+pub mod disk;
 pub mod error;
 pub mod eta;
+pub mod history;
+pub mod idle;
+pub mod known_hosts;
+pub mod ledger;
+pub mod logging;
+pub mod memory;
+pub mod network;
+pub mod notes;
+pub mod notify;
+pub mod output;
+pub mod persist;
+pub mod preflight;
+pub mod proxy;
+pub mod replay;
 pub mod report;
+pub mod retry;
+pub mod schedule;
+pub mod server;
+pub mod solver;
 pub mod ssh;
+pub mod state;
+pub mod tui;
 pub mod types;
 
+use aws::RegionClient;
+use config::DEFAULT_REGION;
+pub use config::{AwsOptions, MonitorConfig};
 pub use error::MonitorError;
-pub use types::{InstanceInfo, InstanceResults, TimeStep};
+pub use notify::{MonitorEvent, Notifier};
+pub use state::MonitorState;
+pub use types::{InstanceInfo, InstanceResults, RawProbe, TimeStep};
+
+/// Initialize AWS configuration for EC2 monitoring in `region`, else in the
+/// region of the SDK default chain (`AWS_REGION`, the profile), else in
+/// [`DEFAULT_REGION`]
+pub async fn init_aws_config(
+    options: &AwsOptions,
+    region: Option<String>,
+) -> aws_config::SdkConfig {
+    let timeout_config = TimeoutConfig::builder()
+        .connect_timeout(options.connect_timeout)
+        .read_timeout(options.read_timeout)
+        .build();
+    let retry_config = RetryConfig::standard().with_max_attempts(options.max_attempts);
 
-/// Initialize AWS configuration for EC2 monitoring
-pub async fn init_aws_config() -> aws_config::SdkConfig {
     aws_config::defaults(BehaviorVersion::latest())
-        .region(aws_config::Region::new("sa-east-1"))
+        .region(
+            RegionProviderChain::first_try(region.map(Region::new))
+                .or_default_provider()
+                .or_else(Region::new(DEFAULT_REGION)),
+        )
+        .timeout_config(timeout_config)
+        .retry_config(retry_config)
         .load()
         .await
 }
 
+/// Initialize the AWS configuration of every configured region, or of the
+/// default region when none is configured
+pub async fn init_aws_configs(options: &AwsOptions) -> Vec<aws_config::SdkConfig> {
+    if options.regions.is_empty() {
+        return vec![init_aws_config(options, None).await];
+    }
+    let mut configs = Vec::new();
+    for region in &options.regions {
+        configs.push(init_aws_config(options, Some(region.clone())).await);
+    }
+    configs
+}
+
 /// Create AWS EC2 client
 pub fn create_ec2_client(config: &aws_config::SdkConfig) -> Client {
     Client::new(config)
@@ -33,117 +102,866 @@ pub fn create_ec2_client(config: &aws_config::SdkConfig) -> Client {
 
 /// Run a complete monitoring cycle
 pub async fn monitor_cycle(
-    client: &Client,
-    previous_timesteps: &mut HashMap<String, TimeStep>,
-    instance_etas: &mut HashMap<String, Vec<f64>>,
+    clients: &[RegionClient],
+    config: &MonitorConfig,
+    state: &mut MonitorState,
+    notifiers: &[Box<dyn Notifier>],
 ) -> Result<(), MonitorError> {
-    // Find all target instances (c8g.48xlarge and c6g.4xlarge)
-    let instances = aws::find_target_instances(client).await?;
+    let cycle_start = Instant::now();
+    let MonitorState {
+        previous_timesteps,
+        instance_etas,
+        observed_cycles,
+        first_observed,
+        completion_ledger,
+        previous_results,
+        host_instance_ids,
+        summary,
+        history,
+        disk_samples,
+        idle_actioned,
+        anonymizer,
+        cycle_count,
+        campaign_complete,
+        sentinel_printed,
+        render,
+        ..
+    } = state;
+
+    // Find the explicit instances, or all instances of the target types and states
+    let instances = aws::discover_instances(clients, config).await?;
+    let instance_regions: HashMap<String, String> = instances
+        .iter()
+        .map(|instance| (instance.instance_id.clone(), instance.region.clone()))
+        .collect();
 
     if instances.is_empty() {
-        println!("No target instances found in sa-east-1 region");
+        info!("{}", aws::no_instances_message(config));
         return Ok(());
     }
 
-    println!("🔍 Found {} target instances:", instances.len());
-    println!("🚀 Processing all instances in parallel...");
+    info!("Found {} target instances", instances.len());
+    let budget = Arc::new(retry::RetryBudget::new(config.retry_budget));
+    let proxy_limits = Arc::new(proxy::ProxyLimits::new(config.proxy_connections));
+    let is_replaced = |instance: &InstanceInfo| {
+        [&instance.public_ip, &instance.private_ip]
+            .into_iter()
+            .flatten()
+            .any(|ip| known_hosts::is_replaced(host_instance_ids, ip, &instance.instance_id))
+    };
+    let mut outcomes = Vec::new();
+    if config.sequential {
+        info!("Processing instances one at a time...");
 
-    // Process all instances in parallel using tokio::spawn
-    let mut tasks = Vec::new();
-    for instance in instances {
-        let instance_clone = instance.clone();
-        let task = tokio::spawn(async move {
-            println!(
-                "Processing instance: {} ({})",
-                instance_clone.name, instance_clone.instance_id
+        // Process the instances in name order, without jitter, reporting each inline
+        let (budget, proxy_limits) = (&budget, &proxy_limits);
+        let probed = probe_sequentially(instances, |instance| async move {
+            let span = instance_span(&instance);
+            async {
+                debug!("Processing instance");
+                let outcome = ssh::process_instance(
+                    &instance,
+                    config,
+                    is_replaced(&instance),
+                    budget,
+                    proxy_limits,
+                )
+                .await;
+                match &outcome {
+                    Ok(_) => info!("Processed"),
+                    Err(e) => error!("Failed: {e}"),
+                }
+                outcome
+            }
+            .instrument(span)
+            .await
+        })
+        .await;
+        outcomes.extend(
+            probed
+                .into_iter()
+                .map(|(instance, outcome)| (instance, Ok(outcome))),
+        );
+    } else {
+        info!("Processing all instances in parallel...");
+
+        // Process all instances in parallel using tokio::spawn, sharing the retry budget
+        // and spreading the connections over the startup jitter
+        let delays = schedule::start_delays(
+            instances.len(),
+            config.startup_jitter,
+            schedule::random_delay,
+        );
+        let mut tasks = JoinSet::new();
+        let mut pending = HashMap::new();
+        for (index, (instance, delay)) in instances.into_iter().zip(delays).enumerate() {
+            let replaced = is_replaced(&instance);
+            let instance_clone = instance.clone();
+            let config_clone = config.clone();
+            let budget = Arc::clone(&budget);
+            let proxy_limits = Arc::clone(&proxy_limits);
+            let span = instance_span(&instance);
+            let task = tasks.spawn(
+                async move {
+                    tokio::time::sleep(delay).await;
+                    debug!("Processing instance");
+                    ssh::process_instance(
+                        &instance_clone,
+                        &config_clone,
+                        replaced,
+                        &budget,
+                        &proxy_limits,
+                    )
+                    .await
+                }
+                .instrument(span),
             );
-            ssh::process_instance(&instance_clone).await
-        });
-        tasks.push((instance, task));
+            pending.insert(task.id(), (index, instance));
+        }
+
+        let cycle_timeout = config.cycle_timeout.unwrap_or(config.refresh_interval);
+        outcomes.extend(collect_until_deadline(tasks, pending, cycle_timeout).await);
     }
 
-    // Wait for all tasks to complete and collect results
+    // Record the raw inputs of the cycle for a later replay
+    if let Some(path) = &config.record_path {
+        let cycle = replay::RecordedCycle {
+            recorded_at: chrono::Utc::now(),
+            instances: outcomes
+                .iter()
+                .map(|(instance, outcome)| {
+                    let outcome = match outcome {
+                        Ok(Ok(result)) => Ok(result),
+                        Ok(Err(e)) => Err(e.to_string()),
+                        Err(e) => Err(e.to_string()),
+                    };
+                    replay::RecordedInstance::new(instance, outcome)
+                })
+                .collect(),
+        };
+        if let Err(e) = replay::append(path, &cycle) {
+            warn!("Failed to record the cycle to {}: {}", path.display(), e);
+        }
+    }
+
+    // Collect the results
     let mut results = Vec::new();
-    for (instance, task) in tasks {
-        match task.await {
+    for (instance, outcome) in outcomes {
+        match outcome {
             Ok(process_result) => {
                 match process_result {
                     Ok(mut result) => {
-                        // Calculate step increase if we have a previous timestep
-                        if let Some(current_timestep) = &mut result.timestep_result {
+                        // Remember which instance answered on this IP
+                        if let Some(endpoint) = &result.endpoint {
+                            host_instance_ids
+                                .insert(endpoint.ip.clone(), instance.instance_id.clone());
+                        }
+
+                        // Carry the previous step forward for one cycle when the log read
+                        // came back empty, e.g. while the solver rotated its log
+                        let was_stale = previous_results
+                            .get(&instance.name)
+                            .is_some_and(|previous| previous.stale_timestep);
+                        if let Some(probe) = &result.raw_probe
+                            && let Some(carried) = TimeStep::carry_forward(
+                                &probe.timestep,
+                                previous_timesteps.get(&instance.name),
+                                was_stale,
+                            )
+                        {
+                            result.timestep_result = Some(carried);
+                            result.stale_timestep = true;
+                        }
+
+                        if result.stale_timestep {
+                            // Keep the previous ETA and rates without recording a sample
+                            if let Some(previous) = previous_results.get(&instance.name) {
+                                result.eta = previous.eta.clone();
+                                result.eta_minutes = previous.eta_minutes;
+                                result.current_rate = previous.current_rate;
+                                result.average_rate = previous.average_rate;
+                            }
+                        } else if let Some(current_timestep) = &mut result.timestep_result {
+                            // Calculate step increase if we have a previous timestep
                             if let Some(previous_timestep) = previous_timesteps.get(&instance.name)
                             {
                                 let step_increase =
                                     current_timestep.step.saturating_sub(previous_timestep.step);
                                 current_timestep.step_increase = Some(step_increase);
+                                // The configured interval when the observations can't be timed
+                                current_timestep.interval_minutes =
+                                    schedule::elapsed_minutes(previous_timestep, current_timestep)
+                                        .or(Some(config.refresh_interval.as_secs_f64() / 60.0));
+                                result.current_rate = current_timestep
+                                    .interval_minutes
+                                    .map(|minutes| step_increase as f64 / minutes);
                             }
 
-                            // Calculate and store ETA
+                            // Lifetime average rate, restarting when the job restarted, over
+                            // the true solver elapsed time when its start time is known
+                            if let Some(observed_at) = current_timestep.observed_at {
+                                let current = (current_timestep.step, observed_at);
+                                let first = first_observed
+                                    .entry(instance.name.clone())
+                                    .or_insert(current);
+                                if current.0 < first.0 {
+                                    *first = current;
+                                }
+                                result.average_rate = match result.solver_started_at {
+                                    Some(started_at) => eta::step_rate((0, started_at), current),
+                                    None => eta::step_rate(*first, current),
+                                };
+                            }
+
+                            // Calculate and store ETA, flagging stalls caused by a full disk
                             result.eta = current_timestep.calculate_eta();
+                            result.eta_minutes = current_timestep.remaining_minutes();
+                            if config.regression_samples >= 2
+                                && current_timestep.total_step > 0
+                                && let Some(observed_at) = current_timestep.observed_at
+                            {
+                                let samples = regression_samples(
+                                    history.get(&instance.name).map_or(&[][..], Vec::as_slice),
+                                    (current_timestep.step, observed_at),
+                                    config.regression_samples,
+                                );
+                                if let Some(eta) =
+                                    eta::regression_eta(&samples, current_timestep.total_step)
+                                {
+                                    result.eta = Some(eta);
+                                    result.eta_minutes = eta::regression_minutes(
+                                        &samples,
+                                        current_timestep.total_step,
+                                    );
+                                }
+                            }
+                            let eta = result.eta.take();
+                            result.eta = eta.map(|eta| {
+                                eta::with_stall_cause(eta, result.free_disk_space.as_deref())
+                            });
+                            if result.diverged {
+                                result.eta = Some(eta::DIVERGED.to_string());
+                                result.eta_minutes = None;
+                            }
+                            if result.read_only {
+                                result.eta = Some(eta::READ_ONLY_FS.to_string());
+                                result.eta_minutes = None;
+                            }
 
-                            // Collect ETA in minutes for median calculation per instance
+                            // Collect ETA in minutes for median calculation per instance,
+                            // showing but not recording the ETA while the job warms up
+                            let cycles = observed_cycles.entry(instance.name.clone()).or_default();
+                            let warming_up = eta::in_warmup(
+                                current_timestep.step,
+                                *cycles,
+                                config.warmup_steps,
+                                config.warmup_cycles,
+                            );
+                            *cycles += 1;
                             if let Some(eta_str) = &result.eta {
-                                // If simulation is complete, set median ETA to 0
-                                if eta_str == "Complete" {
-                                    instance_etas
-                                        .entry(instance.name.clone())
-                                        .or_insert_with(Vec::new)
-                                        .clear(); // Clear previous ETAs
-                                    instance_etas
-                                        .get_mut(&instance.name)
-                                        .unwrap()
-                                        .push(0.0); // Set median ETA to 0 minutes
-                                } else if let Some(eta_minutes) = eta::parse_eta_to_minutes(eta_str) {
-                                    instance_etas
-                                        .entry(instance.name.clone())
-                                        .or_insert_with(Vec::new)
-                                        .push(eta_minutes);
-                                }
+                                eta::record_sample(
+                                    instance_etas.entry(instance.name.clone()).or_default(),
+                                    eta_str,
+                                    result.eta_minutes,
+                                    warming_up,
+                                );
                             }
 
+                            // Record progress history
+                            history.entry(instance.name.clone()).or_default().push(
+                                history::HistorySample {
+                                    timestamp: chrono::Utc::now(),
+                                    step: current_timestep.step,
+                                    total_step: current_timestep.total_step,
+                                    time: current_timestep.time,
+                                    eta_minutes: result.eta_minutes,
+                                },
+                            );
+
                             // Store current timestep for next iteration
                             previous_timesteps
                                 .insert(instance.name.clone(), current_timestep.clone());
                         }
 
+                        // Track the free disk space trend over the latest cycles
+                        if let Some(gb) = result
+                            .free_disk_space
+                            .as_deref()
+                            .and_then(disk::parse_disk_space_gb)
+                        {
+                            let samples = disk_samples.entry(instance.name.clone()).or_default();
+                            samples.push((chrono::Utc::now(), gb));
+                            if samples.len() > disk::DISK_TREND_SAMPLES {
+                                samples.remove(0);
+                            }
+                            let start = samples[0].0;
+                            let points: Vec<_> = samples
+                                .iter()
+                                .map(|(at, gb)| {
+                                    ((*at - start).num_milliseconds() as f64 / 60_000.0, *gb)
+                                })
+                                .collect();
+                            result.disk_full_minutes = disk::time_to_full_minutes(&points);
+                        }
+
+                        // Track how long the current process has been running, warning
+                        // once when an s3 sync exceeds the stuck sync limit
+                        let now = chrono::Utc::now();
+                        let previous = previous_results.get(&instance.name);
+                        result.process_since =
+                            notify::process_since(previous, result.current_process.as_deref(), now);
+                        result.sync_stuck =
+                            notify::is_sync_stuck(&result, now, config.stuck_sync_limit);
+                        if result.sync_stuck && !previous.is_some_and(|p| p.sync_stuck) {
+                            warn!(
+                                "s3 sync of {} running for over {} minutes",
+                                instance.name,
+                                config.stuck_sync_limit.as_secs() / 60
+                            );
+                        }
+
+                        if config.output_eta {
+                            output::update(&mut result, previous, config.output_target_bytes, now);
+                        }
+
+                        // Warn once when the CSV count stays flat while the steps advance
+                        result.csv_flat_cycles = notify::csv_flat_cycles(previous, &result);
+                        result.csv_stalled = result.csv_flat_cycles >= config.csv_stall_cycles;
+                        if result.csv_stalled && !previous.is_some_and(|p| p.csv_stalled) {
+                            warn!(
+                                "CSV count of {} flat for {} cycles while the steps advance",
+                                instance.name, result.csv_flat_cycles
+                            );
+                        }
+
                         results.push(result);
                     }
                     Err(e) => {
                         // Handle process_instance error - convert MonitorError to InstanceResults
-                        let error_message = match &e {
-                            MonitorError::NoPublicIp => "No public IP available".to_string(),
-                            _ => format!("Processing error: {}", e),
-                        };
-                        results.push(InstanceResults {
-                            instance_id: instance.instance_id.clone(),
-                            public_ip: instance.public_ip.clone(),
-                            name: instance.name.clone(),
-                            instance_type: instance.instance_type.clone(),
-                            connection_error: Some(error_message),
-                            ..Default::default()
-                        });
+                        let error_message = error_label(&e);
+                        results.push(failed_result(&instance, error_message, config));
                     }
                 }
             }
             Err(e) => {
                 // Handle tokio task join error
-                results.push(InstanceResults {
-                    instance_id: instance.instance_id.clone(),
-                    public_ip: instance.public_ip.clone(),
-                    name: instance.name.clone(),
-                    instance_type: instance.instance_type.clone(),
-                    connection_error: Some(format!("Task error: {}", e)),
-                    ..Default::default()
-                });
+                results.push(failed_result(
+                    &instance,
+                    format!("Task error: {}", e),
+                    config,
+                ));
+            }
+        }
+    }
+
+    // Keep recently completed instances visible after their termination
+    completion_ledger.update(
+        &results,
+        config.complete_retention_cycles,
+        chrono::Utc::now(),
+    );
+    results.extend(completion_ledger.retained());
+
+    // Bound the progress history: forget the instances that are gone and
+    // downsample all but the latest samples, which feed the regression ETA
+    history.retain(|name, _| results.iter().any(|r| &r.name == name));
+    for samples in history.values_mut() {
+        history::compact(
+            samples,
+            config.history_interval,
+            config.regression_samples.max(history::RECENT_SAMPLES),
+        );
+    }
+
+    // Attach notes, reloading the file every cycle so edits show up without a restart
+    let notes = notes::Notes::load(&config.notes_path).unwrap_or_else(|e| {
+        warn!("Ignoring notes from {}: {}", config.notes_path.display(), e);
+        notes::Notes::default()
+    });
+    for result in &mut results {
+        result.note = notes
+            .lookup(&result.instance_id, &result.name)
+            .map(str::to_string);
+    }
+
+    // Export the downsampled progress history
+    let exported_history = if config.anonymize {
+        Cow::Owned(anonymizer.alias_keys(history))
+    } else {
+        Cow::Borrowed(&*history)
+    };
+    if let Some(path) = &config.history_csv
+        && let Err(e) =
+            history::export_history_csv(path, &exported_history, config.history_interval)
+    {
+        warn!("Failed to export history to {}: {}", path.display(), e);
+    }
+
+    // Append this cycle's samples to the per-instance ETA logs
+    if let Some(dir) = &config.eta_log_dir {
+        let samples: Vec<_> = results
+            .iter()
+            .filter(|r| r.connection_error.is_none() && r.timestep_result.is_some())
+            .filter_map(|r| Some((&r.name, history.get(&r.name)?.last()?)))
+            .map(|(name, sample)| {
+                let name = if config.anonymize {
+                    anonymizer.alias(name)
+                } else {
+                    name.clone()
+                };
+                (name, sample)
+            })
+            .collect();
+        let samples = samples
+            .iter()
+            .map(|(name, sample)| (name.as_str(), *sample));
+        if let Err(e) = history::append_eta_logs(dir, samples) {
+            warn!("Failed to write ETA logs to {}: {}", dir.display(), e);
+        }
+    }
+
+    // Stop or terminate the finished instances idle for longer than the idle timeout
+    if let Some(action) = config.idle_action {
+        let now = chrono::Utc::now();
+        for result in &results {
+            if !idle::is_idle_action_due(result, now, config.idle_timeout)
+                || !idle_actioned.insert(result.instance_id.clone())
+            {
+                continue;
+            }
+            let idle_for = idle::idle_minutes(result, now).unwrap_or_default();
+            if config.dry_run {
+                info!(
+                    "Would {action} {} ({}), idle for {idle_for} minutes (dry run)",
+                    result.name, result.instance_id
+                );
+                continue;
+            }
+            let Some(client) = instance_regions
+                .get(&result.instance_id)
+                .and_then(|region| aws::client_in(clients, region))
+            else {
+                continue;
+            };
+            match idle::apply(client, action, &result.instance_id).await {
+                Ok(()) => info!(
+                    "{} {} ({}), idle for {idle_for} minutes",
+                    action.past_tense(),
+                    result.name,
+                    result.instance_id
+                ),
+                Err(e) => {
+                    // Try again on the next cycle
+                    idle_actioned.remove(&result.instance_id);
+                    warn!("Failed to {action} idle instance {}: {}", result.name, e);
+                }
             }
         }
     }
 
-    // Clear terminal for clean display
-    report::clear_terminal();
+    // ETA samples entering the medians, optionally without the first one of each instance
+    let median_etas: HashMap<_, _> = instance_etas
+        .iter()
+        .map(|(name, etas)| {
+            let samples = eta::median_samples(etas, config.skip_first_eta_sample);
+            (name.clone(), samples.to_vec())
+        })
+        .collect();
+    let instance_etas = &median_etas;
+
+    // Publish the fleet aggregates for the summary endpoint
+    *summary.write().unwrap_or_else(|e| e.into_inner()) =
+        report::FleetSummary::from_results(&results, instance_etas);
+
+    // Redact the instance names, ids and IPs, and the account, of the reports
+    let anonymized = config.anonymize.then(|| {
+        let results: Vec<_> = results.iter().map(|r| anonymizer.anonymize(r)).collect();
+        let etas = anonymizer.alias_keys(instance_etas);
+        let previous: HashMap<_, _> = previous_results
+            .iter()
+            .map(|(name, r)| (anonymizer.alias(name), anonymizer.anonymize(r)))
+            .collect();
+        (results, etas, previous)
+    });
+    let (report_results, report_etas, report_previous, account) = match &anonymized {
+        Some((results, etas, previous)) => (results.as_slice(), etas, previous, None),
+        None => (
+            results.as_slice(),
+            instance_etas,
+            &*previous_results,
+            config.account.as_deref(),
+        ),
+    };
+
+    *cycle_count += 1;
+    let options = report::ReportOptions::from_config(config);
+    if config.interactive || config.quiet {
+        // The interactive mode renders the stored results itself
+    } else if config.json_output {
+        report::print_summary_report_json(report_results, report_etas)?;
+    } else if config.changes_only {
+        // Append-style feed of changed rows, no terminal clearing
+        let next_update = chrono::Utc::now()
+            + schedule::sleep_duration(config.refresh_interval, cycle_start.elapsed());
+        report::print_changes_report(
+            report_results,
+            report_previous,
+            &options,
+            *cycle_count,
+            next_update,
+        )?;
+    } else if config.pager {
+        report::page_summary_report(report_results, report_etas, &options, account)?;
+    } else {
+        // Render the summary report, drawing it now unless the screen was
+        // redrawn within the render interval
+        let mut rendered = Vec::new();
+        report::write_summary_report(
+            &mut rendered,
+            report_results,
+            report_etas,
+            &options,
+            account,
+        )?;
+        let rendered = String::from_utf8_lossy(&rendered).into_owned();
+        if let Some(rendered) = render.submit(rendered, Instant::now(), config.render_interval) {
+            report::redraw(&rendered, options.plain);
+        }
+    }
+
+    // Notify the events of this cycle
+    let mut events = notify::detect_events(
+        previous_results,
+        &results,
+        *campaign_complete,
+        chrono::Utc::now(),
+    );
+    if config.alert_vanished {
+        for name in notify::vanished_instances(previous_results, &results) {
+            warn!("Instance {name} vanished before completing");
+            events.push(MonitorEvent::InstanceVanished {
+                name: name.to_string(),
+            });
+        }
+    }
+    *campaign_complete = notify::is_campaign_complete(&results);
+    notify::dispatch(notifiers, &events).await;
 
-    // Print summary report
-    report::print_summary_report(&results, instance_etas)?;
+    // Print the sentinel line for wrapper scripts, once per run
+    if let Some(line) = notify::campaign_sentinel(&results, *sentinel_printed, chrono::Utc::now()) {
+        println!("{line}");
+        *sentinel_printed = true;
+    }
+
+    // Store results for change detection in the next cycle
+    *previous_results = results
+        .into_iter()
+        .map(|result| (result.name.clone(), result))
+        .collect();
+
+    // Keep the ETA state for a restart of the monitor
+    if let Some(path) = &config.state_file {
+        let persisted = persist::PersistedState::from_state(state);
+        if let Err(e) = persist::save(
+            path,
+            &persisted,
+            config.state_rotate_bytes,
+            config.state_keep,
+        ) {
+            warn!("Failed to save the state to {}: {}", path.display(), e);
+        }
+    }
 
     Ok(())
 }
+
+/// Run a shell command on every target instance in parallel and print the
+/// output of each instance, in name order
+pub async fn exec_on_fleet(
+    clients: &[RegionClient],
+    config: &MonitorConfig,
+    command: &str,
+) -> Result<(), MonitorError> {
+    let mut instances = aws::discover_instances(clients, config).await?;
+    if instances.is_empty() {
+        info!("{}", aws::no_instances_message(config));
+        return Ok(());
+    }
+    instances.sort_by(|a, b| a.name.cmp(&b.name));
+
+    let proxy_limits = Arc::new(proxy::ProxyLimits::new(config.proxy_connections));
+    let mut tasks = Vec::new();
+    for instance in instances {
+        let instance_clone = instance.clone();
+        let config_clone = config.clone();
+        let command = command.to_string();
+        let proxy_limits = Arc::clone(&proxy_limits);
+        let span = instance_span(&instance);
+        let task = tokio::spawn(
+            async move {
+                ssh::exec_on_instance(&instance_clone, &config_clone, &command, &proxy_limits).await
+            }
+            .instrument(span),
+        );
+        tasks.push((instance, task));
+    }
+
+    for (instance, task) in tasks {
+        println!("=== {} ({}) ===", instance.name, instance.instance_id);
+        match task.await? {
+            Ok(output) => println!("{output}"),
+            Err(e) => println!("❌ {e}"),
+        }
+    }
+    Ok(())
+}
+
+/// Connection column label of a failed probe, labelling a retried error by its cause
+fn error_label(error: &MonitorError) -> String {
+    let cause = match error {
+        MonitorError::AfterAttempts { source, .. } => source.as_ref(),
+        e => e,
+    };
+    match cause {
+        MonitorError::NoPublicIp => "No public IP available".to_string(),
+        MonitorError::NoPrivateIp => "No private IP available".to_string(),
+        MonitorError::HostKeyMismatch { .. } => "Host key changed".to_string(),
+        MonitorError::HandshakeTimeout { .. } => "Handshake timeout".to_string(),
+        MonitorError::ConnectionTimeout { .. } => "Connection timeout".to_string(),
+        MonitorError::CycleTimeout { .. } => "Cycle timeout".to_string(),
+        MonitorError::DirectConnect { .. } => "Connection failed".to_string(),
+        MonitorError::ProxyConnect { .. } => "Proxy failed".to_string(),
+        _ => format!("Processing error: {}", error),
+    }
+}
+
+/// Span of the logs of an instance, carrying its id and name
+fn instance_span(instance: &InstanceInfo) -> tracing::Span {
+    info_span!(
+        "instance",
+        instance_id = %instance.instance_id,
+        name = %instance.name
+    )
+}
+
+/// Probe the instances one at a time in name order, each probe finishing
+/// before the next one starts
+async fn probe_sequentially<T, F, Fut>(
+    mut instances: Vec<InstanceInfo>,
+    mut probe: F,
+) -> Vec<(InstanceInfo, T)>
+where
+    F: FnMut(InstanceInfo) -> Fut,
+    Fut: Future<Output = T>,
+{
+    instances.sort_by(|a, b| a.name.cmp(&b.name));
+    let mut probed = Vec::with_capacity(instances.len());
+    for instance in instances {
+        let outcome = probe(instance.clone()).await;
+        probed.push((instance, outcome));
+    }
+    probed
+}
+
+/// Collect the outcomes of the probe tasks as they finish, until
+/// `cycle_timeout`, in the order of their `pending` index
+///
+/// The tasks still running at the deadline are reported as timed out, and
+/// aborted when the task set is dropped.
+async fn collect_until_deadline<R: Send + 'static>(
+    mut tasks: JoinSet<Result<R, MonitorError>>,
+    mut pending: HashMap<tokio::task::Id, (usize, InstanceInfo)>,
+    cycle_timeout: Duration,
+) -> Vec<(InstanceInfo, Result<Result<R, MonitorError>, JoinError>)> {
+    let deadline = tokio::time::Instant::now() + cycle_timeout;
+    let mut finished = Vec::new();
+    while let Ok(Some(joined)) = tokio::time::timeout_at(deadline, tasks.join_next_with_id()).await
+    {
+        let (id, outcome) = match joined {
+            Ok((id, outcome)) => (id, Ok(outcome)),
+            Err(e) => (e.id(), Err(e)),
+        };
+        if let Some((index, instance)) = pending.remove(&id) {
+            finished.push((index, instance, outcome));
+        }
+    }
+
+    for (index, instance) in pending.into_values() {
+        let timeout = MonitorError::CycleTimeout {
+            secs: cycle_timeout.as_secs(),
+        };
+        finished.push((index, instance, Ok(Err(timeout))));
+    }
+    finished.sort_by_key(|(index, _, _)| *index);
+    finished
+        .into_iter()
+        .map(|(_, instance, outcome)| (instance, outcome))
+        .collect()
+}
+
+/// Latest `(minutes, step)` samples of the current job, ending with the current
+/// observation, with the minutes counted from the first sample
+fn regression_samples(
+    history: &[history::HistorySample],
+    current: (usize, chrono::DateTime<chrono::Utc>),
+    count: usize,
+) -> Vec<(f64, usize)> {
+    // Samples from before a restart of the job do not belong to the fit
+    let mut observations: Vec<_> = history
+        .iter()
+        .map(|sample| (sample.step, sample.timestamp))
+        .chain(std::iter::once(current))
+        .collect();
+    if let Some(restart) = observations
+        .windows(2)
+        .rposition(|pair| pair[1].0 < pair[0].0)
+    {
+        observations.drain(..=restart);
+    }
+    let recent = &observations[observations.len().saturating_sub(count)..];
+    let start = recent[0].1;
+    recent
+        .iter()
+        .map(|(step, at)| ((*at - start).num_milliseconds() as f64 / 60_000.0, *step))
+        .collect()
+}
+
+/// Build the results of an instance that could not be processed, flagging
+/// instances still within their startup grace period
+fn failed_result(
+    instance: &InstanceInfo,
+    error_message: String,
+    config: &MonitorConfig,
+) -> InstanceResults {
+    let age = instance
+        .launch_time
+        .and_then(|launch_time| (chrono::Utc::now() - launch_time).to_std().ok());
+    InstanceResults {
+        instance_id: instance.instance_id.clone(),
+        public_ip: instance.public_ip.clone(),
+        name: instance.name.clone(),
+        instance_type: instance.instance_type.clone(),
+        tags: instance.tags.clone(),
+        connection_error: Some(error_message),
+        starting_up: report::is_starting_up(age, config.startup_grace),
+        ..Default::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn aws_config_carries_the_timeouts_and_attempts() {
+        let options = AwsOptions {
+            connect_timeout: std::time::Duration::from_secs(7),
+            read_timeout: std::time::Duration::from_secs(42),
+            max_attempts: 5,
+            ..AwsOptions::default()
+        };
+        let config = init_aws_config(&options, Some("us-east-1".to_string())).await;
+
+        let timeouts = config.timeout_config().unwrap();
+        assert_eq!(timeouts.connect_timeout(), Some(options.connect_timeout));
+        assert_eq!(timeouts.read_timeout(), Some(options.read_timeout));
+        assert_eq!(config.retry_config().unwrap().max_attempts(), 5);
+        assert_eq!(config.region().map(|r| r.as_ref()), Some("us-east-1"));
+    }
+
+    #[tokio::test]
+    async fn sequential_probes_run_one_at_a_time_in_name_order() {
+        let instances = ["node-3", "node-1", "node-2"]
+            .map(|name| InstanceInfo {
+                name: name.to_string(),
+                ..InstanceInfo::default()
+            })
+            .to_vec();
+        let started = std::sync::Mutex::new(Vec::new());
+        let running = std::sync::atomic::AtomicUsize::new(0);
+
+        let probed = probe_sequentially(instances, |instance| {
+            let (started, running) = (&started, &running);
+            async move {
+                use std::sync::atomic::Ordering::SeqCst;
+                assert_eq!(running.fetch_add(1, SeqCst), 0, "probes overlap");
+                started.lock().unwrap().push(instance.name.clone());
+                tokio::task::yield_now().await;
+                running.fetch_sub(1, SeqCst);
+                instance.name.len()
+            }
+        })
+        .await;
+
+        assert_eq!(*started.lock().unwrap(), ["node-1", "node-2", "node-3"]);
+        let names: Vec<_> = probed
+            .iter()
+            .map(|(instance, _)| instance.name.as_str())
+            .collect();
+        assert_eq!(names, ["node-1", "node-2", "node-3"]);
+    }
+
+    #[tokio::test]
+    async fn fast_results_are_collected_despite_a_slow_task() {
+        let mut tasks = JoinSet::new();
+        let mut pending = HashMap::new();
+        for (index, (name, millis)) in [("slow", 60_000), ("fast-1", 0), ("fast-2", 10)]
+            .into_iter()
+            .enumerate()
+        {
+            let task = tasks.spawn(async move {
+                tokio::time::sleep(Duration::from_millis(millis)).await;
+                Ok::<_, MonitorError>(name)
+            });
+            let instance = InstanceInfo {
+                name: name.to_string(),
+                ..InstanceInfo::default()
+            };
+            pending.insert(task.id(), (index, instance));
+        }
+
+        let start = Instant::now();
+        let outcomes = collect_until_deadline(tasks, pending, Duration::from_millis(200)).await;
+        assert!(start.elapsed() < Duration::from_secs(5));
+
+        let outcomes: Vec<_> = outcomes
+            .into_iter()
+            .map(|(instance, outcome)| (instance.name, outcome.unwrap()))
+            .collect();
+        assert_eq!(outcomes[0].0, "slow");
+        assert!(matches!(
+            outcomes[0].1,
+            Err(MonitorError::CycleTimeout { secs: 0 })
+        ));
+        assert_eq!(outcomes[1].0, "fast-1");
+        assert_eq!(outcomes[1].1.as_ref().unwrap(), &"fast-1");
+        assert_eq!(outcomes[2].1.as_ref().unwrap(), &"fast-2");
+    }
+
+    #[test]
+    fn each_route_failure_has_its_own_label() {
+        let refused = || std::io::Error::from(std::io::ErrorKind::ConnectionRefused);
+        let direct = MonitorError::DirectConnect {
+            target: "10.0.1.5:22".to_string(),
+            source: refused(),
+        };
+        let proxy = MonitorError::ProxyConnect {
+            proxy: "bastion:1080".to_string(),
+            target: "10.0.1.5:22".to_string(),
+            source: refused(),
+        };
+        assert_eq!(error_label(&direct), "Connection failed");
+        assert_eq!(error_label(&proxy), "Proxy failed");
+        assert_eq!(
+            error_label(&MonitorError::AfterAttempts {
+                attempts: 3,
+                source: Box::new(proxy),
+            }),
+            "Proxy failed"
+        );
+        assert_eq!(
+            error_label(&MonitorError::AuthenticationFailed),
+            "Processing error: SSH authentication failed"
+        );
+    }
+}
@@ -0,0 +1,173 @@
+//! Status logs of the monitor on stderr, leaving stdout to the reports
+
+use std::io::{self, Write};
+use std::sync::Mutex;
+use tracing::Subscriber;
+use tracing_subscriber::EnvFilter;
+use tracing_subscriber::fmt::MakeWriter;
+use tracing_subscriber::util::SubscriberInitExt;
+
+/// Filter of the logs when `RUST_LOG` is unset: the monitor at info level, or
+/// debug and trace with `-v` and `-vv`, and only the warnings of the dependencies
+pub fn default_filter(verbosity: u8) -> String {
+    let level = match verbosity {
+        0 => "info",
+        1 => "debug",
+        _ => "trace",
+    };
+    format!("warn,ec2_monitor={level}")
+}
+
+/// Subscriber writing the logs filtered by `filter` to `writer`
+pub fn subscriber<W>(filter: EnvFilter, writer: W) -> impl Subscriber + Send + Sync
+where
+    W: for<'w> MakeWriter<'w> + Send + Sync + 'static,
+{
+    tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(writer)
+        .with_target(false)
+        .finish()
+}
+
+/// Install the subscriber writing the logs to stderr, filtered by `RUST_LOG`,
+/// e.g. `RUST_LOG=warn` in cron, else by the verbosity
+pub fn init(verbosity: u8) {
+    let filter = EnvFilter::try_from_default_env()
+        .unwrap_or_else(|_| EnvFilter::new(default_filter(verbosity)));
+    subscriber(filter, || StderrOrCaptured).init();
+}
+
+/// Last log line while the logs are captured, `None` while they go to stderr
+static CAPTURED: Mutex<Option<String>> = Mutex::new(None);
+
+/// Writer of the logs to stderr, or to the last captured line while the
+/// interactive mode draws the screen
+struct StderrOrCaptured;
+
+impl Write for StderrOrCaptured {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut captured = CAPTURED.lock().unwrap_or_else(|e| e.into_inner());
+        match captured.as_mut() {
+            Some(last) => {
+                let text = String::from_utf8_lossy(buf);
+                if let Some(line) = text.lines().rfind(|line| !line.trim().is_empty()) {
+                    *last = strip_ansi(line);
+                }
+                Ok(buf.len())
+            }
+            None => io::stderr().write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        io::stderr().flush()
+    }
+}
+
+/// Keeps the logs off stderr until dropped, so that they do not overwrite the
+/// screen of the interactive mode
+pub struct CaptureGuard;
+
+impl Drop for CaptureGuard {
+    fn drop(&mut self) {
+        *CAPTURED.lock().unwrap_or_else(|e| e.into_inner()) = None;
+    }
+}
+
+/// Capture the logs instead of writing them to stderr, keeping the last line
+pub fn capture() -> CaptureGuard {
+    *CAPTURED.lock().unwrap_or_else(|e| e.into_inner()) = Some(String::new());
+    CaptureGuard
+}
+
+/// Last line logged since the capture started, if any
+pub fn last_captured() -> Option<String> {
+    CAPTURED
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .clone()
+        .filter(|line| !line.is_empty())
+}
+
+/// Remove the color escape sequences of a log line
+fn strip_ansi(line: &str) -> String {
+    let mut stripped = String::with_capacity(line.len());
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            // Skip up to the letter ending the sequence, e.g. `m`
+            chars.by_ref().find(|c| c.is_ascii_alphabetic());
+        } else {
+            stripped.push(c);
+        }
+    }
+    stripped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    /// Writer collecting the logs in memory
+    #[derive(Clone, Default)]
+    struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Buffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl<'a> MakeWriter<'a> for Buffer {
+        type Writer = Buffer;
+
+        fn make_writer(&'a self) -> Self::Writer {
+            self.clone()
+        }
+    }
+
+    /// Logs of a cycle processing one instance at the verbosity
+    fn cycle_logs(verbosity: u8) -> String {
+        let buffer = Buffer::default();
+        let filter = EnvFilter::new(default_filter(verbosity));
+        tracing::subscriber::with_default(subscriber(filter, buffer.clone()), || {
+            tracing::info!(target: "ec2_monitor", "Processing all instances in parallel...");
+            tracing::debug!(target: "ec2_monitor", "Processing instance");
+        });
+        String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap()
+    }
+
+    #[test]
+    fn processing_lines_are_emitted_only_when_verbose() {
+        let logs = cycle_logs(0);
+        assert!(logs.contains("Processing all instances"), "{logs}");
+        assert!(!logs.contains("Processing instance"), "{logs}");
+
+        let logs = cycle_logs(1);
+        assert!(logs.contains("Processing instance"), "{logs}");
+    }
+
+    #[test]
+    fn captured_logs_keep_their_last_line() {
+        let guard = capture();
+        assert_eq!(last_captured(), None);
+        StderrOrCaptured
+            .write_all(b"\x1b[32m INFO\x1b[0m Processing all instances in parallel...\n")
+            .unwrap();
+        StderrOrCaptured
+            .write_all(b"\x1b[33m WARN\x1b[0m Failed to write ETA logs\n")
+            .unwrap();
+        assert_eq!(
+            last_captured().as_deref(),
+            Some(" WARN Failed to write ETA logs")
+        );
+        drop(guard);
+        assert_eq!(last_captured(), None);
+    }
+}
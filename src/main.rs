@@ -1,30 +1,171 @@
-use ec2_monitor::{init_aws_config, create_ec2_client, monitor_cycle, MonitorError, TimeStep};
-use std::collections::HashMap;
+use ec2_monitor::aws::RegionClient;
+use ec2_monitor::cli::{Cli, Command};
+use ec2_monitor::notify::NoopNotifier;
+use ec2_monitor::{
+    MonitorConfig, MonitorError, MonitorState, Notifier, aws, eta, exec_on_fleet, init_aws_configs,
+    known_hosts, logging, monitor_cycle, persist, preflight, report, schedule, server, tui,
+};
 use tokio::signal;
-use tokio::time::{Duration, sleep};
+use tokio::time::{Instant, sleep, sleep_until};
+use tracing::{error, info, warn};
 
 #[tokio::main]
 async fn main() -> Result<(), MonitorError> {
+    let cli = Cli::parse_args();
+    // Start logging before reading the environment, whose invalid values are logged
+    logging::init(cli.global.verbose);
+    let (command, mut monitor_config) = cli.into_command();
+    if let Command::Version = command {
+        println!("{} {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
+        return Ok(());
+    }
+    if let Command::CampaignReport { state_file } = &command {
+        let Some((_, persisted)) = persist::load(state_file, monitor_config.state_keep) else {
+            return Err(MonitorError::StateFileNotFound {
+                path: state_file.display().to_string(),
+            });
+        };
+        print!("{}", report::campaign_summary(&persisted.history));
+        return Ok(());
+    }
+
+    if monitor_config.verify_host_keys && known_hosts::known_hosts_path(&monitor_config).is_none() {
+        warn!(
+            "Host key verification disabled: HOME is not set, use --known-hosts or MONITOR_KNOWN_HOSTS"
+        );
+        monitor_config.verify_host_keys = false;
+    }
+
     // Initialize AWS configuration
-    let config = init_aws_config().await;
-    let client = create_ec2_client(&config);
-    let mut previous_timesteps: HashMap<String, TimeStep> = HashMap::new();
-    let mut instance_etas: HashMap<String, Vec<f64>> = HashMap::new(); // Track ETAs per instance
+    let configs = init_aws_configs(&monitor_config.aws).await;
+    let clients: Vec<RegionClient> = configs.iter().map(RegionClient::new).collect();
+    let config = &configs[0];
+    monitor_config.aws.regions = clients.iter().map(|client| client.region.clone()).collect();
+
+    // Check the prerequisites of every region before monitoring, the SSH key only when connecting
+    preflight::preflight(&configs, &monitor_config, !matches!(command, Command::List)).await?;
 
-    println!("🚀 Starting EC2 Monitor - Refreshing every 6 minutes");
-    println!("Press Ctrl+C to stop monitoring\n");
+    // Name the account in the report header, telling apart reports of several accounts
+    if !matches!(command, Command::List | Command::Exec { .. }) {
+        monitor_config.account = aws::caller_account(config).await;
+    }
+
+    let mut state = MonitorState::default(); // Track timesteps, ETAs and results per instance
+    let notifiers: Vec<Box<dyn Notifier>> = vec![Box::new(NoopNotifier)];
+    if let Some(path) = &monitor_config.state_file
+        && let Some((loaded, persisted)) = persist::load(path, monitor_config.state_keep)
+    {
+        info!("Restored the ETA state from {}", loaded.display());
+        persisted.restore(&mut state);
+    }
 
-    // Continuous monitoring loop
+    match command {
+        Command::List => {
+            let instances = aws::discover_instances(&clients, &monitor_config).await?;
+            print!("{}", report::format_discovery(&instances));
+            Ok(())
+        }
+        Command::Exec { command } => {
+            exec_on_fleet(&clients, &monitor_config, &command.join(" ")).await
+        }
+        Command::Once(_) => monitor_cycle(&clients, &monitor_config, &mut state, &notifiers).await,
+        Command::Diff(_) => {
+            // Record a baseline quietly, then report what changed one interval later
+            let baseline = MonitorConfig {
+                quiet: true,
+                ..monitor_config.clone()
+            };
+            monitor_cycle(&clients, &baseline, &mut state, &notifiers).await?;
+            info!(
+                "Comparing in {}...",
+                eta::format_minutes(monitor_config.refresh_interval.as_secs_f64() / 60.0)
+            );
+            sleep(monitor_config.refresh_interval).await;
+            let changes = MonitorConfig {
+                changes_only: true,
+                ..monitor_config
+            };
+            monitor_cycle(&clients, &changes, &mut state, &notifiers).await
+        }
+        Command::Run(_) => run(&clients, &monitor_config, &mut state, &notifiers).await,
+        Command::CampaignReport { .. } | Command::Version => {
+            unreachable!("handled before AWS initialization")
+        }
+    }
+}
+
+/// Monitor the fleet continuously until interrupted
+async fn run(
+    clients: &[RegionClient],
+    monitor_config: &MonitorConfig,
+    state: &mut MonitorState,
+    notifiers: &[Box<dyn Notifier>],
+) -> Result<(), MonitorError> {
+    if let Some(addr) = monitor_config.summary_addr {
+        let summary = state.summary.clone();
+        tokio::spawn(async move {
+            if let Err(e) = server::serve_summary(addr, summary).await {
+                error!("Summary endpoint stopped: {e}");
+            }
+        });
+    }
+
+    if monitor_config.interactive {
+        return tui::run(clients, monitor_config, state, notifiers).await;
+    }
+
+    info!(
+        "Starting EC2 Monitor - Refreshing every {}",
+        eta::format_minutes(monitor_config.refresh_interval.as_secs_f64() / 60.0)
+    );
+    info!("Press Ctrl+C to stop monitoring");
+    let plain = report::ReportOptions::from_config(monitor_config).plain;
+
+    // Continuous monitoring loop, starting a cycle every refresh interval
     loop {
+        let cycle_start = Instant::now();
         tokio::select! {
             _ = signal::ctrl_c() => {
-                println!("\n👋 Monitoring stopped by user");
+                info!("Monitoring stopped by user");
                 break;
             }
-            _ = monitor_cycle(&client, &mut previous_timesteps, &mut instance_etas) => {
-                // Sleep for 6 minutes before next cycle
-                println!("\n⏰ Next update in 6 minutes...");
-                sleep(Duration::from_secs(360)).await;
+            _ = monitor_cycle(clients, monitor_config, state, notifiers) => {
+                // Warn once the cycles consistently overrun the interval, the
+                // ETAs then drifting from the nominal cadence
+                let cycle_duration = cycle_start.elapsed();
+                state.overrun_cycles = schedule::overrun_cycles(
+                    state.overrun_cycles,
+                    monitor_config.refresh_interval,
+                    cycle_duration,
+                );
+                if state.overrun_cycles > 0 {
+                    state.total_overruns += 1;
+                }
+                if state.overrun_cycles == schedule::OVERRUN_WARN_CYCLES {
+                    warn!(
+                        "Falling behind: the last {} cycles took longer than the {} interval (latest {}, {} overruns so far), consider a longer --interval",
+                        state.overrun_cycles,
+                        eta::format_minutes(monitor_config.refresh_interval.as_secs_f64() / 60.0),
+                        eta::format_minutes(cycle_duration.as_secs_f64() / 60.0),
+                        state.total_overruns
+                    );
+                }
+
+                // Sleep for the rest of the interval before next cycle, drawing the
+                // latest report once the render interval allows it
+                let wait = schedule::sleep_duration(monitor_config.refresh_interval, cycle_duration);
+                info!("Next update in {}...", eta::format_minutes(wait.as_secs_f64() / 60.0));
+                let next_cycle = Instant::now() + wait;
+                while let Some(due) = state.render.due_at(monitor_config.render_interval)
+                    && Instant::from_std(due) < next_cycle
+                {
+                    sleep_until(Instant::from_std(due)).await;
+                    let now = std::time::Instant::now();
+                    if let Some(rendered) = state.render.take_due(now, monitor_config.render_interval) {
+                        report::redraw(&rendered, plain);
+                    }
+                }
+                sleep_until(next_cycle).await;
             }
         }
     }
@@ -0,0 +1,59 @@
+//! Memory usage of the solver
+
+/// Default fraction of the instance memory above which the solver is flagged
+pub const DEFAULT_MEMORY_THRESHOLD: f64 = 0.9;
+
+/// Kilobytes per gigabyte, as reported by `ps` and `/proc/meminfo`
+const KB_PER_GB: f64 = 1024.0 * 1024.0;
+
+/// Sum the resident set sizes in kilobytes of `ps -o rss=`, one process per line
+///
+/// Returns `None` when no line is a number, i.e. no solver process is running.
+pub fn parse_rss_kb(output: &str) -> Option<u64> {
+    output
+        .lines()
+        .filter_map(|line| line.trim().parse::<u64>().ok())
+        .reduce(|total, rss| total + rss)
+}
+
+/// Parse the total memory in kilobytes from the `MemTotal:` line of `/proc/meminfo`
+pub fn parse_mem_total_kb(meminfo: &str) -> Option<u64> {
+    meminfo
+        .lines()
+        .find_map(|line| line.strip_prefix("MemTotal:"))
+        .and_then(|value| value.split_whitespace().next())
+        .and_then(|kb| kb.parse().ok())
+}
+
+/// Convert kilobytes to gigabytes
+pub fn kb_to_gb(kb: u64) -> f64 {
+    kb as f64 / KB_PER_GB
+}
+
+/// Check whether the solver uses more than `threshold` of the instance memory
+pub fn is_memory_high(rss_kb: u64, total_kb: u64, threshold: f64) -> bool {
+    total_kb > 0 && rss_kb as f64 > total_kb as f64 * threshold
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rss_of_every_solver_process_is_summed() {
+        assert_eq!(parse_rss_kb(" 1048576\n 524288\n"), Some(1_572_864));
+        assert_eq!(parse_rss_kb(""), None);
+        assert_eq!(kb_to_gb(1_572_864), 1.5);
+        assert_eq!(
+            parse_mem_total_kb("MemTotal:       393216000 kB\nMemFree: 1 kB\n"),
+            Some(393_216_000)
+        );
+    }
+
+    #[test]
+    fn memory_is_high_above_the_threshold_fraction() {
+        assert!(is_memory_high(95, 100, DEFAULT_MEMORY_THRESHOLD));
+        assert!(!is_memory_high(90, 100, DEFAULT_MEMORY_THRESHOLD));
+        assert!(!is_memory_high(95, 0, DEFAULT_MEMORY_THRESHOLD));
+    }
+}
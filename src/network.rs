@@ -0,0 +1,125 @@
+//! Network throughput of the instances, telling a progressing s3 sync from a stuck one
+
+/// Seconds between the two snapshots of the interface counters
+pub const NET_SAMPLE_SECS: u64 = 2;
+
+/// Line separating the two snapshots of the probe output
+const SNAPSHOT_SEPARATOR: &str = "--";
+
+/// Bytes per megabyte of the reported throughput
+const BYTES_PER_MB: f64 = 1_000_000.0;
+
+/// Prefixes of the loopback and virtual interfaces left out of the throughput
+const VIRTUAL_INTERFACES: [&str; 5] = ["lo", "docker", "veth", "br-", "virbr"];
+
+/// Command printing two snapshots of `/proc/net/dev`, `NET_SAMPLE_SECS` apart
+pub fn net_dev_probe() -> String {
+    format!(
+        "cat /proc/net/dev && echo {SNAPSHOT_SEPARATOR} && sleep {NET_SAMPLE_SECS} && cat /proc/net/dev"
+    )
+}
+
+/// Sum the received and transmitted bytes of the primary interfaces in a
+/// `/proc/net/dev` snapshot
+///
+/// Returns `None` when the snapshot has no primary interface.
+pub fn primary_bytes(snapshot: &str) -> Option<u64> {
+    // Received bytes are the first counter, transmitted bytes the ninth
+    primary_counters(snapshot, &[0, 8])
+}
+
+/// Sum the transmitted bytes of the primary interfaces in a `/proc/net/dev`
+/// snapshot, `None` when it has no primary interface
+pub fn primary_tx_bytes(snapshot: &str) -> Option<u64> {
+    primary_counters(snapshot, &[8])
+}
+
+/// Sum the counters at the given columns of the primary interfaces
+fn primary_counters(snapshot: &str, columns: &[usize]) -> Option<u64> {
+    snapshot
+        .lines()
+        .filter_map(|line| line.split_once(':'))
+        .filter(|(name, _)| {
+            let name = name.trim();
+            !VIRTUAL_INTERFACES
+                .iter()
+                .any(|prefix| name.starts_with(prefix))
+        })
+        .filter_map(|(_, counters)| {
+            let counters: Vec<_> = counters.split_whitespace().collect();
+            columns
+                .iter()
+                .map(|&column| counters.get(column)?.parse::<u64>().ok())
+                .sum::<Option<u64>>()
+        })
+        .reduce(|total, bytes| total + bytes)
+}
+
+/// Throughput in MB/s between two byte counts `seconds` apart
+///
+/// Returns `None` for a non-positive interval or counters going backwards,
+/// e.g. after an interface reset.
+pub fn throughput_mb_per_sec(before: u64, after: u64, seconds: f64) -> Option<f64> {
+    (seconds > 0.0 && after >= before).then(|| (after - before) as f64 / BYTES_PER_MB / seconds)
+}
+
+/// Parse the throughput in MB/s from the output of `net_dev_probe`
+pub fn parse_throughput(output: &str) -> Option<f64> {
+    let (before, after) = output.split_once(&format!("\n{SNAPSHOT_SEPARATOR}\n"))?;
+    throughput_mb_per_sec(
+        primary_bytes(before)?,
+        primary_bytes(after)?,
+        NET_SAMPLE_SECS as f64,
+    )
+}
+
+/// Format a throughput as e.g. `12.3 MB/s`, `N/A` when unknown
+pub fn format_throughput(mb_per_sec: Option<f64>) -> String {
+    match mb_per_sec {
+        Some(rate) => format!("{rate:.1} MB/s"),
+        None => "N/A".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `/proc/net/dev` snapshot with the received and transmitted bytes of
+    /// the loopback, a docker bridge and two primary interfaces
+    fn snapshot(lo: u64, docker: u64, eth0: (u64, u64), ens5: (u64, u64)) -> String {
+        let line = |name: &str, (rx, tx): (u64, u64)| {
+            format!("{name:>6}: {rx} 100 0 0 0 0 0 0 {tx} 100 0 0 0 0 0 0\n")
+        };
+        format!(
+            "Inter-|   Receive                            |  Transmit\n \
+             face |bytes    packets errs drop fifo frame compressed multicast|bytes\n{}{}{}{}",
+            line("lo", (lo, lo)),
+            line("docker0", (docker, docker)),
+            line("eth0", eth0),
+            line("ens5", ens5),
+        )
+    }
+
+    #[test]
+    fn throughput_sums_the_primary_interfaces_between_snapshots() {
+        let before = snapshot(1_000, 5_000, (1_000_000, 2_000_000), (0, 500_000));
+        let after = snapshot(
+            9_000_000,
+            90_000_000,
+            (3_000_000, 10_000_000),
+            (0, 4_500_000),
+        );
+        assert_eq!(primary_bytes(&before), Some(3_500_000));
+        assert_eq!(primary_bytes(&after), Some(17_500_000));
+        assert_eq!(primary_tx_bytes(&after), Some(14_500_000));
+
+        let output = format!("{before}{SNAPSHOT_SEPARATOR}\n{after}");
+        assert_eq!(parse_throughput(&output), Some(7.0));
+        assert_eq!(format_throughput(parse_throughput(&output)), "7.0 MB/s");
+
+        assert_eq!(throughput_mb_per_sec(2_000_000, 1_000_000, 2.0), None);
+        assert_eq!(throughput_mb_per_sec(1_000_000, 2_000_000, 0.0), None);
+        assert_eq!(format_throughput(None), "N/A");
+    }
+}
@@ -0,0 +1,85 @@
+//! Per-instance notes loaded from a TOML file
+//!
+//! The notes file maps an instance id or name to a free-form note:
+//!
+//! ```toml
+//! "node-7" = "rerun of the failed case"
+//! "i-0123456789abcdef0" = "spot replacement"
+//! ```
+
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::Path;
+
+use crate::MonitorError;
+
+/// Notes keyed by instance id or instance name
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(transparent)]
+pub struct Notes(HashMap<String, String>);
+
+impl Notes {
+    /// Load notes from a TOML file, a missing file yields no notes
+    pub fn load(path: &Path) -> Result<Self, MonitorError> {
+        match fs::read_to_string(path) {
+            Ok(contents) => Self::parse(&contents),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Parse notes from TOML contents
+    pub fn parse(contents: &str) -> Result<Self, MonitorError> {
+        toml::from_str(contents).map_err(|e| MonitorError::Notes {
+            reason: e.to_string(),
+        })
+    }
+
+    /// Find the note of an instance, matching its id first and then its name
+    pub fn lookup(&self, instance_id: &str, name: &str) -> Option<&str> {
+        self.0
+            .get(instance_id)
+            .or_else(|| self.0.get(name))
+            .map(String::as_str)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOTES: &str = r#"
+"node-7" = "rerun of the failed case"
+"i-0123456789abcdef0" = "spot replacement"
+"#;
+
+    #[test]
+    fn notes_are_matched_by_id() {
+        let notes = Notes::parse(NOTES).unwrap();
+        assert_eq!(
+            notes.lookup("i-0123456789abcdef0", "node-1"),
+            Some("spot replacement")
+        );
+    }
+
+    #[test]
+    fn notes_are_matched_by_name() {
+        let notes = Notes::parse(NOTES).unwrap();
+        assert_eq!(
+            notes.lookup("i-0000000000000000", "node-7"),
+            Some("rerun of the failed case")
+        );
+        assert_eq!(notes.lookup("i-0000000000000000", "node-8"), None);
+    }
+
+    #[test]
+    fn the_id_takes_precedence_over_the_name() {
+        let notes = Notes::parse(NOTES).unwrap();
+        assert_eq!(
+            notes.lookup("i-0123456789abcdef0", "node-7"),
+            Some("spot replacement")
+        );
+    }
+}
@@ -0,0 +1,439 @@
+//! Monitoring events and pluggable notifiers
+
+use async_trait::async_trait;
+use chrono::{DateTime, SecondsFormat, Utc};
+use std::collections::HashMap;
+use std::time::Duration;
+
+use crate::{InstanceResults, disk, eta};
+
+/// Noteworthy changes detected between two monitoring cycles
+#[derive(Debug, Clone, PartialEq)]
+pub enum MonitorEvent {
+    /// An instance's simulation reached its total number of steps
+    JobComplete { name: String },
+    /// An instance's simulation stopped progressing
+    JobStalled { name: String, status: String },
+    /// An instance is running out of disk space
+    DiskCritical {
+        name: String,
+        free_disk_space: String,
+    },
+    /// An instance's s3 sync has been running for longer than the stuck sync limit
+    SyncStuck { name: String, minutes: i64 },
+    /// An instance's steps advanced without its CSV count growing for the
+    /// CSV stall cycles, i.e. its output pipeline is broken
+    CsvStalled { name: String, cycles: usize },
+    /// An incomplete instance is no longer discovered, e.g. after a crash or
+    /// an unexpected termination
+    InstanceVanished { name: String },
+    /// Every instance of the fleet is complete
+    CampaignComplete,
+}
+
+/// Alert backend receiving monitoring events
+#[async_trait]
+pub trait Notifier: Send + Sync {
+    async fn notify(&self, event: &MonitorEvent);
+}
+
+/// Notifier discarding every event
+#[derive(Debug, Default, Clone, Copy)]
+pub struct NoopNotifier;
+
+#[async_trait]
+impl Notifier for NoopNotifier {
+    async fn notify(&self, _event: &MonitorEvent) {}
+}
+
+/// Send every event to every notifier
+pub async fn dispatch(notifiers: &[Box<dyn Notifier>], events: &[MonitorEvent]) {
+    for event in events {
+        for notifier in notifiers {
+            notifier.notify(event).await;
+        }
+    }
+}
+
+/// Detect the events of a cycle by comparing its results with the previous cycle
+///
+/// Instance events only fire on a transition from a previously seen state, and
+/// `CampaignComplete` only fires if the campaign was not already complete.
+pub fn detect_events(
+    previous_results: &HashMap<String, InstanceResults>,
+    results: &[InstanceResults],
+    campaign_was_complete: bool,
+    now: DateTime<Utc>,
+) -> Vec<MonitorEvent> {
+    let mut events = Vec::new();
+
+    for result in results {
+        let Some(previous) = previous_results.get(&result.name) else {
+            continue;
+        };
+
+        if is_complete(result) && !is_complete(previous) {
+            events.push(MonitorEvent::JobComplete {
+                name: result.name.clone(),
+            });
+        }
+
+        if let Some(status) = stall_status(result)
+            && stall_status(previous).is_none()
+        {
+            events.push(MonitorEvent::JobStalled {
+                name: result.name.clone(),
+                status: status.to_string(),
+            });
+        }
+
+        if result.sync_stuck
+            && !previous.sync_stuck
+            && let Some(since) = result.process_since
+        {
+            events.push(MonitorEvent::SyncStuck {
+                name: result.name.clone(),
+                minutes: (now - since).num_minutes(),
+            });
+        }
+
+        if result.csv_stalled && !previous.csv_stalled {
+            events.push(MonitorEvent::CsvStalled {
+                name: result.name.clone(),
+                cycles: result.csv_flat_cycles,
+            });
+        }
+
+        if let Some(free_disk_space) = critical_disk(result)
+            && critical_disk(previous).is_none()
+        {
+            events.push(MonitorEvent::DiskCritical {
+                name: result.name.clone(),
+                free_disk_space: free_disk_space.to_string(),
+            });
+        }
+    }
+
+    if is_campaign_complete(results) && !campaign_was_complete {
+        events.push(MonitorEvent::CampaignComplete);
+    }
+
+    events
+}
+
+/// Process label of an instance running `aws s3 sync`
+pub const S3_SYNC: &str = "s3 sync";
+
+/// Time the current process of an instance was first observed: carried over
+/// from the previous cycle while the process is unchanged, `now` otherwise
+pub fn process_since(
+    previous: Option<&InstanceResults>,
+    current_process: Option<&str>,
+    now: DateTime<Utc>,
+) -> Option<DateTime<Utc>> {
+    let current_process = current_process?;
+    match previous {
+        Some(previous) if previous.current_process.as_deref() == Some(current_process) => {
+            previous.process_since.or(Some(now))
+        }
+        _ => Some(now),
+    }
+}
+
+/// Check whether an instance has been running the s3 sync for at least `limit`
+pub fn is_sync_stuck(result: &InstanceResults, now: DateTime<Utc>, limit: Duration) -> bool {
+    result.current_process.as_deref() == Some(S3_SYNC)
+        && result
+            .process_since
+            .and_then(|since| (now - since).to_std().ok())
+            .is_some_and(|running| running >= limit)
+}
+
+/// Consecutive cycles the steps of an instance advanced without its CSV count
+/// growing, counting on from the previous cycle
+pub fn csv_flat_cycles(previous: Option<&InstanceResults>, result: &InstanceResults) -> usize {
+    let Some(previous) = previous else {
+        return 0;
+    };
+    let advanced = result
+        .timestep_result
+        .as_ref()
+        .and_then(|ts| ts.step_increase)
+        .is_some_and(|increase| increase > 0);
+    let flat = result.csv_count.is_some() && result.csv_count <= previous.csv_count;
+    if advanced && flat {
+        previous.csv_flat_cycles + 1
+    } else {
+        0
+    }
+}
+
+/// Names of the instances of the previous cycle missing from the current one
+/// without having completed, sorted by name
+pub fn vanished_instances<'a>(
+    previous_results: &'a HashMap<String, InstanceResults>,
+    results: &[InstanceResults],
+) -> Vec<&'a str> {
+    let mut vanished: Vec<_> = previous_results
+        .values()
+        .filter(|previous| !is_complete(previous))
+        .filter(|previous| !results.iter().any(|r| r.name == previous.name))
+        .map(|previous| previous.name.as_str())
+        .collect();
+    vanished.sort();
+    vanished
+}
+
+/// Keyword of the line printed once when the whole campaign is complete
+pub const CAMPAIGN_COMPLETE_SENTINEL: &str = "CAMPAIGN_COMPLETE";
+
+/// Machine-parseable `CAMPAIGN_COMPLETE <timestamp>` line for a cycle, `None`
+/// unless the campaign is complete and the line was not printed yet
+pub fn campaign_sentinel(
+    results: &[InstanceResults],
+    already_printed: bool,
+    now: DateTime<Utc>,
+) -> Option<String> {
+    (!already_printed && is_campaign_complete(results)).then(|| {
+        format!(
+            "{CAMPAIGN_COMPLETE_SENTINEL} {}",
+            now.to_rfc3339_opts(SecondsFormat::Secs, true)
+        )
+    })
+}
+
+/// Check whether every instance of a non-empty fleet is complete
+pub fn is_campaign_complete(results: &[InstanceResults]) -> bool {
+    !results.is_empty() && results.iter().all(is_complete)
+}
+
+fn is_complete(result: &InstanceResults) -> bool {
+    result
+        .timestep_result
+        .as_ref()
+        .is_some_and(|ts| ts.is_complete())
+}
+
+fn stall_status(result: &InstanceResults) -> Option<&str> {
+    result
+        .eta
+        .as_deref()
+        .filter(|status| status.starts_with(eta::STALLED))
+}
+
+fn critical_disk(result: &InstanceResults) -> Option<&str> {
+    result
+        .free_disk_space
+        .as_deref()
+        .filter(|space| disk::is_disk_full(space))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::TimeStep;
+    use chrono::TimeZone;
+    use std::sync::{Arc, Mutex};
+
+    /// Notifier keeping every event it receives
+    struct RecordingNotifier {
+        events: Arc<Mutex<Vec<MonitorEvent>>>,
+    }
+
+    #[async_trait]
+    impl Notifier for RecordingNotifier {
+        async fn notify(&self, event: &MonitorEvent) {
+            self.events.lock().unwrap().push(event.clone());
+        }
+    }
+
+    fn result(name: &str, step: usize, eta: &str) -> InstanceResults {
+        InstanceResults {
+            name: name.to_string(),
+            timestep_result: Some(TimeStep {
+                step,
+                total_step: 1000,
+                ..TimeStep::default()
+            }),
+            eta: Some(eta.to_string()),
+            ..InstanceResults::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn notifiers_receive_the_transitions_of_the_cycle() {
+        let previous_results = HashMap::from([
+            ("node-1".to_string(), result("node-1", 900, "10m")),
+            ("node-2".to_string(), result("node-2", 500, "1h 0m")),
+            ("node-3".to_string(), result("node-3", 1000, eta::COMPLETE)),
+        ]);
+        let results = [
+            result("node-1", 1000, eta::COMPLETE),
+            result("node-2", 500, eta::STALLED),
+            result("node-3", 1000, eta::COMPLETE),
+        ];
+        let events = detect_events(&previous_results, &results, false, Utc::now());
+
+        let recorded = Arc::new(Mutex::new(Vec::new()));
+        let notifiers: Vec<Box<dyn Notifier>> = vec![
+            Box::new(RecordingNotifier {
+                events: recorded.clone(),
+            }),
+            Box::new(NoopNotifier),
+        ];
+        dispatch(&notifiers, &events).await;
+
+        assert_eq!(
+            *recorded.lock().unwrap(),
+            [
+                MonitorEvent::JobComplete {
+                    name: "node-1".to_string()
+                },
+                MonitorEvent::JobStalled {
+                    name: "node-2".to_string(),
+                    status: eta::STALLED.to_string()
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn campaign_completion_fires_once() {
+        let results = [result("node-1", 1000, eta::COMPLETE)];
+        assert_eq!(
+            detect_events(&HashMap::new(), &results, false, Utc::now()),
+            [MonitorEvent::CampaignComplete]
+        );
+        assert!(detect_events(&HashMap::new(), &results, true, Utc::now()).is_empty());
+    }
+
+    #[test]
+    fn sentinel_prints_once_across_complete_cycles() {
+        let running = [result("node-1", 900, "10m")];
+        let complete = [result("node-1", 1000, eta::COMPLETE)];
+        let now = Utc.with_ymd_and_hms(2024, 5, 1, 12, 0, 0).unwrap();
+
+        let mut printed = false;
+        let mut lines = Vec::new();
+        for results in [&running[..], &complete, &complete, &complete] {
+            if let Some(line) = campaign_sentinel(results, printed, now) {
+                lines.push(line);
+                printed = true;
+            }
+        }
+        assert_eq!(lines, ["CAMPAIGN_COMPLETE 2024-05-01T12:00:00Z"]);
+    }
+
+    #[test]
+    fn incomplete_instances_missing_from_the_cycle_vanished() {
+        let previous_results = HashMap::from([
+            ("node-1".to_string(), result("node-1", 900, "10m")),
+            ("node-2".to_string(), result("node-2", 1000, eta::COMPLETE)),
+            ("node-3".to_string(), result("node-3", 100, "5h 0m")),
+            ("node-4".to_string(), result("node-4", 500, "1h 0m")),
+        ]);
+        let results = [result("node-4", 600, "50m")];
+        assert_eq!(
+            vanished_instances(&previous_results, &results),
+            ["node-1", "node-3"]
+        );
+        assert!(vanished_instances(&HashMap::new(), &results).is_empty());
+    }
+
+    #[test]
+    fn sync_is_stuck_once_running_past_the_limit_across_cycles() {
+        let start = Utc.with_ymd_and_hms(2024, 5, 1, 12, 0, 0).unwrap();
+        let limit = Duration::from_secs(2 * 3600);
+        let mut previous: Option<InstanceResults> = None;
+        let mut stuck = Vec::new();
+        for (hour, process) in [
+            (0, S3_SYNC),
+            (1, S3_SYNC),
+            (2, S3_SYNC),
+            (3, "zcsvs"),
+            (4, S3_SYNC),
+        ] {
+            let now = start + chrono::Duration::hours(hour);
+            let result = InstanceResults {
+                current_process: Some(process.to_string()),
+                process_since: process_since(previous.as_ref(), Some(process), now),
+                ..result("node-1", 1000, eta::COMPLETE)
+            };
+            stuck.push(is_sync_stuck(&result, now, limit));
+            previous = Some(result);
+        }
+        assert_eq!(stuck, [false, false, true, false, false]);
+        assert_eq!(
+            previous.unwrap().process_since,
+            Some(start + chrono::Duration::hours(4))
+        );
+    }
+
+    #[test]
+    fn flat_csv_count_is_counted_only_while_steps_advance() {
+        let mut previous: Option<InstanceResults> = None;
+        let mut flat = Vec::new();
+        for (increase, csv_count) in [
+            (None, 10),
+            (Some(50), 12),
+            (Some(50), 12),
+            (Some(50), 12),
+            (Some(0), 12),
+            (Some(50), 12),
+            (Some(50), 13),
+        ] {
+            let mut result = InstanceResults {
+                timestep_result: Some(TimeStep {
+                    step_increase: increase,
+                    ..TimeStep::default()
+                }),
+                csv_count: Some(csv_count),
+                ..InstanceResults::default()
+            };
+            result.csv_flat_cycles = csv_flat_cycles(previous.as_ref(), &result);
+            flat.push(result.csv_flat_cycles);
+            previous = Some(result);
+        }
+        assert_eq!(flat, [0, 0, 1, 2, 0, 1, 0]);
+    }
+
+    #[test]
+    fn a_job_finishing_then_idle_only_completes() {
+        let cycles = [
+            (900, Some(100)),
+            (1000, Some(100)),
+            (1000, Some(0)),
+            (1000, Some(0)),
+        ];
+        let mut previous_results = HashMap::new();
+        let mut events = Vec::new();
+        for (step, step_increase) in cycles {
+            let ts = TimeStep {
+                step,
+                total_step: 1000,
+                step_increase,
+                interval_minutes: Some(6.0),
+                ..TimeStep::default()
+            };
+            let free_disk_space = Some("512M".to_string());
+            let result = InstanceResults {
+                name: "node-1".to_string(),
+                eta: ts
+                    .calculate_eta()
+                    .map(|eta| eta::with_stall_cause(eta, free_disk_space.as_deref())),
+                timestep_result: Some(ts),
+                free_disk_space,
+                ..InstanceResults::default()
+            };
+            let results = [result];
+            events.extend(detect_events(&previous_results, &results, true, Utc::now()));
+            previous_results = HashMap::from([("node-1".to_string(), results[0].clone())]);
+        }
+        assert_eq!(
+            events,
+            [MonitorEvent::JobComplete {
+                name: "node-1".to_string()
+            }]
+        );
+    }
+}
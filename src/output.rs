@@ -0,0 +1,202 @@
+//! ETA of the output-bound phases, from the rate of the bytes written by a
+//! finalize or uploaded by an s3 sync
+
+use chrono::{DateTime, Utc};
+
+use crate::InstanceResults;
+use crate::notify::S3_SYNC;
+use crate::{network, solver};
+
+/// Process label of an instance finalizing its output
+pub const FINALIZE: &str = "finalize";
+
+/// Bytes of output of an instance at a point in time, towards a target
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OutputSample {
+    pub at: DateTime<Utc>,
+    pub bytes: u64,
+    pub target: u64,
+}
+
+/// Command printing the bytes of the case directory, then a snapshot of
+/// `/proc/net/dev` for the bytes transmitted by an s3 sync
+pub fn output_probe(case: &str) -> String {
+    format!(
+        "du -sb {} | cut -f1 && cat /proc/net/dev",
+        solver::shell_quote(case)
+    )
+}
+
+/// Parse the bytes of the case directory and the bytes transmitted by the
+/// primary interfaces from the output of `output_probe`
+pub fn parse_output_probe(output: &str) -> (Option<u64>, Option<u64>) {
+    let (case_bytes, net_dev) = output.split_once('\n').unwrap_or((output, ""));
+    (
+        case_bytes.trim().parse().ok(),
+        network::primary_tx_bytes(net_dev),
+    )
+}
+
+/// Minutes left until `after` reaches its target at the byte rate since
+/// `before`, 0 once reached, or `None` without a growing byte count
+pub fn eta_minutes(before: &OutputSample, after: &OutputSample) -> Option<f64> {
+    let remaining = after.target.saturating_sub(after.bytes);
+    if remaining == 0 {
+        return Some(0.0);
+    }
+    let minutes = (after.at - before.at).num_milliseconds() as f64 / 60_000.0;
+    if minutes <= 0.0 || after.bytes <= before.bytes {
+        return None;
+    }
+    let bytes_per_minute = (after.bytes - before.bytes) as f64 / minutes;
+    Some(remaining as f64 / bytes_per_minute)
+}
+
+/// Sample the output of an instance and estimate the end of its output-bound
+/// phase from the sample of the previous cycle
+///
+/// A finalize writes to the case directory towards `finalize_target` bytes;
+/// an s3 sync uploads the case directory, counted by the bytes transmitted
+/// since the sync was seen starting, i.e. with another process in the
+/// previous cycle. A sync already running when first seen has no baseline,
+/// hence no ETA. The target being the whole case directory, a sync skipping
+/// files already uploaded is estimated to take longer than it does.
+pub fn update(
+    result: &mut InstanceResults,
+    previous: Option<&InstanceResults>,
+    finalize_target: Option<u64>,
+    now: DateTime<Utc>,
+) {
+    let Some((case_bytes, tx_bytes)) = result
+        .raw_probe
+        .as_ref()
+        .and_then(|probe| probe.output.as_deref())
+        .map(parse_output_probe)
+    else {
+        return;
+    };
+    let process = result.current_process.as_deref();
+    let same_process = previous.filter(|p| p.current_process.as_deref() == process);
+
+    let sample = match process {
+        Some(FINALIZE) => case_bytes
+            .zip(finalize_target)
+            .map(|(bytes, target)| OutputSample {
+                at: now,
+                bytes,
+                target,
+            }),
+        Some(S3_SYNC) => {
+            result.sync_start_tx_bytes = match previous {
+                Some(previous) if same_process.is_some() => previous.sync_start_tx_bytes,
+                Some(_) => tx_bytes,
+                None => None,
+            };
+            tx_bytes
+                .zip(result.sync_start_tx_bytes)
+                .zip(case_bytes)
+                .map(|((tx, start), target)| OutputSample {
+                    at: now,
+                    bytes: tx.saturating_sub(start),
+                    target,
+                })
+        }
+        _ => None,
+    };
+    result.output_eta_minutes = same_process
+        .and_then(|p| p.output_sample.as_ref())
+        .zip(sample.as_ref())
+        .and_then(|(before, after)| eta_minutes(before, after));
+    result.output_sample = sample;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::RawProbe;
+    use chrono::TimeZone;
+
+    fn at(minute: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 5, 1, 12, minute, 0).unwrap()
+    }
+
+    #[test]
+    fn byte_rate_eta_reaches_the_target() {
+        let before = OutputSample {
+            at: at(0),
+            bytes: 1_000,
+            target: 10_000,
+        };
+        let after = OutputSample {
+            at: at(10),
+            bytes: 3_000,
+            ..before
+        };
+        // 200 bytes per minute, 7000 bytes to go
+        assert_eq!(eta_minutes(&before, &after), Some(35.0));
+        let done = OutputSample {
+            bytes: 10_000,
+            ..after
+        };
+        assert_eq!(eta_minutes(&before, &done), Some(0.0));
+        assert_eq!(eta_minutes(&after, &before), None);
+        assert_eq!(eta_minutes(&after, &after), None);
+    }
+
+    #[test]
+    fn case_directory_is_quoted() {
+        assert_eq!(
+            output_probe("it's a case"),
+            r"du -sb 'it'\''s a case' | cut -f1 && cat /proc/net/dev"
+        );
+    }
+
+    /// Result of an instance running `process`, with a case of 600 MB and
+    /// `tx_mb` transmitted
+    fn probed(process: &str, tx_mb: u64) -> InstanceResults {
+        let net_dev = format!(
+            "  eth0: 0 0 0 0 0 0 0 0 {} 0 0 0 0 0 0 0",
+            tx_mb * 1_000_000
+        );
+        InstanceResults {
+            current_process: Some(process.to_string()),
+            raw_probe: Some(RawProbe {
+                output: Some(format!("600000000\n{net_dev}")),
+                ..RawProbe::default()
+            }),
+            ..InstanceResults::default()
+        }
+    }
+
+    /// Output ETAs of the cycles of an instance running the processes, one minute apart
+    fn cycle_etas(cycles: &[(&str, u64)]) -> Vec<Option<f64>> {
+        let mut previous: Option<InstanceResults> = None;
+        let mut etas = Vec::new();
+        for (minute, &(process, tx_mb)) in cycles.iter().enumerate() {
+            let mut result = probed(process, tx_mb);
+            update(&mut result, previous.as_ref(), None, at(minute as u32));
+            etas.push(result.output_eta_minutes);
+            previous = Some(result);
+        }
+        etas
+    }
+
+    #[test]
+    fn sync_eta_is_estimated_only_from_a_sync_seen_starting() {
+        // 100 MB uploaded per minute out of the 600 MB of the case
+        assert_eq!(
+            cycle_etas(&[
+                ("zcsvs", 1000),
+                (S3_SYNC, 1000),
+                (S3_SYNC, 1100),
+                (S3_SYNC, 1200)
+            ]),
+            [None, None, Some(5.0), Some(4.0)]
+        );
+        // Without the start of the sync, the bytes already uploaded are unknown
+        assert_eq!(
+            cycle_etas(&[(S3_SYNC, 1000), (S3_SYNC, 1100), (S3_SYNC, 1200)]),
+            [None, None, None]
+        );
+    }
+}
@@ -0,0 +1,220 @@
+//! State file keeping the ETA samples across restarts of the monitor
+//!
+//! The state is rewritten every cycle. Once the file grows past the rotation
+//! size it is renamed to `<file>.1`, the previous `<file>.1` to `<file>.2` and
+//! so on, keeping the last files. States written to a `.gz` path are
+//! gzip-compressed, and the loader falls back to older files when the latest
+//! ones cannot be read.
+
+use chrono::{DateTime, Utc};
+use flate2::Compression;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use tracing::warn;
+
+use crate::history::HistorySample;
+use crate::{MonitorError, MonitorState};
+
+/// First bytes of a gzip stream
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// Part of the monitoring state worth keeping across restarts
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct PersistedState {
+    pub instance_etas: HashMap<String, Vec<f64>>,
+    pub observed_cycles: HashMap<String, usize>,
+    pub first_observed: HashMap<String, (usize, DateTime<Utc>)>,
+    pub sentinel_printed: bool,
+    /// Progress samples of every cycle, missing from the states of older versions
+    #[serde(default)]
+    pub history: HashMap<String, Vec<HistorySample>>,
+}
+
+impl PersistedState {
+    /// Copy the persisted part of the monitoring state
+    pub fn from_state(state: &MonitorState) -> Self {
+        Self {
+            instance_etas: state.instance_etas.clone(),
+            observed_cycles: state.observed_cycles.clone(),
+            first_observed: state.first_observed.clone(),
+            sentinel_printed: state.sentinel_printed,
+            history: state.history.clone(),
+        }
+    }
+
+    /// Restore the persisted part of the monitoring state
+    pub fn restore(self, state: &mut MonitorState) {
+        state.instance_etas = self.instance_etas;
+        state.observed_cycles = self.observed_cycles;
+        state.first_observed = self.first_observed;
+        state.sentinel_printed = self.sentinel_printed;
+        state.history = self.history;
+    }
+}
+
+/// Path of a state file generation, 0 being the current file
+pub fn rotated_path(path: &Path, generation: usize) -> PathBuf {
+    if generation == 0 {
+        return path.to_path_buf();
+    }
+    let mut name = OsString::from(path.as_os_str());
+    name.push(format!(".{generation}"));
+    PathBuf::from(name)
+}
+
+/// Paths of the `keep` state file generations, from the most recent one
+pub fn generation_paths(path: &Path, keep: usize) -> Vec<PathBuf> {
+    (0..keep.max(1))
+        .map(|generation| rotated_path(path, generation))
+        .collect()
+}
+
+/// Check whether a state file of `size` bytes should be rotated before the next write
+pub fn needs_rotation(size: u64, rotate_bytes: u64) -> bool {
+    rotate_bytes > 0 && size >= rotate_bytes
+}
+
+/// Read the first of the `candidates` that can be read, warning about the others
+pub fn select_latest<T>(
+    candidates: impl IntoIterator<Item = PathBuf>,
+    mut read: impl FnMut(&Path) -> Result<T, MonitorError>,
+) -> Option<(PathBuf, T)> {
+    for path in candidates {
+        if !path.exists() {
+            continue;
+        }
+        match read(&path) {
+            Ok(value) => return Some((path, value)),
+            Err(e) => warn!("Skipping unreadable state file {}: {}", path.display(), e),
+        }
+    }
+    None
+}
+
+/// Serialize a state as JSON, gzip-compressed if requested
+pub fn encode(state: &PersistedState, gzip: bool) -> Result<Vec<u8>, MonitorError> {
+    let json = serde_json::to_vec(state).map_err(|e| MonitorError::Json {
+        reason: e.to_string(),
+    })?;
+    if !gzip {
+        return Ok(json);
+    }
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(&json)?;
+    Ok(encoder.finish()?)
+}
+
+/// Deserialize a state, decompressing it first if it is gzip-compressed
+pub fn decode(bytes: &[u8]) -> Result<PersistedState, MonitorError> {
+    let json = if bytes.starts_with(&GZIP_MAGIC) {
+        let mut json = Vec::new();
+        GzDecoder::new(bytes).read_to_end(&mut json)?;
+        json
+    } else {
+        bytes.to_vec()
+    };
+    serde_json::from_slice(&json).map_err(|e| MonitorError::Json {
+        reason: e.to_string(),
+    })
+}
+
+/// Shift the state file generations by one, dropping the oldest of `keep`
+fn rotate(path: &Path, keep: usize) -> io::Result<()> {
+    let paths = generation_paths(path, keep);
+    if let Some(oldest) = paths.last().filter(|oldest| oldest.exists()) {
+        fs::remove_file(oldest)?;
+    }
+    for pair in paths.windows(2).rev() {
+        if pair[0].exists() {
+            fs::rename(&pair[0], &pair[1])?;
+        }
+    }
+    Ok(())
+}
+
+/// Write the state to `path`, rotating the file first once it reached `rotate_bytes`
+pub fn save(
+    path: &Path,
+    state: &PersistedState,
+    rotate_bytes: u64,
+    keep: usize,
+) -> Result<(), MonitorError> {
+    let gzip = path.extension().is_some_and(|extension| extension == "gz");
+    let bytes = encode(state, gzip)?;
+    if let Ok(metadata) = fs::metadata(path)
+        && needs_rotation(metadata.len(), rotate_bytes)
+    {
+        rotate(path, keep)?;
+    }
+
+    // Write to a temporary file first, never leaving a truncated state behind
+    let mut tmp = OsString::from(path.as_os_str());
+    tmp.push(".tmp");
+    fs::write(&tmp, bytes)?;
+    fs::rename(&tmp, path)?;
+    Ok(())
+}
+
+/// Load the most recent readable state among the `keep` generations of `path`
+pub fn load(path: &Path, keep: usize) -> Option<(PathBuf, PersistedState)> {
+    select_latest(generation_paths(path, keep), |path| {
+        decode(&fs::read(path)?)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// State telling the cycle it was written in
+    fn state(cycle: usize) -> PersistedState {
+        PersistedState {
+            observed_cycles: HashMap::from([("node-1".to_string(), cycle)]),
+            ..PersistedState::default()
+        }
+    }
+
+    fn cycle_of(state: &PersistedState) -> usize {
+        state.observed_cycles["node-1"]
+    }
+
+    #[test]
+    fn rotation_keeps_the_latest_files_and_skips_corrupt_ones() {
+        let dir = std::env::temp_dir().join(format!("ec2_monitor_state_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("state.json.gz");
+
+        // Every write rotates the previous file, keeping three generations
+        for cycle in 1..=5 {
+            save(&path, &state(cycle), 1, 3).unwrap();
+        }
+        let cycles: Vec<_> = generation_paths(&path, 3)
+            .iter()
+            .map(|path| cycle_of(&decode(&fs::read(path).unwrap()).unwrap()))
+            .collect();
+        assert_eq!(cycles, [5, 4, 3]);
+        assert!(!rotated_path(&path, 3).exists());
+
+        fs::write(&path, b"{ truncated").unwrap();
+        let (loaded_from, loaded) = load(&path, 3).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(loaded_from, rotated_path(&path, 1));
+        assert_eq!(cycle_of(&loaded), 4);
+    }
+
+    #[test]
+    fn plain_and_gzip_states_decode_alike() {
+        for gzip in [false, true] {
+            let bytes = encode(&state(7), gzip).unwrap();
+            assert_eq!(bytes.starts_with(&GZIP_MAGIC), gzip);
+            assert_eq!(cycle_of(&decode(&bytes).unwrap()), 7);
+        }
+    }
+}
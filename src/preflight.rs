@@ -0,0 +1,194 @@
+//! Startup self-check of the prerequisites of the monitor
+//!
+//! Missing credentials or SSH keys otherwise only show up as failures in the
+//! middle of a cycle, so they are checked once before monitoring starts.
+
+use aws_sdk_ec2::config::ProvideCredentials;
+use std::fs::File;
+use std::path::PathBuf;
+use tracing::{error, info};
+
+use crate::{MonitorConfig, MonitorError};
+
+/// Outcome of a single prerequisite check
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Check {
+    /// What was checked
+    pub name: &'static str,
+    /// Details of a passed check, or the reason of a failed one
+    pub outcome: Result<String, String>,
+}
+
+impl Check {
+    /// Log the check, a failure as an error
+    pub fn log(&self) {
+        match &self.outcome {
+            Ok(details) => info!("Preflight check passed, {}: {details}", self.name),
+            Err(reason) => error!("Preflight check failed, {}: {reason}", self.name),
+        }
+    }
+
+    /// Prefix the details or the reason of the check with the region it was run in
+    fn in_region(self, region: &str) -> Check {
+        let prefix = |text: String| format!("{region}: {text}");
+        Check {
+            name: self.name,
+            outcome: self.outcome.map(prefix).map_err(prefix),
+        }
+    }
+}
+
+/// Check that a region name looks like an AWS region, e.g. `sa-east-1` or `us-gov-west-1`
+pub fn check_region(region: &str) -> Check {
+    let parts: Vec<&str> = region.split('-').collect();
+    let valid = (3..=4).contains(&parts.len())
+        && parts[0].len() == 2
+        && parts[..parts.len() - 1]
+            .iter()
+            .all(|part| !part.is_empty() && part.chars().all(|c| c.is_ascii_lowercase()))
+        && parts[parts.len() - 1].chars().all(|c| c.is_ascii_digit())
+        && !parts[parts.len() - 1].is_empty();
+    Check {
+        name: "AWS region",
+        outcome: if valid {
+            Ok(region.to_string())
+        } else {
+            Err(format!("{region:?} is not a valid region name"))
+        },
+    }
+}
+
+/// Check that at least one of the SSH private keys exists and can be read
+pub fn check_key_files(key_paths: Result<&[PathBuf], &MonitorError>) -> Check {
+    let outcome = match key_paths {
+        Ok([]) => Err("no key configured, set AWS_KEYPAIR or use --key".to_string()),
+        Ok(paths) => {
+            let mut readable = Vec::new();
+            let mut reasons = Vec::new();
+            for path in paths {
+                match File::open(path) {
+                    Ok(_) => readable.push(path.display().to_string()),
+                    Err(e) => reasons.push(format!("{}: {e}", path.display())),
+                }
+            }
+            if readable.is_empty() {
+                Err(reasons.join(", "))
+            } else {
+                Ok(readable.join(", "))
+            }
+        }
+        Err(e) => Err(format!("{e}, set AWS_KEYPAIR or use --key")),
+    };
+    Check {
+        name: "SSH key",
+        outcome,
+    }
+}
+
+/// Check that the AWS credentials resolve
+pub async fn check_credentials(sdk_config: &aws_config::SdkConfig) -> Check {
+    let outcome = match sdk_config.credentials_provider() {
+        Some(provider) => match provider.provide_credentials().await {
+            Ok(credentials) => Ok(format!("access key {}", credentials.access_key_id())),
+            Err(e) => Err(e.to_string()),
+        },
+        None => Err("no credentials provider configured".to_string()),
+    };
+    Check {
+        name: "AWS credentials",
+        outcome,
+    }
+}
+
+/// Run the prerequisite checks, the credentials in every region of
+/// `sdk_configs` and the SSH key only when the command connects to the
+/// instances, and log them as a checklist on stderr, keeping stdout to the reports
+///
+/// Returns an error when any check failed.
+pub async fn preflight(
+    sdk_configs: &[aws_config::SdkConfig],
+    config: &MonitorConfig,
+    check_ssh_key: bool,
+) -> Result<(), MonitorError> {
+    let mut checks: Vec<_> = config
+        .aws
+        .regions
+        .iter()
+        .map(|region| check_region(region))
+        .collect();
+    for (region, sdk_config) in config.aws.regions.iter().zip(sdk_configs) {
+        checks.push(check_credentials(sdk_config).await.in_region(region));
+    }
+    if check_ssh_key {
+        checks.push(check_key_files(config.resolve_key_paths().as_deref()));
+    }
+
+    for check in &checks {
+        check.log();
+    }
+    let failures = checks.iter().filter(|check| check.outcome.is_err()).count();
+    if failures > 0 {
+        return Err(MonitorError::PreflightFailed { failures });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use aws_sdk_ec2::config::{Credentials, SharedCredentialsProvider};
+
+    #[test]
+    fn region_names_are_validated() {
+        for region in ["sa-east-1", "us-gov-west-1", "ap-southeast-2"] {
+            assert!(check_region(region).outcome.is_ok(), "{region}");
+        }
+        for region in ["", "saeast1", "sa-east", "SA-EAST-1", "south-america-1"] {
+            assert!(check_region(region).outcome.is_err(), "{region}");
+        }
+    }
+
+    #[test]
+    fn one_readable_key_is_enough() {
+        let key = std::env::temp_dir().join(format!("ec2_monitor_key_{}.pem", std::process::id()));
+        std::fs::write(&key, "key").unwrap();
+        let missing = PathBuf::from("/nonexistent/key.pem");
+
+        let readable = check_key_files(Ok(&[missing.clone(), key.clone()]));
+        std::fs::remove_file(&key).unwrap();
+        assert_eq!(readable.outcome, Ok(key.display().to_string()));
+        assert!(check_key_files(Ok(&[missing])).outcome.is_err());
+        assert!(check_key_files(Ok(&[])).outcome.is_err());
+        assert!(
+            check_key_files(Err(&MonitorError::NoPublicIp))
+                .outcome
+                .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn resolved_credentials_pass() {
+        let credentials = Credentials::new("AKIDEXAMPLE", "secret", None, None, "test");
+        let sdk_config = aws_config::SdkConfig::builder()
+            .credentials_provider(SharedCredentialsProvider::new(credentials))
+            .build();
+        assert_eq!(
+            check_credentials(&sdk_config).await.outcome,
+            Ok("access key AKIDEXAMPLE".to_string())
+        );
+        let without = aws_config::SdkConfig::builder().build();
+        assert!(check_credentials(&without).await.outcome.is_err());
+    }
+
+    #[test]
+    fn credential_checks_name_their_region() {
+        let failed = Check {
+            name: "AWS credentials",
+            outcome: Err("expired token".to_string()),
+        };
+        assert_eq!(
+            failed.in_region("sa-east-1").outcome,
+            Err("sa-east-1: expired token".to_string())
+        );
+    }
+}
@@ -0,0 +1,220 @@
+//! SOCKS5 proxy routing of the SSH connections
+
+use socks::Socks5Stream;
+use std::collections::HashMap;
+use std::io;
+use std::net::{TcpStream, ToSocketAddrs};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+use crate::MonitorError;
+
+/// How the TCP connection to an instance is established
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Route<'a> {
+    /// Connect to the instance directly
+    Direct,
+    /// Tunnel the connection through a SOCKS5 proxy at `host:port`
+    Socks5 { proxy: &'a str },
+}
+
+/// Pick the route of the connections, through the proxy when one is configured
+pub fn route(socks_proxy: Option<&str>) -> Route<'_> {
+    match socks_proxy {
+        Some(proxy) if !proxy.trim().is_empty() => Route::Socks5 { proxy },
+        _ => Route::Direct,
+    }
+}
+
+/// Extract the `host:port` of a SOCKS5 proxy from an `ALL_PROXY` value such as
+/// `socks5://proxy.example.com:1080`, `None` for other kinds of proxies
+pub fn socks5_proxy_address(all_proxy: &str) -> Option<String> {
+    let address = all_proxy
+        .trim()
+        .strip_prefix("socks5://")
+        .or_else(|| all_proxy.trim().strip_prefix("socks5h://"))?
+        .trim_end_matches('/');
+    (!address.is_empty()).then(|| address.to_string())
+}
+
+/// Open a TCP stream to `target` (`host:port`) along the given route, the
+/// error telling which route failed
+///
+/// A direct connection gives up on each address of the target after `timeout`,
+/// failing with `TimedOut`; the SOCKS5 client has no connect timeout.
+pub fn connect(target: &str, route: Route, timeout: Duration) -> Result<TcpStream, MonitorError> {
+    match route {
+        Route::Direct => {
+            connect_with_timeout(target, timeout).map_err(|source| MonitorError::DirectConnect {
+                target: target.to_string(),
+                source,
+            })
+        }
+        Route::Socks5 { proxy } => Socks5Stream::connect(proxy, target)
+            .map(Socks5Stream::into_inner)
+            .map_err(|source| MonitorError::ProxyConnect {
+                proxy: proxy.to_string(),
+                target: target.to_string(),
+                source,
+            }),
+    }
+}
+
+/// Connect to the addresses of `target` in turn, returning the first stream
+/// opened within `timeout`, else the last error
+fn connect_with_timeout(target: &str, timeout: Duration) -> io::Result<TcpStream> {
+    let mut outcome = Err(io::Error::new(
+        io::ErrorKind::InvalidInput,
+        format!("no address for {target}"),
+    ));
+    for addr in target.to_socket_addrs()? {
+        outcome = TcpStream::connect_timeout(&addr, timeout);
+        if outcome.is_ok() {
+            break;
+        }
+    }
+    outcome
+}
+
+/// Limits of the concurrent connections tunneled through each proxy, so that
+/// a parallel cycle does not overwhelm a single proxy host
+#[derive(Debug)]
+pub struct ProxyLimits {
+    per_proxy: usize,
+    semaphores: Mutex<HashMap<String, Arc<Semaphore>>>,
+}
+
+impl ProxyLimits {
+    /// Allow at most `per_proxy` concurrent connections through each proxy
+    pub fn new(per_proxy: usize) -> Self {
+        Self {
+            per_proxy,
+            semaphores: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Wait for a connection slot of the proxy of a route, held until the
+    /// permit is dropped, `None` for a direct connection
+    pub async fn acquire(&self, route: Route<'_>) -> Option<OwnedSemaphorePermit> {
+        let Route::Socks5 { proxy } = route else {
+            return None;
+        };
+        let semaphore = Arc::clone(
+            self.semaphores
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .entry(proxy.to_string())
+                .or_insert_with(|| Arc::new(Semaphore::new(self.per_proxy))),
+        );
+        semaphore.acquire_owned().await.ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+
+    #[test]
+    fn connections_go_through_the_proxy_only_when_one_is_configured() {
+        assert_eq!(route(None), Route::Direct);
+        assert_eq!(route(Some("  ")), Route::Direct);
+        assert_eq!(
+            route(Some("proxy:1080")),
+            Route::Socks5 {
+                proxy: "proxy:1080"
+            }
+        );
+    }
+
+    #[test]
+    fn only_socks5_all_proxy_values_are_used() {
+        assert_eq!(
+            socks5_proxy_address("socks5://proxy.example.com:1080/").as_deref(),
+            Some("proxy.example.com:1080")
+        );
+        assert_eq!(
+            socks5_proxy_address("socks5h://proxy:1080").as_deref(),
+            Some("proxy:1080")
+        );
+        assert_eq!(socks5_proxy_address("http://proxy:3128"), None);
+        assert_eq!(socks5_proxy_address("socks5://"), None);
+    }
+
+    #[test]
+    fn the_error_tells_the_route_that_failed() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let target = listener.local_addr().unwrap().to_string();
+        assert!(connect(&target, Route::Direct, Duration::from_secs(1)).is_ok());
+
+        // A port nothing listens on any more stands in for an unreachable proxy
+        let closed = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .to_string();
+        let err = connect(
+            &target,
+            Route::Socks5 { proxy: &closed },
+            Duration::from_secs(1),
+        )
+        .unwrap_err();
+        assert!(
+            matches!(err, MonitorError::ProxyConnect { proxy, .. } if proxy == closed),
+            "unexpected error"
+        );
+    }
+
+    #[tokio::test]
+    async fn connections_through_one_proxy_never_exceed_its_limit() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let limits = Arc::new(ProxyLimits::new(2));
+        let active = Arc::new(AtomicUsize::new(0));
+        let peak = Arc::new(AtomicUsize::new(0));
+        let mut tasks = tokio::task::JoinSet::new();
+        for _ in 0..10 {
+            let (limits, active, peak) = (limits.clone(), active.clone(), peak.clone());
+            tasks.spawn(async move {
+                let permit = limits
+                    .acquire(Route::Socks5 {
+                        proxy: "bastion-1:1080",
+                    })
+                    .await;
+                assert!(permit.is_some());
+                peak.fetch_max(active.fetch_add(1, Ordering::SeqCst) + 1, Ordering::SeqCst);
+                tokio::time::sleep(Duration::from_millis(10)).await;
+                active.fetch_sub(1, Ordering::SeqCst);
+            });
+        }
+
+        // Another proxy and direct connections do not wait for the busy proxy
+        let other = limits
+            .acquire(Route::Socks5 {
+                proxy: "bastion-2:1080",
+            })
+            .await;
+        assert!(other.is_some());
+        assert!(limits.acquire(Route::Direct).await.is_none());
+
+        while let Some(task) = tasks.join_next().await {
+            task.unwrap();
+        }
+        assert_eq!(peak.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn a_refused_direct_connection_is_a_direct_failure() {
+        let closed = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .to_string();
+        let err = connect(&closed, Route::Direct, Duration::from_secs(1)).unwrap_err();
+        assert!(
+            matches!(&err, MonitorError::DirectConnect { target, .. } if *target == closed),
+            "{err}"
+        );
+    }
+}
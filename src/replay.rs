@@ -0,0 +1,174 @@
+//! Replay log of the raw inputs of the monitoring cycles
+//!
+//! Each cycle appends one JSON line with the discovered instances and the raw
+//! outputs of their monitoring commands, so that a reported issue can be
+//! reproduced later without access to the fleet.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, OpenOptions};
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use crate::types::Endpoint;
+use crate::{InstanceInfo, InstanceResults, MonitorConfig, MonitorError, RawProbe, ssh};
+
+/// Raw inputs of one instance in a cycle
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecordedInstance {
+    pub instance: InstanceInfo,
+    /// Outputs of the monitoring commands, `None` if the instance failed
+    pub probe: Option<RawProbe>,
+    pub endpoint: Option<Endpoint>,
+    /// When the step was read from the instance
+    pub observed_at: Option<DateTime<Utc>>,
+    /// Error of a failed instance
+    pub error: Option<String>,
+}
+
+/// Raw inputs of a monitoring cycle
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecordedCycle {
+    pub recorded_at: DateTime<Utc>,
+    pub instances: Vec<RecordedInstance>,
+}
+
+impl RecordedInstance {
+    /// Record the outcome of processing an instance
+    pub fn new(instance: &InstanceInfo, outcome: Result<&InstanceResults, String>) -> Self {
+        match outcome {
+            Ok(result) => Self {
+                instance: instance.clone(),
+                probe: result.raw_probe.clone(),
+                endpoint: result.endpoint.clone(),
+                observed_at: result
+                    .timestep_result
+                    .as_ref()
+                    .and_then(|ts| ts.observed_at),
+                error: None,
+            },
+            Err(error) => Self {
+                instance: instance.clone(),
+                probe: None,
+                endpoint: None,
+                observed_at: None,
+                error: Some(error),
+            },
+        }
+    }
+
+    /// Rebuild the results of the instance from its raw inputs, as processed
+    /// by the monitoring cycle before the step increases and ETAs
+    pub fn replay(&self, config: &MonitorConfig) -> Result<InstanceResults, MonitorError> {
+        let Some(probe) = &self.probe else {
+            return Ok(InstanceResults {
+                instance_id: self.instance.instance_id.clone(),
+                public_ip: self.instance.public_ip.clone(),
+                name: self.instance.name.clone(),
+                instance_type: self.instance.instance_type.clone(),
+                tags: self.instance.tags.clone(),
+                connection_error: self.error.clone(),
+                ..Default::default()
+            });
+        };
+        let mut result =
+            ssh::results_from_probe(&self.instance, config, probe.clone(), self.endpoint.clone())?;
+        if let Some(ts) = &mut result.timestep_result {
+            ts.observed_at = self.observed_at;
+        }
+        Ok(result)
+    }
+}
+
+/// Append a cycle to the replay log as a single JSON line
+pub fn append(path: &Path, cycle: &RecordedCycle) -> Result<(), MonitorError> {
+    let line = serde_json::to_string(cycle).map_err(|e| MonitorError::Json {
+        reason: e.to_string(),
+    })?;
+    let file = OpenOptions::new().create(true).append(true).open(path)?;
+    let mut writer = BufWriter::new(file);
+    writeln!(writer, "{line}")?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// Parse the cycles of a replay log, one JSON line per cycle
+pub fn parse(contents: &str) -> Result<Vec<RecordedCycle>, MonitorError> {
+    contents
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| {
+            serde_json::from_str(line).map_err(|e| MonitorError::Json {
+                reason: e.to_string(),
+            })
+        })
+        .collect()
+}
+
+/// Load the cycles of a replay log
+pub fn load(path: &Path) -> Result<Vec<RecordedCycle>, MonitorError> {
+    parse(&fs::read_to_string(path)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::IpKind;
+
+    #[test]
+    fn a_recorded_cycle_replays_to_identical_results() {
+        let config = MonitorConfig::default();
+        let instance = InstanceInfo {
+            instance_id: "i-0abc".to_string(),
+            name: "wake_2ms".to_string(),
+            instance_type: "c6g.4xlarge".to_string(),
+            public_ip: Some("54.1.2.3".to_string()),
+            ..InstanceInfo::default()
+        };
+        let probe = RawProbe {
+            timestep: "TimeStep 1200: Time = 6".to_string(),
+            csv_count: 12,
+            disk_space:
+                "Filesystem Size Used Avail Use% Mounted on\n/dev/root 100G 80G 20G 80% /\n"
+                    .to_string(),
+            current_process: "zcsvs".to_string(),
+            ..RawProbe::default()
+        };
+        let endpoint = Endpoint {
+            kind: IpKind::Public,
+            ip: "54.1.2.3".to_string(),
+            fallback: false,
+            other_reachable: None,
+        };
+        let live = ssh::results_from_probe(&instance, &config, probe, Some(endpoint)).unwrap();
+        let failed = InstanceInfo {
+            instance_id: "i-0def".to_string(),
+            name: "wake_7ms".to_string(),
+            ..InstanceInfo::default()
+        };
+
+        let path =
+            std::env::temp_dir().join(format!("ec2_monitor_replay_{}.jsonl", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let cycle = RecordedCycle {
+            recorded_at: Utc::now(),
+            instances: vec![
+                RecordedInstance::new(&instance, Ok(&live)),
+                RecordedInstance::new(&failed, Err("Handshake timeout".to_string())),
+            ],
+        };
+        append(&path, &cycle).unwrap();
+        let cycles = load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(cycles.len(), 1);
+        let replayed = cycles[0].instances[0].replay(&config).unwrap();
+        assert_eq!(format!("{replayed:?}"), format!("{live:?}"));
+        let replayed_failure = cycles[0].instances[1].replay(&config).unwrap();
+        assert_eq!(replayed_failure.name, "wake_7ms");
+        assert_eq!(
+            replayed_failure.connection_error.as_deref(),
+            Some("Handshake timeout")
+        );
+    }
+}
@@ -1,10 +1,551 @@
 //! Report generation and terminal utilities
 
-use chrono::{DateTime, Local};
-use std::collections::HashMap;
-use std::io::{self, Write};
+use chrono::{DateTime, FixedOffset, Local, Utc};
+use chrono_tz::Tz;
+use crossterm::style::{Color, Stylize};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::env;
+use std::io::{self, IsTerminal, Write};
+use std::process::{Command, Stdio};
+use std::str::FromStr;
+use std::time::Duration;
+use tracing::warn;
 
-use crate::{InstanceResults, MonitorError};
+use crate::cpu::{self, CpuUtilization};
+use crate::history::HistorySample;
+use crate::types::{Endpoint, IpKind};
+use crate::{
+    InstanceInfo, InstanceResults, MonitorConfig, MonitorError, TimeStep, eta, memory, network,
+};
+
+/// Width of the report rules
+const REPORT_WIDTH: usize = 140;
+
+/// Characters of the rules framing the report table
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BorderStyle {
+    /// `=` and `-` rules
+    #[default]
+    Ascii,
+    /// `═` and `─` box-drawing rules
+    Unicode,
+    /// No rules
+    None,
+}
+
+impl BorderStyle {
+    /// Characters of the heavy and light rules, `None` without rules
+    pub fn rule_chars(self) -> Option<(char, char)> {
+        match self {
+            BorderStyle::Ascii => Some(('=', '-')),
+            BorderStyle::Unicode => Some(('═', '─')),
+            BorderStyle::None => None,
+        }
+    }
+
+    /// Rule framing the report
+    pub fn heavy_rule(self, width: usize) -> Option<String> {
+        self.rule_chars()
+            .map(|(heavy, _)| heavy.to_string().repeat(width))
+    }
+
+    /// Rule separating the table sections
+    pub fn light_rule(self, width: usize) -> Option<String> {
+        self.rule_chars()
+            .map(|(_, light)| light.to_string().repeat(width))
+    }
+}
+
+impl FromStr for BorderStyle {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ascii" => Ok(BorderStyle::Ascii),
+            "unicode" => Ok(BorderStyle::Unicode),
+            "none" => Ok(BorderStyle::None),
+            _ => Err(format!(
+                "unknown border style {s:?}, expected ascii, unicode or none"
+            )),
+        }
+    }
+}
+
+/// Rendering options of the reports
+#[derive(Debug, Clone)]
+pub struct ReportOptions {
+    /// Plain text output: no terminal clearing and no emoji
+    pub plain: bool,
+    /// Show the endpoint column
+    pub show_endpoints: bool,
+    /// Show the instantaneous and lifetime average step rates
+    pub show_rates: bool,
+    /// Show the SSH connection latency column
+    pub show_latency: bool,
+    /// Show the idle complete simulations as such, not only those post-processing
+    pub post_completion_phases: bool,
+    /// Maximum number of characters of the instance names
+    pub name_width: usize,
+    /// Characters of the rules framing the table
+    pub border: BorderStyle,
+    /// Number of solver warnings above which an instance is flagged
+    pub warning_threshold: usize,
+    /// Show the solver memory column
+    pub show_memory: bool,
+    /// Fraction of the instance memory above which the solver memory is flagged
+    pub memory_threshold: f64,
+    /// Show the network throughput column
+    pub show_network: bool,
+    /// Color the footer counts
+    pub color: bool,
+    /// Grow the name and connection columns to fit their content instead of truncating it
+    pub wide: bool,
+    /// Maximum number of characters of the connection errors
+    pub error_width: usize,
+    /// Width of the connection column
+    pub connection_width: usize,
+    /// Show the step and CSV counts with K/M/G suffixes
+    pub human: bool,
+    /// Tag key grouping the rows, with a subtotal per group
+    pub group_by: Option<String>,
+    /// Number of rows shown, all rows when `None`
+    pub top: Option<usize>,
+    /// Timezone of the rendered timestamps, the local timezone when `None`
+    pub timezone: Option<Tz>,
+}
+
+impl Default for ReportOptions {
+    fn default() -> Self {
+        Self {
+            plain: false,
+            show_endpoints: false,
+            show_rates: false,
+            show_latency: false,
+            post_completion_phases: false,
+            name_width: 20,
+            border: BorderStyle::default(),
+            warning_threshold: 0,
+            show_memory: false,
+            memory_threshold: memory::DEFAULT_MEMORY_THRESHOLD,
+            show_network: false,
+            color: false,
+            wide: false,
+            error_width: 18,
+            connection_width: 12,
+            human: false,
+            group_by: None,
+            top: None,
+            timezone: None,
+        }
+    }
+}
+
+impl ReportOptions {
+    /// Resolve the rendering options for the current standard output
+    pub fn from_config(config: &MonitorConfig) -> Self {
+        let plain = use_plain_output(io::stdout().is_terminal(), config.plain_output);
+        Self {
+            plain,
+            color: use_color(plain, std::env::var_os("NO_COLOR").as_deref()),
+            show_endpoints: config.show_endpoints,
+            show_rates: config.show_rates,
+            show_latency: config.show_latency,
+            post_completion_phases: config.post_completion_phases,
+            name_width: config.name_width,
+            border: config.border_style,
+            warning_threshold: config.warning_threshold,
+            show_memory: config.check_memory,
+            memory_threshold: config.memory_threshold,
+            show_network: config.check_network,
+            wide: config.wide_report,
+            human: config.human_counts,
+            group_by: config.group_by.clone(),
+            top: config.top_rows,
+            timezone: config.display_timezone,
+            ..Self::default()
+        }
+    }
+
+    /// Size the name and connection columns to the longest content of the
+    /// results in wide mode, leaving the options unchanged otherwise
+    pub fn fit_to<'a>(&self, results: impl IntoIterator<Item = &'a InstanceResults>) -> Self {
+        if !self.wide {
+            return self.clone();
+        }
+        let mut fitted = self.clone();
+        for result in results {
+            fitted.name_width = fitted.name_width.max(result.name.chars().count());
+            if let Some(error) = &result.connection_error {
+                let width = error.chars().count();
+                fitted.error_width = fitted.error_width.max(width);
+                fitted.connection_width = fitted.connection_width.max(width);
+            }
+        }
+        fitted
+    }
+
+    /// Write the rule framing the report, if the border style has one
+    fn write_heavy_rule<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        if let Some(rule) = self.border.heavy_rule(REPORT_WIDTH) {
+            writeln!(w, "{rule}")?;
+        }
+        Ok(())
+    }
+
+    /// Write the rule separating the table sections, if the border style has one
+    fn write_light_rule<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        if let Some(rule) = self.border.light_rule(REPORT_WIDTH) {
+            writeln!(w, "{rule}")?;
+        }
+        Ok(())
+    }
+
+    /// Prefix a label with its status emoji, unless the output is plain
+    fn decorate(&self, emoji: &str, label: &str) -> String {
+        if self.plain {
+            label.to_string()
+        } else {
+            format!("{emoji} {label}")
+        }
+    }
+
+    /// Color a footer count, unless colors are disabled
+    fn paint(&self, count: usize, color: Option<Color>) -> String {
+        match color {
+            Some(color) if self.color => count.to_string().with(color).to_string(),
+            _ => count.to_string(),
+        }
+    }
+}
+
+/// Status of a complete simulation combined with the process still running on
+/// its instance, e.g. "Complete → s3 sync" or "Complete (idle)"
+pub fn completion_status(current_process: Option<&str>, plain: bool) -> String {
+    let arrow = if plain { "->" } else { "→" };
+    match current_process {
+        Some("none") => format!("{} (idle)", eta::COMPLETE),
+        Some(process) => format!("{} {arrow} {process}", eta::COMPLETE),
+        None => eta::COMPLETE.to_string(),
+    }
+}
+
+/// Processes run on an instance once its solver steps are complete
+const POST_PROCESSES: [&str; 2] = ["finalize", "s3 sync"];
+
+/// ETA shown for an instance, aware of its running process: once the steps
+/// are complete, a post-process running is shown instead of the solver ETA,
+/// with its own ETA when known, and with `post_completion_phases` an idle
+/// instance too
+pub fn eta_display(result: &InstanceResults, options: &ReportOptions) -> String {
+    let steps_complete = result
+        .timestep_result
+        .as_ref()
+        .is_some_and(|ts| ts.is_complete())
+        || result.eta.as_deref() == Some(eta::COMPLETE);
+    let post_processing = result
+        .current_process
+        .as_deref()
+        .is_some_and(|process| POST_PROCESSES.contains(&process));
+    match (result.eta.as_deref(), result.output_eta_minutes) {
+        _ if result.terminated => eta::COMPLETE_TERMINATED.to_string(),
+        (_, Some(minutes)) if steps_complete => {
+            output_eta_status(result.current_process.as_deref(), minutes)
+        }
+        _ if steps_complete && (post_processing || options.post_completion_phases) => {
+            completion_status(result.current_process.as_deref(), options.plain)
+        }
+        (Some(eta), _) => eta.to_string(),
+        (None, _) => "N/A".to_string(),
+    }
+}
+
+/// ETA cell of a complete instance in an output-bound phase, e.g. `s3 sync 12m`
+pub fn output_eta_status(current_process: Option<&str>, minutes: f64) -> String {
+    format!(
+        "{} {}",
+        current_process.unwrap_or_default(),
+        eta::format_remaining(minutes)
+    )
+}
+
+/// Format a count with one decimal and a K, M or G suffix, e.g. `1.5K` or
+/// `2.4M`, leaving counts below 1000 as they are
+pub fn humanize_count(n: u64) -> String {
+    const SUFFIXES: [&str; 3] = ["K", "M", "G"];
+    if n < 1000 {
+        return n.to_string();
+    }
+    let mut value = n as f64 / 1000.0;
+    let mut suffix = 0;
+    // Move up a suffix when the value would round to 1000.0, e.g. 999_960
+    while suffix + 1 < SUFFIXES.len() && (value * 10.0).round() >= 10_000.0 {
+        value /= 1000.0;
+        suffix += 1;
+    }
+    format!("{value:.1}{}", SUFFIXES[suffix])
+}
+
+/// Format a timestep as its step, or step increase, and simulation time,
+/// humanizing the step count if requested
+fn format_timestep(ts: &TimeStep, options: &ReportOptions) -> String {
+    if !options.human {
+        return ts.to_string();
+    }
+    match ts.step_increase {
+        Some(inc) => format!("(+{}){:8.2}", humanize_count(inc as u64), ts.time),
+        None => format!("({}){:8.2}", humanize_count(ts.step as u64), ts.time),
+    }
+}
+
+/// Shorten a text to at most `width` characters, ending it with "..." when cut
+pub fn truncate(text: &str, width: usize) -> String {
+    if text.chars().count() <= width {
+        return text.to_string();
+    }
+    let kept: String = text.chars().take(width.saturating_sub(3)).collect();
+    format!("{kept}...")
+}
+
+/// Check whether a failed instance is young enough to still be starting up
+pub fn is_starting_up(instance_age: Option<Duration>, grace: Duration) -> bool {
+    instance_age.is_some_and(|age| age < grace)
+}
+
+/// Decide whether to use plain output: an explicit choice wins, otherwise plain
+/// output is used whenever standard output is not a terminal
+pub fn use_plain_output(is_terminal: bool, plain_override: Option<bool>) -> bool {
+    plain_override.unwrap_or(!is_terminal)
+}
+
+/// Decide whether to color the output: never for plain output, nor when
+/// `NO_COLOR` is set to a non-empty value
+pub fn use_color(plain: bool, no_color: Option<&std::ffi::OsStr>) -> bool {
+    !plain && no_color.is_none_or(|value| value.is_empty())
+}
+
+/// Color of the successful connections count: red when any connection failed,
+/// else green
+pub fn connection_color(successful: usize, total: usize) -> Color {
+    if successful < total {
+        Color::Red
+    } else {
+        Color::Green
+    }
+}
+
+/// Color of a footer count: `color` when the count is not zero
+pub fn count_color(count: usize, color: Color) -> Option<Color> {
+    (count > 0).then_some(color)
+}
+
+/// Fleet-wide aggregates of a monitoring cycle
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct FleetSummary {
+    /// Steps completed over total steps across all instances, in percent
+    pub fleet_progress_percent: f64,
+    /// Number of instances whose simulation is complete
+    pub complete_count: usize,
+    /// Number of instances whose simulation is stalled
+    pub stalled_count: usize,
+    /// Median ETA of the slowest instance, i.e. when the whole fleet is done
+    pub fleet_eta: Option<String>,
+}
+
+impl FleetSummary {
+    /// Aggregate the results and per-instance ETA samples of a cycle
+    pub fn from_results(
+        results: &[InstanceResults],
+        instance_etas: &HashMap<String, Vec<f64>>,
+    ) -> Self {
+        let (done, total) = results
+            .iter()
+            .filter_map(|r| r.timestep_result.as_ref())
+            .filter(|ts| ts.total_step > 0)
+            .fold((0usize, 0usize), |(done, total), ts| {
+                (done + ts.step.min(ts.total_step), total + ts.total_step)
+            });
+        let fleet_progress_percent = if total > 0 {
+            (1000.0 * done as f64 / total as f64).round() / 10.0
+        } else {
+            0.0
+        };
+
+        let complete_count = results
+            .iter()
+            .filter(|r| {
+                r.timestep_result
+                    .as_ref()
+                    .is_some_and(|ts| ts.is_complete())
+            })
+            .count();
+        let stalled_count = results
+            .iter()
+            .filter(|r| {
+                r.eta
+                    .as_deref()
+                    .is_some_and(|e| e.starts_with(eta::STALLED))
+            })
+            .count();
+
+        let fleet_eta = if !results.is_empty() && complete_count == results.len() {
+            Some(eta::COMPLETE.to_string())
+        } else {
+            fleet_eta_minutes(results, instance_etas).map(eta::format_remaining)
+        };
+
+        Self {
+            fleet_progress_percent,
+            complete_count,
+            stalled_count,
+            fleet_eta,
+        }
+    }
+}
+
+/// Median ETA in minutes of the slowest instance, i.e. when the whole fleet is done
+pub fn fleet_eta_minutes<'a>(
+    results: impl IntoIterator<Item = &'a InstanceResults>,
+    instance_etas: &HashMap<String, Vec<f64>>,
+) -> Option<f64> {
+    results
+        .into_iter()
+        .filter_map(|r| instance_etas.get(&r.name))
+        .filter_map(|etas| eta::median_minutes(etas))
+        .max_by(|a, b| a.total_cmp(b))
+}
+
+/// Group value of the instances without the grouping tag
+pub const UNTAGGED: &str = "(untagged)";
+
+/// Split the first `top` results off the others, returning them with the
+/// number of results left out
+///
+/// With a `top`, the results are sorted by relevance first, so that the rows
+/// cut are the least urgent ones
+pub fn top_rows(results: &[InstanceResults], top: Option<usize>) -> (Vec<&InstanceResults>, usize) {
+    let mut rows: Vec<_> = results.iter().collect();
+    let Some(top) = top else {
+        return (rows, 0);
+    };
+    rows.sort_by(|a, b| relevance_order(a, b));
+    let hidden = rows.len().saturating_sub(top);
+    rows.truncate(top);
+    (rows, hidden)
+}
+
+/// Order of the rows by relevance: failed, stalled or diverged instances
+/// first, then by ascending ETA, unknown last, then by name
+fn relevance_order(a: &InstanceResults, b: &InstanceResults) -> std::cmp::Ordering {
+    let eta = |r: &InstanceResults| r.eta_minutes.unwrap_or(f64::INFINITY);
+    needs_attention(b)
+        .cmp(&needs_attention(a))
+        .then_with(|| eta(a).total_cmp(&eta(b)))
+        .then_with(|| a.name.cmp(&b.name))
+}
+
+/// Whether the instance failed, stalled or diverged
+fn needs_attention(result: &InstanceResults) -> bool {
+    (result.connection_error.is_some() && !result.starting_up)
+        || result.diverged
+        || result
+            .eta
+            .as_deref()
+            .is_some_and(|eta| eta == eta::DIVERGED || eta.starts_with(eta::STALLED))
+}
+
+/// Group the results by the value of the tag `key`, sorted by value, keeping
+/// the order of the results within a group
+pub fn group_by_tag<'a>(
+    results: impl IntoIterator<Item = &'a InstanceResults>,
+    key: &str,
+) -> Vec<(&'a str, Vec<&'a InstanceResults>)> {
+    let mut groups: BTreeMap<&str, Vec<&InstanceResults>> = BTreeMap::new();
+    for result in results {
+        let value = result.tags.get(key).map_or(UNTAGGED, String::as_str);
+        groups.entry(value).or_default().push(result);
+    }
+    groups.into_iter().collect()
+}
+
+/// Subtotal line of a group: its number of instances, complete instances and
+/// the median ETA of its slowest instance
+pub fn group_subtotal(
+    group: &[&InstanceResults],
+    instance_etas: &HashMap<String, Vec<f64>>,
+) -> String {
+    let complete_count = group
+        .iter()
+        .filter(|r| {
+            r.timestep_result
+                .as_ref()
+                .is_some_and(|ts| ts.is_complete())
+        })
+        .count();
+    let group_eta = if complete_count == group.len() {
+        eta::COMPLETE.to_string()
+    } else {
+        fleet_eta_minutes(group.iter().copied(), instance_etas)
+            .map_or("N/A".to_string(), eta::format_remaining)
+    };
+    format!(
+        "  Subtotal: {} instances | {complete_count} complete | ETA {group_eta}",
+        group.len()
+    )
+}
+
+/// Instances whose disk is projected to be full before the fleet completes,
+/// with their minutes until full
+pub fn disk_filling_before(
+    results: &[InstanceResults],
+    fleet_eta_minutes: f64,
+) -> Vec<(&str, f64)> {
+    results
+        .iter()
+        .filter(|r| !r.terminated)
+        .filter_map(|r| Some((r.name.as_str(), r.disk_full_minutes?)))
+        .filter(|(_, minutes)| *minutes < fleet_eta_minutes)
+        .collect()
+}
+
+/// Labels of the ETA histogram buckets
+const ETA_BUCKETS: [&str; 4] = ["<1h", "1-6h", "6-24h", ">24h"];
+
+/// Index of the ETA histogram bucket of an ETA in minutes
+pub fn eta_bucket(minutes: f64) -> usize {
+    match minutes / 60.0 {
+        hours if hours < 1.0 => 0,
+        hours if hours < 6.0 => 1,
+        hours if hours < 24.0 => 2,
+        _ => 3,
+    }
+}
+
+/// Text histogram of the latest ETA sample of every running instance of the
+/// results, one line per bucket with a bar and the count, empty without samples
+pub fn eta_histogram<'a>(
+    results: impl IntoIterator<Item = &'a InstanceResults>,
+    instance_etas: &HashMap<String, Vec<f64>>,
+) -> String {
+    let mut counts = [0usize; ETA_BUCKETS.len()];
+    for minutes in results
+        .into_iter()
+        .filter_map(|r| instance_etas.get(&r.name)?.last())
+    {
+        // Complete simulations are recorded as 0 minutes
+        if *minutes > 0.0 {
+            counts[eta_bucket(*minutes)] += 1;
+        }
+    }
+    if counts.iter().all(|&count| count == 0) {
+        return String::new();
+    }
+    ETA_BUCKETS
+        .iter()
+        .zip(counts)
+        .map(|(label, count)| format!("  {label:<6} | {} {count}\n", "#".repeat(count)))
+        .collect()
+}
 
 /// Clear terminal screen
 pub fn clear_terminal() {
@@ -13,19 +554,503 @@ pub fn clear_terminal() {
     io::stdout().flush().unwrap();
 }
 
+/// Time of an instant in the display timezone, the local timezone when `None`
+pub fn display_time(instant: DateTime<Utc>, timezone: Option<Tz>) -> DateTime<FixedOffset> {
+    match timezone {
+        Some(timezone) => instant.with_timezone(&timezone).fixed_offset(),
+        None => instant.with_timezone(&Local).fixed_offset(),
+    }
+}
+
+/// Draw a rendered report, clearing the terminal first unless in plain mode
+pub fn redraw(report: &str, plain: bool) {
+    if !plain {
+        clear_terminal();
+    }
+    print!("{report}");
+    io::stdout().flush().unwrap();
+}
+
+/// Title of the summary report, naming the AWS account when known
+pub fn report_title(now: DateTime<FixedOffset>, account: Option<&str>) -> String {
+    match account {
+        Some(account) => format!("SUMMARY REPORT @ {now} | AWS account {account}"),
+        None => format!("SUMMARY REPORT @ {now}"),
+    }
+}
+
 /// Print comprehensive summary report
 pub fn print_summary_report(
     results: &[InstanceResults],
-    _instance_etas: &HashMap<String, Vec<f64>>,
+    instance_etas: &HashMap<String, Vec<f64>>,
+    options: &ReportOptions,
+    account: Option<&str>,
+) -> Result<(), MonitorError> {
+    write_summary_report(
+        &mut io::stdout().lock(),
+        results,
+        instance_etas,
+        options,
+        account,
+    )
+}
+
+/// Pager command line from `$PAGER`, defaulting to `less -R` to keep the colors
+fn pager_command() -> Vec<String> {
+    env::var("PAGER")
+        .ok()
+        .map(|pager| {
+            pager
+                .split_whitespace()
+                .map(str::to_string)
+                .collect::<Vec<_>>()
+        })
+        .filter(|args| !args.is_empty())
+        .unwrap_or_else(|| vec!["less".to_string(), "-R".to_string()])
+}
+
+/// Print comprehensive summary report through the pager, or directly to
+/// stdout if the pager cannot be started
+pub fn page_summary_report(
+    results: &[InstanceResults],
+    instance_etas: &HashMap<String, Vec<f64>>,
+    options: &ReportOptions,
+    account: Option<&str>,
+) -> Result<(), MonitorError> {
+    page_summary_report_with(&pager_command(), results, instance_etas, options, account)
+}
+
+/// Print comprehensive summary report through the pager command line
+/// `command`, or directly to stdout if the pager cannot be started
+fn page_summary_report_with(
+    command: &[String],
+    results: &[InstanceResults],
+    instance_etas: &HashMap<String, Vec<f64>>,
+    options: &ReportOptions,
+    account: Option<&str>,
+) -> Result<(), MonitorError> {
+    let mut pager = match Command::new(&command[0])
+        .args(&command[1..])
+        .stdin(Stdio::piped())
+        .spawn()
+    {
+        Ok(pager) => pager,
+        Err(e) => {
+            warn!("Failed to start the pager {}: {e}", command[0]);
+            return print_summary_report(results, instance_etas, options, account);
+        }
+    };
+    if let Some(mut stdin) = pager.stdin.take() {
+        match write_summary_report(&mut stdin, results, instance_etas, options, account) {
+            // The pager was quit before reading the whole report
+            Err(MonitorError::Io(e)) if e.kind() == io::ErrorKind::BrokenPipe => {}
+            result => result?,
+        }
+    }
+    pager.wait()?;
+    Ok(())
+}
+
+/// Write comprehensive summary report to `w`
+pub fn write_summary_report<W: Write>(
+    w: &mut W,
+    results: &[InstanceResults],
+    instance_etas: &HashMap<String, Vec<f64>>,
+    options: &ReportOptions,
+    account: Option<&str>,
+) -> Result<(), MonitorError> {
+    let options = &options.fit_to(results);
+    let now = display_time(Utc::now(), options.timezone);
+    writeln!(w)?;
+    options.write_heavy_rule(w)?;
+    writeln!(w, "{}", report_title(now, account))?;
+    options.write_heavy_rule(w)?;
+
+    write_table_header(w, options)?;
+
+    let (shown, hidden) = top_rows(results, options.top);
+    match &options.group_by {
+        Some(key) => {
+            for (value, group) in group_by_tag(shown, key) {
+                writeln!(w, "{key}={value}")?;
+                for result in &group {
+                    writeln!(w, "{}", format_result_row(result, options))?;
+                }
+                writeln!(w, "{}", group_subtotal(&group, instance_etas))?;
+            }
+        }
+        None => {
+            for result in shown {
+                writeln!(w, "{}", format_result_row(result, options))?;
+            }
+        }
+    }
+    if hidden > 0 {
+        writeln!(w, "... and {hidden} more")?;
+    }
+
+    options.write_light_rule(w)?;
+
+    // Instance notes as footnotes
+    let noted: Vec<_> = results
+        .iter()
+        .filter_map(|r| r.note.as_ref().map(|note| (&r.name, note)))
+        .collect();
+    if !noted.is_empty() {
+        writeln!(w, "Notes:")?;
+        for (name, note) in noted {
+            writeln!(w, "  {name}: {note}")?;
+        }
+        options.write_light_rule(w)?;
+    }
+
+    // Solvers running fewer threads than their node has processors
+    let underutilized: Vec<_> = results
+        .iter()
+        .filter_map(|r| match (r.solver_threads, r.nproc) {
+            (Some(threads), Some(nproc)) => match cpu::classify_utilization(threads, nproc) {
+                CpuUtilization::Under { threads, nproc } => Some((&r.name, threads, nproc)),
+                _ => None,
+            },
+            _ => None,
+        })
+        .collect();
+    if !underutilized.is_empty() {
+        writeln!(w, "{}", options.decorate("⚠️", "Underutilized processors:"))?;
+        for (name, threads, nproc) in underutilized {
+            writeln!(
+                w,
+                "  {name}: {threads} solver threads on {nproc} processors"
+            )?;
+        }
+        options.write_light_rule(w)?;
+    }
+
+    // Warnings in the solver log tails, flagged above the threshold
+    let warned: Vec<_> = results
+        .iter()
+        .filter_map(|r| r.warning_count.map(|count| (&r.name, count)))
+        .filter(|(_, count)| *count > 0)
+        .collect();
+    if !warned.is_empty() {
+        writeln!(w, "Solver warnings in the log tail:")?;
+        for (name, count) in warned {
+            let label = format!("{name}: {count}");
+            if count > options.warning_threshold {
+                writeln!(w, "  {}", options.decorate("⚠️", &label))?;
+            } else {
+                writeln!(w, "  {label}")?;
+            }
+        }
+        options.write_light_rule(w)?;
+    }
+
+    // Summary statistics
+    let total_instances = results.len();
+    let successful_connections = results
+        .iter()
+        .filter(|r| r.connection_error.is_none())
+        .count();
+    let zcsvs_count = results
+        .iter()
+        .filter(|r| r.current_process.as_ref() == Some(&"zcsvs".to_string()))
+        .count();
+    let finalize_count = results
+        .iter()
+        .filter(|r| r.current_process.as_ref() == Some(&"finalize".to_string()))
+        .count();
+    let s3_sync_count = results
+        .iter()
+        .filter(|r| r.current_process.as_ref() == Some(&"s3 sync".to_string()))
+        .count();
+    let idle_count = results
+        .iter()
+        .filter(|r| r.current_process.as_ref() == Some(&"none".to_string()))
+        .count();
+
+    writeln!(
+        w,
+        "Summary: {} total instances | {} successful connections | {} zcsvs | {} finalize | {} s3 sync | {} idle",
+        total_instances,
+        options.paint(
+            successful_connections,
+            Some(connection_color(successful_connections, total_instances))
+        ),
+        zcsvs_count,
+        options.paint(finalize_count, count_color(finalize_count, Color::Yellow)),
+        s3_sync_count,
+        options.paint(idle_count, count_color(idle_count, Color::Yellow))
+    )?;
+
+    let fleet = FleetSummary::from_results(results, instance_etas);
+    writeln!(
+        w,
+        "Fleet: {:.1}% complete | {} complete | {} stalled | fleet ETA {}",
+        fleet.fleet_progress_percent,
+        options.paint(
+            fleet.complete_count,
+            count_color(fleet.complete_count, Color::Green)
+        ),
+        fleet.stalled_count,
+        fleet.fleet_eta.as_deref().unwrap_or("N/A")
+    )?;
+
+    if let Some(fleet_minutes) = fleet_eta_minutes(results, instance_etas) {
+        let filling = disk_filling_before(results, fleet_minutes);
+        if !filling.is_empty() {
+            writeln!(
+                w,
+                "{}",
+                options.decorate("⚠️", "Disk projected to fill before the fleet completes:")
+            )?;
+            for (name, minutes) in filling {
+                writeln!(
+                    w,
+                    "  {name}: full in {} (fleet ETA {})",
+                    eta::format_minutes(minutes),
+                    eta::format_minutes(fleet_minutes)
+                )?;
+            }
+        }
+    }
+
+    let histogram = eta_histogram(results, instance_etas);
+    if !histogram.is_empty() {
+        writeln!(w, "ETA distribution:")?;
+        write!(w, "{histogram}")?;
+    }
+
+    options.write_heavy_rule(w)?;
+    Ok(())
+}
+
+/// Machine-readable state of an instance in the JSON report
+#[derive(Debug, Clone, Serialize)]
+pub struct InstanceReport {
+    pub name: String,
+    pub instance_id: String,
+    pub instance_type: String,
+    pub step: Option<usize>,
+    pub total_step: Option<usize>,
+    pub time: Option<f64>,
+    pub eta: Option<String>,
+    pub eta_minutes: Option<f64>,
+    pub csv_count: Option<i32>,
+    pub free_disk_space: Option<String>,
+    pub current_process: Option<String>,
+    pub connection_error: Option<String>,
+    pub ssh_username: Option<String>,
+}
+
+impl From<&InstanceResults> for InstanceReport {
+    fn from(result: &InstanceResults) -> Self {
+        let ts = result.timestep_result.as_ref();
+        Self {
+            name: result.name.clone(),
+            instance_id: result.instance_id.clone(),
+            instance_type: result.instance_type.clone(),
+            step: ts.map(|ts| ts.step),
+            total_step: ts.map(|ts| ts.total_step),
+            time: ts.map(|ts| ts.time),
+            eta: result.eta.clone(),
+            eta_minutes: result.eta_minutes,
+            csv_count: result.csv_count,
+            free_disk_space: result.free_disk_space.clone(),
+            current_process: result.current_process.clone(),
+            connection_error: result.connection_error.clone(),
+            ssh_username: result.ssh_username.clone(),
+        }
+    }
+}
+
+/// Fleet aggregates of the JSON report
+#[derive(Debug, Clone, Serialize)]
+pub struct ReportSummary {
+    pub total: usize,
+    pub successful: usize,
+    pub complete: usize,
+    pub stalled: usize,
+    pub fleet_eta: Option<String>,
+}
+
+/// JSON report of a cycle: the instances and their aggregates
+#[derive(Debug, Clone, Serialize)]
+pub struct JsonReport {
+    pub generated_at: DateTime<Utc>,
+    pub instances: Vec<InstanceReport>,
+    pub summary: ReportSummary,
+}
+
+impl JsonReport {
+    /// Build the report of the results and per-instance ETA samples of a cycle
+    pub fn new(
+        results: &[InstanceResults],
+        instance_etas: &HashMap<String, Vec<f64>>,
+        generated_at: DateTime<Utc>,
+    ) -> Self {
+        let fleet = FleetSummary::from_results(results, instance_etas);
+        Self {
+            generated_at,
+            instances: results.iter().map(InstanceReport::from).collect(),
+            summary: ReportSummary {
+                total: results.len(),
+                successful: results
+                    .iter()
+                    .filter(|r| r.connection_error.is_none())
+                    .count(),
+                complete: fleet.complete_count,
+                stalled: fleet.stalled_count,
+                fleet_eta: fleet.fleet_eta,
+            },
+        }
+    }
+}
+
+/// Print the report of a cycle as a single JSON object
+pub fn print_summary_report_json(
+    results: &[InstanceResults],
+    instance_etas: &HashMap<String, Vec<f64>>,
 ) -> Result<(), MonitorError> {
-    let local: DateTime<Local> = Local::now();
-    println!("{}", "\n".to_string() + "=".repeat(135).as_str());
-    println!("SUMMARY REPORT @ {local}");
-    println!("{}", "=".repeat(135));
-
-    // Table headers
-    println!(
-        "{:<22} {:^19} {:^12} {:^13} {:^15} {:^5} {:^8} {:<13} {:<12}",
+    let report = JsonReport::new(results, instance_etas, Utc::now());
+    let json = serde_json::to_string(&report).map_err(|e| MonitorError::Json {
+        reason: e.to_string(),
+    })?;
+    println!("{json}");
+    Ok(())
+}
+
+/// Describe the instances matched by the discovery filters, one per line after the count
+pub fn format_discovery(instances: &[InstanceInfo]) -> String {
+    let mut text = format!("Matched {} target instances\n", instances.len());
+    for instance in instances {
+        text.push_str(&format!(
+            "  {:<22} {:<19} {:<13} {}\n",
+            instance.name,
+            instance.instance_id,
+            instance.instance_type,
+            instance.public_ip.as_deref().unwrap_or("no public IP")
+        ));
+    }
+    text
+}
+
+/// Summarize the whole campaign from the progress history: per instance the
+/// steps achieved, the monitored runtime, the average step rate and whether
+/// the job completed, sorted by instance name
+pub fn campaign_summary(history: &HashMap<String, Vec<HistorySample>>) -> String {
+    let mut text = format!("CAMPAIGN REPORT: {} instances\n", history.len());
+    text.push_str(&format!(
+        "  {:<22} {:>21} {:>12} {:>10} {}\n",
+        "Instance", "Steps", "Runtime", "Steps/min", "Status"
+    ));
+
+    let mut names: Vec<_> = history.keys().collect();
+    names.sort();
+    let mut complete_count = 0;
+    for name in names {
+        let (Some(first), Some(last)) = (history[name].first(), history[name].last()) else {
+            continue;
+        };
+        let minutes = (last.timestamp - first.timestamp).num_seconds() as f64 / 60.0;
+        let achieved = last.step.saturating_sub(first.step);
+        let rate = if minutes > 0.0 {
+            format!("{:.1}", achieved as f64 / minutes)
+        } else {
+            "N/A".to_string()
+        };
+        let complete = last.total_step > 0 && last.step >= last.total_step;
+        if complete {
+            complete_count += 1;
+        }
+        text.push_str(&format!(
+            "  {:<22} {:>21} {:>12} {:>10} {}\n",
+            name,
+            format!("{}/{} (+{achieved})", last.step, last.total_step),
+            eta::format_minutes(minutes),
+            rate,
+            if complete { "Complete" } else { "Incomplete" }
+        ));
+    }
+    text.push_str(&format!(
+        "{complete_count} of {} instances complete\n",
+        history.len()
+    ));
+    text
+}
+
+/// Heartbeat line of a cycle without changes, showing that the monitor is alive
+pub fn heartbeat_line(
+    cycle: usize,
+    now: DateTime<FixedOffset>,
+    next_update: DateTime<FixedOffset>,
+) -> String {
+    format!(
+        "[{}] cycle {cycle} completed, no changes, next update at {}",
+        now.format("%Y-%m-%d %H:%M:%S"),
+        next_update.format("%H:%M:%S")
+    )
+}
+
+/// Print only the instances that changed since the previous cycle, or a
+/// heartbeat line for cycle number `cycle` when nothing changed
+pub fn print_changes_report(
+    results: &[InstanceResults],
+    previous_results: &HashMap<String, InstanceResults>,
+    options: &ReportOptions,
+    cycle: usize,
+    next_update: DateTime<Utc>,
+) -> Result<(), MonitorError> {
+    write_changes_report(
+        &mut io::stdout().lock(),
+        results,
+        previous_results,
+        options,
+        cycle,
+        next_update,
+    )
+}
+
+/// Write only the instances that changed since the previous cycle, or a
+/// heartbeat line for cycle number `cycle` when nothing changed
+pub fn write_changes_report<W: Write>(
+    w: &mut W,
+    results: &[InstanceResults],
+    previous_results: &HashMap<String, InstanceResults>,
+    options: &ReportOptions,
+    cycle: usize,
+    next_update: DateTime<Utc>,
+) -> Result<(), MonitorError> {
+    let now = display_time(Utc::now(), options.timezone);
+    let next_update = display_time(next_update, options.timezone);
+    let changed: Vec<_> = results
+        .iter()
+        .filter(|r| r.has_changed_since(previous_results.get(&r.name)))
+        .collect();
+
+    if changed.is_empty() {
+        writeln!(w, "{}", heartbeat_line(cycle, now, next_update))?;
+        return Ok(());
+    }
+
+    let options = &options.fit_to(results);
+    writeln!(w, "\nCHANGES @ {now}")?;
+    write_table_header(w, options)?;
+    for result in changed {
+        writeln!(w, "{}", format_result_row(result, options))?;
+    }
+    Ok(())
+}
+
+/// Write the table column headers followed by a rule
+fn write_table_header<W: Write>(w: &mut W, options: &ReportOptions) -> io::Result<()> {
+    writeln!(w, "{}", table_header(options))?;
+    options.write_light_rule(w)
+}
+
+/// Format the table column headers
+pub(crate) fn table_header(options: &ReportOptions) -> String {
+    let mut header = format!(
+        "{:<name_width$} {:^19} {:^12} {:^18} {:^15} {:^5} {:^8} {:<13} {:<connection_width$}",
         "Instance Name",
         "Instance ID",
         "Instance Type",
@@ -34,123 +1059,824 @@ pub fn print_summary_report(
         "CSV",
         "Disk",
         "Process",
-        "Connection"
+        "Connection",
+        name_width = options.name_width + 2,
+        connection_width = options.connection_width,
     );
-    println!("{}", "-".repeat(135));
+    if options.show_memory {
+        header.push_str(&format!(" {:^10}", "Mem"));
+    }
+    if options.show_network {
+        header.push_str(&format!(" {:^11}", "Net"));
+    }
+    if options.show_rates {
+        header.push_str(&format!(" {:^19}", "Steps/min now/avg"));
+    }
+    if options.show_latency {
+        header.push_str(&format!(" {:^9}", "Latency"));
+    }
+    if options.show_endpoints {
+        header.push_str(" Endpoint");
+    }
+    header
+}
 
-    for result in results {
-        let instance_name = if result.name.len() > 20 {
-            format!("{}...", &result.name[..17])
-        } else {
-            result.name.clone()
-        };
+/// Format a single instance as a table row
+pub(crate) fn format_result_row(result: &InstanceResults, options: &ReportOptions) -> String {
+    let instance_name = if options.wide {
+        result.name.clone()
+    } else {
+        truncate(&result.name, options.name_width)
+    };
 
-        let current_eta_display = match &result.timestep_result {
-            Some(timestep) => match timestep.calculate_eta() {
-                Some(eta) => eta,
-                None => "N/A".to_string(),
-            },
-            None => "N/A".to_string(),
-        };
-
-        let (
-            timestep_display,
-            csv_count_display,
-            disk_display,
-            process_display,
-            connection_display,
-        ) = if let Some(error) = &result.connection_error {
-            let error_msg = if error.len() > 18 {
-                format!("{}...", &error[..15])
-            } else {
+    let current_eta_display = eta_display(result, options);
+
+    let (timestep_display, csv_count_display, disk_display, process_display, connection_display) =
+        if let Some(error) = &result.connection_error {
+            let error_msg = if options.wide {
                 error.clone()
+            } else {
+                truncate(error, options.error_width)
+            };
+            let status = if result.starting_up {
+                options.decorate("⏳", "Starting up")
+            } else {
+                options.decorate("❌", "Failed")
             };
             (
-                "❌ Failed".to_string(),
-                "❌ Failed".to_string(),
-                "❌ Failed".to_string(),
-                "❌ Failed".to_string(),
+                status.clone(),
+                status.clone(),
+                status.clone(),
+                status,
                 error_msg,
             )
         } else {
             let timestep = match &result.timestep_result {
-                Some(ts) => ts.to_string(),
-                None => "❌ Failed".to_string(),
+                Some(ts) if result.stale_timestep => {
+                    format!("{} (stale)", format_timestep(ts, options))
+                }
+                Some(ts) if ts.assumed_total => {
+                    format!("{} (assumed)", format_timestep(ts, options))
+                }
+                Some(ts) => format_timestep(ts, options),
+                None => options.decorate("❌", "Failed"),
             };
 
             let csv_count = match result.csv_count {
+                Some(count) if options.human => humanize_count(count.unsigned_abs().into()),
                 Some(count) => count.to_string(),
-                None => "❌ Failed".to_string(),
+                None => options.decorate("❌", "Failed"),
             };
 
             let disk = match &result.free_disk_space {
                 Some(space) => space.clone(),
-                None => "❌ Failed".to_string(),
+                None => options.decorate("❌", "Failed"),
             };
 
             let process = match &result.current_process {
                 Some(proc) => match proc.as_str() {
-                    "zcsvs" => "🟢 zcsvs".to_string(),
-                    "finalize" => "🟡 finalize".to_string(),
-                    "s3 sync" => "🔵 s3 sync".to_string(),
-                    "none" => "⚪ none".to_string(),
+                    "zcsvs" => options.decorate("🟢", "zcsvs"),
+                    "finalize" => options.decorate("🟡", "finalize"),
+                    "s3 sync" => options.decorate("🔵", "s3 sync"),
+                    "none" => options.decorate("⚪", "none"),
                     _ => proc.clone(),
                 },
-                None => "❌ Failed".to_string(),
+                None => options.decorate("❌", "Failed"),
             };
 
-            (timestep, csv_count, disk, process, "✅ Success".to_string())
+            (
+                timestep,
+                csv_count,
+                disk,
+                process,
+                options.decorate("✅", "Success"),
+            )
+        };
+
+    let mut row = format!(
+        "{:<name_width$} {:>19} {:>12} {:>18} {:>15} {:>5} {:>8} {:<13} {:<connection_width$}",
+        instance_name,
+        result.instance_id,
+        result.instance_type,
+        current_eta_display,
+        timestep_display,
+        csv_count_display,
+        disk_display,
+        process_display,
+        connection_display,
+        name_width = options.name_width + 2,
+        connection_width = options.connection_width,
+    );
+    if options.show_memory {
+        row.push_str(&format!(
+            " {:>10}",
+            format_memory(result.solver_rss_kb, result.mem_total_kb, options)
+        ));
+    }
+    if options.show_network {
+        row.push_str(&format!(
+            " {:>11}",
+            network::format_throughput(result.network_mb_per_sec)
+        ));
+    }
+    if options.show_rates {
+        row.push_str(&format!(
+            " {:>19}",
+            format_rates(result.current_rate, result.average_rate)
+        ));
+    }
+    if options.show_latency {
+        row.push_str(&format!(" {:>9}", format_latency(result.ssh_latency_ms)));
+    }
+    if options.show_endpoints {
+        row.push(' ');
+        row.push_str(&format_endpoint(result.endpoint.as_ref(), options));
+    }
+    row
+}
+
+/// Format the instantaneous and lifetime average step rates, e.g. "41.5 / 44.0"
+fn format_rates(current_rate: Option<f64>, average_rate: Option<f64>) -> String {
+    let rate = |rate: Option<f64>| rate.map_or("N/A".to_string(), |rate| format!("{rate:.1}"));
+    format!("{} / {}", rate(current_rate), rate(average_rate))
+}
+
+/// Format the SSH connection latency, e.g. "182 ms"
+pub fn format_latency(latency_ms: Option<u64>) -> String {
+    latency_ms.map_or("N/A".to_string(), |ms| format!("{ms} ms"))
+}
+
+/// Format the solver memory in GB, e.g. "112.4G", flagged above the threshold
+/// of the instance memory
+pub fn format_memory(
+    rss_kb: Option<u64>,
+    total_kb: Option<u64>,
+    options: &ReportOptions,
+) -> String {
+    let Some(rss_kb) = rss_kb else {
+        return "N/A".to_string();
+    };
+    let text = format!("{:.1}G", memory::kb_to_gb(rss_kb));
+    match total_kb {
+        Some(total_kb) if memory::is_memory_high(rss_kb, total_kb, options.memory_threshold) => {
+            if options.plain {
+                format!("{text} HIGH")
+            } else {
+                format!("⚠️ {text}")
+            }
+        }
+        _ => text,
+    }
+}
+
+/// Format the endpoint an instance was reached on, e.g. "private 10.0.1.5 | public ✓"
+pub(crate) fn format_endpoint(endpoint: Option<&Endpoint>, options: &ReportOptions) -> String {
+    let Some(endpoint) = endpoint else {
+        return "N/A".to_string();
+    };
+    let mut text = format!("{} {}", endpoint.kind, endpoint.ip);
+    if endpoint.fallback {
+        text.push_str(" (fallback)");
+    }
+    if let Some(reachable) = endpoint.other_reachable {
+        let other = match endpoint.kind {
+            IpKind::Public => IpKind::Private,
+            IpKind::Private => IpKind::Public,
+        };
+        let status = match (reachable, options.plain) {
+            (true, false) => "✓",
+            (false, false) => "✗",
+            (true, true) => "reachable",
+            (false, true) => "unreachable",
+        };
+        text.push_str(&format!(" | {other} {status}"));
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn output_is_plain_only_when_not_a_terminal_unless_overridden() {
+        assert!(!use_plain_output(true, None));
+        assert!(use_plain_output(false, None));
+        assert!(use_plain_output(true, Some(true)));
+        assert!(!use_plain_output(false, Some(false)));
+    }
+
+    /// Status column of a failed instance launched `age_secs` ago, with a two minute grace
+    fn failed_status(age_secs: u64) -> String {
+        let result = InstanceResults {
+            name: "node-1".to_string(),
+            connection_error: Some("Connection refused".to_string()),
+            starting_up: is_starting_up(
+                Some(Duration::from_secs(age_secs)),
+                Duration::from_secs(120),
+            ),
+            ..InstanceResults::default()
+        };
+        let options = ReportOptions {
+            plain: true,
+            ..ReportOptions::default()
         };
+        format_result_row(&result, &options)
+    }
+
+    #[test]
+    fn failures_within_the_grace_period_are_starting_up() {
+        let row = failed_status(30);
+        assert!(row.contains("Starting up"), "{row}");
+        assert!(!row.contains("Failed"), "{row}");
+    }
 
-        println!(
-            "{:<22} {:>19} {:>12} {:>13} {:>15} {:>5} {:>8} {:<13} {:<12}",
-            instance_name,
-            result.instance_id,
-            result.instance_type,
-            current_eta_display,
-            timestep_display,
-            csv_count_display,
-            disk_display,
-            process_display,
-            connection_display
+    #[test]
+    fn failures_after_the_grace_period_are_failed() {
+        let row = failed_status(300);
+        assert!(row.contains("Failed"), "{row}");
+        assert!(!row.contains("Starting up"), "{row}");
+        assert!(!is_starting_up(None, Duration::from_secs(120)));
+    }
+
+    fn named(name: &str) -> InstanceResults {
+        InstanceResults {
+            name: name.to_string(),
+            ..InstanceResults::default()
+        }
+    }
+
+    #[test]
+    fn known_etas_fall_in_their_buckets() {
+        assert_eq!(eta_bucket(30.0), 0);
+        assert_eq!(eta_bucket(60.0), 1);
+        assert_eq!(eta_bucket(5.0 * 60.0), 1);
+        assert_eq!(eta_bucket(6.0 * 60.0), 2);
+        assert_eq!(eta_bucket(23.9 * 60.0), 2);
+        assert_eq!(eta_bucket(48.0 * 60.0), 3);
+    }
+
+    #[test]
+    fn histogram_counts_the_latest_eta_of_the_current_instances() {
+        let results = [named("node-1"), named("node-2"), named("node-3")];
+        let instance_etas = HashMap::from([
+            ("node-1".to_string(), vec![600.0, 45.0]),
+            ("node-2".to_string(), vec![120.0]),
+            ("node-3".to_string(), vec![0.0]),
+            ("vanished".to_string(), vec![30.0]),
+        ]);
+        assert_eq!(
+            eta_histogram(&results, &instance_etas),
+            "  <1h    | # 1\n  1-6h   | # 1\n  6-24h  |  0\n  >24h   |  0\n"
         );
+        assert_eq!(eta_histogram(&[], &instance_etas), "");
     }
 
-    println!("{}", "-".repeat(135));
+    fn complete_running(process: &str) -> InstanceResults {
+        InstanceResults {
+            timestep_result: Some(TimeStep {
+                step: 1000,
+                total_step: 1000,
+                ..TimeStep::default()
+            }),
+            eta: Some(eta::COMPLETE.to_string()),
+            current_process: Some(process.to_string()),
+            ..named("node-1")
+        }
+    }
 
-    // Summary statistics
-    let total_instances = results.len();
-    let successful_connections = results
-        .iter()
-        .filter(|r| r.connection_error.is_none())
-        .count();
-    let zcsvs_count = results
-        .iter()
-        .filter(|r| r.current_process.as_ref() == Some(&"zcsvs".to_string()))
-        .count();
-    let finalize_count = results
-        .iter()
-        .filter(|r| r.current_process.as_ref() == Some(&"finalize".to_string()))
-        .count();
-    let s3_sync_count = results
-        .iter()
-        .filter(|r| r.current_process.as_ref() == Some(&"s3 sync".to_string()))
-        .count();
-    let idle_count = results
-        .iter()
-        .filter(|r| r.current_process.as_ref() == Some(&"none".to_string()))
-        .count();
+    #[test]
+    fn completion_status_shows_the_post_completion_phase() {
+        assert_eq!(
+            completion_status(Some("s3 sync"), false),
+            "Complete → s3 sync"
+        );
+        assert_eq!(
+            completion_status(Some("s3 sync"), true),
+            "Complete -> s3 sync"
+        );
+        assert_eq!(completion_status(Some("none"), false), "Complete (idle)");
+        assert_eq!(completion_status(None, false), "Complete");
+    }
 
-    println!(
-        "Summary: {} total instances | {} successful connections | {} zcsvs | {} finalize | {} s3 sync | {} idle",
-        total_instances,
-        successful_connections,
-        zcsvs_count,
-        finalize_count,
-        s3_sync_count,
-        idle_count
-    );
+    #[test]
+    fn idle_completion_is_shown_only_with_post_completion_phases() {
+        let options = ReportOptions::default();
+        assert_eq!(
+            eta_display(&complete_running("finalize"), &options),
+            "Complete → finalize"
+        );
+        assert_eq!(eta_display(&complete_running("none"), &options), "Complete");
 
-    println!("{}", "=".repeat(135));
-    Ok(())
+        let options = ReportOptions {
+            post_completion_phases: true,
+            ..options
+        };
+        assert_eq!(
+            eta_display(&complete_running("none"), &options),
+            "Complete (idle)"
+        );
+    }
+
+    #[test]
+    fn discovery_reports_the_matched_count_and_names() {
+        let discovered: Vec<_> = ["node-1", "node-2", "node-3"]
+            .iter()
+            .enumerate()
+            .map(|(i, name)| InstanceInfo {
+                instance_id: format!("i-{i}"),
+                name: name.to_string(),
+                instance_type: "c6g.4xlarge".to_string(),
+                ..InstanceInfo::default()
+            })
+            .collect();
+        let text = format_discovery(&discovered);
+        let mut lines = text.lines();
+        assert_eq!(lines.next(), Some("Matched 3 target instances"));
+        let names: Vec<_> = lines
+            .map(|line| line.split_whitespace().next().unwrap())
+            .collect();
+        assert_eq!(names, ["node-1", "node-2", "node-3"]);
+        assert_eq!(format_discovery(&[]), "Matched 0 target instances\n");
+    }
+
+    #[test]
+    fn multi_byte_names_are_truncated_on_char_boundaries() {
+        let name = "étude-vent-2ms-ürün-😀-long";
+        assert_eq!(truncate(name, 18), "étude-vent-2ms-...");
+        assert_eq!(truncate("ééé😀😀", 5), "ééé😀😀");
+        assert_eq!(truncate("ééé😀😀😀", 5), "éé...");
+    }
+
+    /// Report of a single running instance rendered with the options
+    fn render(options: &ReportOptions) -> String {
+        let mut out = Vec::new();
+        write_summary_report(
+            &mut out,
+            &[complete_running("zcsvs")],
+            &HashMap::new(),
+            options,
+            None,
+        )
+        .unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn each_border_style_draws_its_rule_characters() {
+        for (border, present, absent) in [
+            (BorderStyle::Ascii, ["===", "---"], ["═══", "───"]),
+            (BorderStyle::Unicode, ["═══", "───"], ["===", "---"]),
+        ] {
+            let report = render(&ReportOptions {
+                border,
+                ..ReportOptions::default()
+            });
+            for rule in present {
+                assert!(report.contains(rule), "{border:?} lacks {rule}");
+            }
+            for rule in absent {
+                assert!(!report.contains(rule), "{border:?} has {rule}");
+            }
+        }
+
+        let report = render(&ReportOptions {
+            border: BorderStyle::None,
+            ..ReportOptions::default()
+        });
+        for rule in ["===", "---", "═══", "───"] {
+            assert!(!report.contains(rule), "None has {rule}");
+        }
+    }
+
+    #[test]
+    fn footer_counts_are_colored_by_the_fleet_state() {
+        let failed = InstanceResults {
+            connection_error: Some("Connection refused".to_string()),
+            ..named("node-2")
+        };
+        let results = [complete_running("none"), failed];
+        let mut out = Vec::new();
+        let options = ReportOptions {
+            color: true,
+            ..ReportOptions::default()
+        };
+        write_summary_report(&mut out, &results, &HashMap::new(), &options, None).unwrap();
+        let report = String::from_utf8(out).unwrap();
+
+        let painted = |count: usize, color| count.to_string().with(color).to_string();
+        assert!(report.contains(&format!(
+            "{} successful connections",
+            painted(1, Color::Red)
+        )));
+        assert!(report.contains(&format!("{} idle", painted(1, Color::Yellow))));
+        assert!(report.contains(&format!("{} complete |", painted(1, Color::Green))));
+        // Zero counts are left uncolored
+        assert!(report.contains("| 0 finalize |"));
+    }
+
+    #[test]
+    fn colors_follow_no_color_and_plain_output() {
+        assert_eq!(connection_color(3, 3), Color::Green);
+        assert_eq!(connection_color(2, 3), Color::Red);
+        assert_eq!(count_color(0, Color::Yellow), None);
+        assert!(use_color(false, None));
+        assert!(use_color(false, Some("".as_ref())));
+        assert!(!use_color(false, Some("1".as_ref())));
+        assert!(!use_color(true, None));
+    }
+
+    #[test]
+    fn wide_mode_renders_long_names_and_errors_in_full() {
+        let name = "wind-tunnel-case-with-a-very-long-descriptive-name";
+        let error = "Connection timed out after 30 seconds while reaching the bastion";
+        let results = [
+            InstanceResults {
+                connection_error: Some(error.to_string()),
+                ..named(name)
+            },
+            named("node-1"),
+        ];
+        let options = ReportOptions {
+            plain: true,
+            ..ReportOptions::default()
+        };
+        let row = format_result_row(&results[0], &options);
+        assert!(!row.contains(name), "{row}");
+        assert!(!row.contains(error), "{row}");
+
+        let wide = ReportOptions {
+            wide: true,
+            ..options
+        }
+        .fit_to(&results);
+        assert_eq!(wide.name_width, name.chars().count());
+        assert_eq!(wide.error_width, error.chars().count());
+        let row = format_result_row(&results[0], &wide);
+        assert!(row.contains(name), "{row}");
+        assert!(row.contains(error), "{row}");
+    }
+
+    /// Changes report of `results` following `previous`, at cycle 7
+    fn render_changes(results: &[InstanceResults], previous: &[InstanceResults]) -> String {
+        let previous_results = previous
+            .iter()
+            .map(|r| (r.name.clone(), r.clone()))
+            .collect();
+        let next_update = Utc::now() + chrono::Duration::minutes(5);
+        let mut out = Vec::new();
+        let options = ReportOptions {
+            plain: true,
+            ..ReportOptions::default()
+        };
+        write_changes_report(
+            &mut out,
+            results,
+            &previous_results,
+            &options,
+            7,
+            next_update,
+        )
+        .unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn heartbeat_is_written_on_a_cycle_without_changes() {
+        let results = [complete_running("zcsvs")];
+        let report = render_changes(&results, &results);
+        assert!(
+            report.contains("cycle 7 completed, no changes, next update at"),
+            "{report}"
+        );
+        assert_eq!(report.lines().count(), 1);
+
+        let report = render_changes(&results, &[]);
+        assert!(!report.contains("no changes"), "{report}");
+        assert!(report.contains("CHANGES @"), "{report}");
+    }
+
+    #[test]
+    fn json_summary_aggregates_the_instances() {
+        let generated_at = Utc::now();
+        let results = [
+            complete_running("zcsvs"),
+            InstanceResults {
+                eta: Some(eta::STALLED.to_string()),
+                ..named("node-2")
+            },
+            InstanceResults {
+                connection_error: Some("Connection refused".to_string()),
+                ..named("node-3")
+            },
+        ];
+        let instance_etas = HashMap::from([("node-2".to_string(), vec![30.0])]);
+
+        let json =
+            serde_json::to_value(JsonReport::new(&results, &instance_etas, generated_at)).unwrap();
+
+        assert_eq!(
+            json["generated_at"],
+            serde_json::to_value(generated_at).unwrap()
+        );
+        let instances = json["instances"].as_array().unwrap();
+        assert_eq!(instances.len(), results.len());
+        let summary = &json["summary"];
+        assert_eq!(summary["total"], instances.len());
+        assert_eq!(summary["successful"], 2);
+        assert_eq!(summary["complete"], 1);
+        assert_eq!(summary["stalled"], 1);
+        assert_eq!(summary["fleet_eta"], "30m");
+    }
+
+    #[test]
+    fn header_names_the_account_when_known() {
+        let mut out = Vec::new();
+        write_summary_report(
+            &mut out,
+            &[complete_running("zcsvs")],
+            &HashMap::new(),
+            &ReportOptions::default(),
+            Some("123456789012"),
+        )
+        .unwrap();
+        let report = String::from_utf8(out).unwrap();
+        let title = report
+            .lines()
+            .find(|line| line.starts_with("SUMMARY REPORT @"))
+            .unwrap();
+        assert!(title.ends_with(" | AWS account 123456789012"), "{title}");
+
+        let now = display_time(Utc::now(), None);
+        assert_eq!(report_title(now, None), format!("SUMMARY REPORT @ {now}"));
+    }
+
+    #[test]
+    fn report_is_written_to_the_pager_stdin() {
+        let path = std::env::temp_dir().join(format!("ec2_monitor_pager_{}", std::process::id()));
+        let pager = [
+            "sh".to_string(),
+            "-c".to_string(),
+            "cat > \"$0\"".to_string(),
+            path.display().to_string(),
+        ];
+        let results = [complete_running("zcsvs")];
+        let options = ReportOptions {
+            plain: true,
+            ..ReportOptions::default()
+        };
+        page_summary_report_with(&pager, &results, &HashMap::new(), &options, None).unwrap();
+
+        let paged = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(paged.contains("SUMMARY REPORT @"), "{paged}");
+        assert!(paged.contains("node-1"), "{paged}");
+    }
+
+    #[test]
+    fn counts_are_humanized_with_one_decimal() {
+        assert_eq!(humanize_count(999), "999");
+        assert_eq!(humanize_count(1500), "1.5K");
+        assert_eq!(humanize_count(2_400_000), "2.4M");
+        assert_eq!(humanize_count(999_960), "1.0M");
+        assert_eq!(humanize_count(3_000_000_000_000), "3000.0G");
+    }
+
+    #[test]
+    fn campaign_summary_aggregates_the_history_of_each_instance() {
+        use chrono::TimeZone;
+
+        let sample = |minute: i64, step: usize| HistorySample {
+            timestamp: Utc.with_ymd_and_hms(2024, 5, 1, 12, 0, 0).unwrap()
+                + chrono::Duration::minutes(minute),
+            step,
+            total_step: 1000,
+            time: step as f64 / 100.0,
+            eta_minutes: None,
+        };
+        let history = HashMap::from([
+            (
+                "node-2".to_string(),
+                vec![sample(0, 200), sample(60, 500), sample(120, 800)],
+            ),
+            ("node-1".to_string(), vec![sample(0, 0), sample(100, 1000)]),
+            ("node-3".to_string(), vec![sample(0, 50)]),
+        ]);
+
+        let summary = campaign_summary(&history);
+        let rows: Vec<Vec<_>> = summary
+            .lines()
+            .skip(2)
+            .map(|line| line.split_whitespace().collect())
+            .collect();
+        assert_eq!(
+            rows,
+            [
+                vec![
+                    "node-1",
+                    "1000/1000",
+                    "(+1000)",
+                    "1h",
+                    "40m",
+                    "10.0",
+                    "Complete"
+                ],
+                vec![
+                    "node-2",
+                    "800/1000",
+                    "(+600)",
+                    "2h",
+                    "0m",
+                    "5.0",
+                    "Incomplete"
+                ],
+                vec!["node-3", "50/1000", "(+0)", "0m", "N/A", "Incomplete"],
+                vec!["1", "of", "3", "instances", "complete"],
+            ]
+        );
+        assert!(summary.starts_with("CAMPAIGN REPORT: 3 instances\n"));
+    }
+
+    #[test]
+    fn rows_are_grouped_by_a_custom_tag_with_subtotals() {
+        let meshed = |name: &str, mesh: Option<&str>| InstanceResults {
+            tags: mesh
+                .map(|mesh| HashMap::from([("Mesh".to_string(), mesh.to_string())]))
+                .unwrap_or_default(),
+            ..named(name)
+        };
+        let results = [
+            meshed("node-1", Some("fine")),
+            InstanceResults {
+                tags: HashMap::from([("Mesh".to_string(), "coarse".to_string())]),
+                ..complete_running("zcsvs")
+            },
+            meshed("node-3", None),
+            meshed("node-4", Some("fine")),
+        ];
+        let instance_etas = HashMap::from([
+            ("node-1".to_string(), vec![30.0]),
+            ("node-4".to_string(), vec![90.0]),
+        ]);
+
+        let groups = group_by_tag(&results, "Mesh");
+        let names: Vec<(&str, Vec<&str>)> = groups
+            .iter()
+            .map(|(value, group)| (*value, group.iter().map(|r| r.name.as_str()).collect()))
+            .collect();
+        assert_eq!(
+            names,
+            [
+                (UNTAGGED, vec!["node-3"]),
+                ("coarse", vec!["node-1"]),
+                ("fine", vec!["node-1", "node-4"]),
+            ]
+        );
+        assert_eq!(
+            group_subtotal(&groups[1].1, &instance_etas),
+            "  Subtotal: 1 instances | 1 complete | ETA Complete"
+        );
+        assert_eq!(
+            group_subtotal(&groups[2].1, &instance_etas),
+            "  Subtotal: 2 instances | 0 complete | ETA 1h 30m"
+        );
+    }
+
+    #[test]
+    fn top_rows_render_with_a_more_line() {
+        let mut results: Vec<_> = (1..=10)
+            .map(|i| InstanceResults {
+                eta_minutes: Some(60.0),
+                ..named(&format!("node-{i:02}"))
+            })
+            .collect();
+        // The failed instance, then the closest ETA, come first
+        results[6].connection_error = Some("Connection refused".to_string());
+        results[6].eta_minutes = None;
+        results[8].eta_minutes = Some(10.0);
+        let options = ReportOptions {
+            plain: true,
+            top: Some(5),
+            ..ReportOptions::default()
+        };
+        let mut out = Vec::new();
+        write_summary_report(&mut out, &results, &HashMap::new(), &options, None).unwrap();
+        let report = String::from_utf8(out).unwrap();
+
+        let rows: Vec<_> = report
+            .lines()
+            .filter(|line| line.starts_with("node-"))
+            .map(|line| line.split_whitespace().next().unwrap())
+            .collect();
+        assert_eq!(
+            rows,
+            ["node-07", "node-09", "node-01", "node-02", "node-03"]
+        );
+        assert_eq!(
+            report
+                .lines()
+                .filter(|line| *line == "... and 5 more")
+                .count(),
+            1
+        );
+        assert_eq!(top_rows(&results, Some(10)).1, 0);
+        assert_eq!(top_rows(&results, None).0.len(), 10);
+    }
+
+    #[test]
+    fn top_rows_keep_the_most_relevant_results() {
+        let with_eta = |name: &str, minutes: f64| InstanceResults {
+            eta_minutes: Some(minutes),
+            ..named(name)
+        };
+        let results = [
+            with_eta("node-a", 90.0),
+            InstanceResults {
+                eta: Some(eta::STALLED_DISK_FULL.to_string()),
+                ..named("node-b")
+            },
+            with_eta("node-c", 30.0),
+            named("node-d"),
+            InstanceResults {
+                connection_error: Some("Connection refused".to_string()),
+                ..named("node-e")
+            },
+            with_eta("node-f", 30.0),
+            InstanceResults {
+                diverged: true,
+                eta: Some(eta::DIVERGED.to_string()),
+                ..named("node-g")
+            },
+        ];
+
+        let (shown, hidden) = top_rows(&results, Some(5));
+        let names: Vec<_> = shown.iter().map(|r| r.name.as_str()).collect();
+        assert_eq!(names, ["node-b", "node-e", "node-g", "node-c", "node-f"]);
+        assert_eq!(hidden, 2);
+    }
+
+    #[test]
+    fn assumed_totals_are_marked_in_the_row() {
+        let result = InstanceResults {
+            timestep_result: Some(TimeStep {
+                step: 100,
+                total_step: 18_000,
+                assumed_total: true,
+                ..TimeStep::default()
+            }),
+            ..named("wake_calm")
+        };
+        let row = format_result_row(&result, &ReportOptions::default());
+        assert!(row.contains("(assumed)"), "{row}");
+    }
+
+    #[test]
+    fn one_instant_renders_in_each_display_timezone() {
+        use chrono::TimeZone;
+
+        let instant = Utc.with_ymd_and_hms(2024, 1, 15, 12, 30, 0).unwrap();
+        let sao_paulo = display_time(instant, Some(chrono_tz::America::Sao_Paulo));
+        let tokyo = display_time(instant, Some(chrono_tz::Asia::Tokyo));
+        assert_eq!(sao_paulo.to_string(), "2024-01-15 09:30:00 -03:00");
+        assert_eq!(tokyo.to_string(), "2024-01-15 21:30:00 +09:00");
+        assert_eq!(sao_paulo, tokyo);
+    }
+
+    #[test]
+    fn eta_column_follows_the_process_of_the_instance() {
+        let options = ReportOptions::default();
+        let stale_eta = |process: &str| InstanceResults {
+            eta: Some("12m".to_string()),
+            ..complete_running(process)
+        };
+        assert_eq!(
+            eta_display(&stale_eta("s3 sync"), &options),
+            "Complete → s3 sync"
+        );
+        assert_eq!(eta_display(&stale_eta("zcsvs"), &options), "12m");
+
+        let syncing = InstanceResults {
+            output_eta_minutes: Some(42.0),
+            ..complete_running("s3 sync")
+        };
+        assert_eq!(eta_display(&syncing, &options), "s3 sync 42m");
+
+        let solving = InstanceResults {
+            timestep_result: Some(TimeStep {
+                step: 500,
+                total_step: 1000,
+                ..TimeStep::default()
+            }),
+            eta: Some("1h 0m".to_string()),
+            ..complete_running("s3 sync")
+        };
+        assert_eq!(eta_display(&solving, &options), "1h 0m");
+
+        let terminated = InstanceResults {
+            terminated: true,
+            ..complete_running("s3 sync")
+        };
+        assert_eq!(eta_display(&terminated, &options), eta::COMPLETE_TERMINATED);
+        assert_eq!(eta_display(&named("node-2"), &options), "N/A");
+    }
 }
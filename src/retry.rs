@@ -0,0 +1,184 @@
+//! Retries of transient instance failures under a fleet-wide budget
+
+use std::future::Future;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+use crate::MonitorError;
+
+/// Delay before the first retry of a failed attempt, doubled for every further retry
+pub const DEFAULT_RETRY_DELAY: Duration = Duration::from_secs(2);
+
+/// Number of retries left for the whole fleet in the current cycle
+#[derive(Debug)]
+pub struct RetryBudget {
+    remaining: AtomicUsize,
+}
+
+impl RetryBudget {
+    /// Create a budget allowing `retries` retries in total
+    pub fn new(retries: usize) -> Self {
+        Self {
+            remaining: AtomicUsize::new(retries),
+        }
+    }
+
+    /// Take one retry from the budget, returning `false` once it is exhausted
+    pub fn try_consume(&self) -> bool {
+        self.remaining
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| n.checked_sub(1))
+            .is_ok()
+    }
+
+    /// Number of retries left
+    pub fn remaining(&self) -> usize {
+        self.remaining.load(Ordering::SeqCst)
+    }
+}
+
+/// Check whether an error is transient and worth retrying, e.g. a connection
+/// refused while sshd starts or a timeout, unlike a missing key or a failed
+/// authentication
+pub fn is_retryable(error: &MonitorError) -> bool {
+    matches!(
+        error,
+        MonitorError::SshConnection(_)
+            | MonitorError::Io(_)
+            | MonitorError::DirectConnect { .. }
+            | MonitorError::ProxyConnect { .. }
+            | MonitorError::HandshakeTimeout { .. }
+            | MonitorError::ConnectionTimeout { .. }
+    )
+}
+
+/// Delay before retry number `retry`, counted from 0: `base_delay` doubled
+/// for every previous retry
+pub fn backoff_delay(base_delay: Duration, retry: usize) -> Duration {
+    base_delay.saturating_mul(1 << retry.min(16))
+}
+
+/// Run an attempt, retrying transient failures up to `max_retries` times with
+/// an exponential backoff from `base_delay` while the shared budget allows it
+///
+/// The error of a retried attempt notes the number of attempts made.
+pub async fn with_retries<T, F, Fut>(
+    budget: &RetryBudget,
+    max_retries: usize,
+    base_delay: Duration,
+    mut attempt: F,
+) -> Result<T, MonitorError>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, MonitorError>>,
+{
+    let mut retries = 0;
+    loop {
+        match attempt().await {
+            Err(e) if retries < max_retries && is_retryable(&e) && budget.try_consume() => {
+                tokio::time::sleep(backoff_delay(base_delay, retries)).await;
+                retries += 1;
+            }
+            Err(e) if retries > 0 => {
+                return Err(MonitorError::AfterAttempts {
+                    attempts: retries + 1,
+                    source: Box::new(e),
+                });
+            }
+            outcome => return outcome,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn timeout() -> MonitorError {
+        MonitorError::HandshakeTimeout { secs: 10 }
+    }
+
+    #[tokio::test]
+    async fn the_budget_caps_the_retries_of_the_whole_fleet() {
+        let budget = RetryBudget::new(3);
+        let attempts = AtomicUsize::new(0);
+        for _ in 0..4 {
+            let outcome: Result<(), _> = with_retries(&budget, 5, Duration::ZERO, || async {
+                attempts.fetch_add(1, Ordering::SeqCst);
+                Err(timeout())
+            })
+            .await;
+            assert!(outcome.is_err());
+        }
+        // One attempt per instance, plus the three retries of the budget
+        assert_eq!(attempts.load(Ordering::SeqCst), 4 + 3);
+        assert_eq!(budget.remaining(), 0);
+    }
+
+    #[test]
+    fn an_exhausted_budget_refuses_retries() {
+        let budget = RetryBudget::new(1);
+        assert!(budget.try_consume());
+        assert!(!budget.try_consume());
+        assert_eq!(budget.remaining(), 0);
+    }
+
+    #[test]
+    fn only_transient_errors_are_retryable() {
+        let refused = || std::io::Error::from(std::io::ErrorKind::ConnectionRefused);
+        for error in [
+            MonitorError::Io(refused()),
+            MonitorError::DirectConnect {
+                target: "10.0.1.5:22".to_string(),
+                source: refused(),
+            },
+            MonitorError::ProxyConnect {
+                proxy: "bastion:1080".to_string(),
+                target: "10.0.1.5:22".to_string(),
+                source: refused(),
+            },
+            timeout(),
+            MonitorError::ConnectionTimeout {
+                ip: "10.0.1.5".to_string(),
+                port: 22,
+                elapsed: Duration::from_secs(10),
+            },
+        ] {
+            assert!(is_retryable(&error), "{error}");
+        }
+        for error in [
+            MonitorError::KeyFileNotFound {
+                path: "key.pem".to_string(),
+            },
+            MonitorError::AuthenticationFailed,
+            MonitorError::HostKeyMismatch {
+                host: "10.0.1.5".to_string(),
+            },
+            MonitorError::SshCommandFailed {
+                code: 2,
+                stderr: "No such file".to_string(),
+            },
+        ] {
+            assert!(!is_retryable(&error), "{error}");
+        }
+    }
+
+    #[tokio::test]
+    async fn non_retryable_errors_are_returned_at_once() {
+        let budget = RetryBudget::new(10);
+        let attempts = AtomicUsize::new(0);
+        let outcome: Result<(), _> = with_retries(&budget, 3, Duration::ZERO, || async {
+            attempts.fetch_add(1, Ordering::SeqCst);
+            Err(MonitorError::AuthenticationFailed)
+        })
+        .await;
+        assert!(matches!(outcome, Err(MonitorError::AuthenticationFailed)));
+        assert_eq!(attempts.load(Ordering::SeqCst), 1);
+
+        let outcome: Result<(), _> =
+            with_retries(&budget, 3, Duration::ZERO, || async { Err(timeout()) }).await;
+        assert!(matches!(
+            outcome,
+            Err(MonitorError::AfterAttempts { attempts: 4, .. })
+        ));
+    }
+}
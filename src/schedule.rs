@@ -0,0 +1,196 @@
+//! Scheduling of the monitoring cycles and of the instance probes within a cycle
+
+use rand::Rng;
+use std::time::{Duration, Instant};
+
+use crate::TimeStep;
+
+/// Nominal time between the starts of two monitoring cycles
+pub const REFRESH_INTERVAL: Duration = Duration::from_secs(360);
+
+/// Time to sleep after a cycle so that cycles start every `interval`,
+/// or no time at all if the cycle took longer than that
+pub fn sleep_duration(interval: Duration, cycle_duration: Duration) -> Duration {
+    interval.saturating_sub(cycle_duration)
+}
+
+/// Consecutive cycles longer than the refresh interval after which the monitor
+/// is reported as falling behind
+pub const OVERRUN_WARN_CYCLES: usize = 3;
+
+/// Consecutive cycles that took longer than `interval`, counting on from
+/// `previous` overruns
+pub fn overrun_cycles(previous: usize, interval: Duration, cycle_duration: Duration) -> usize {
+    if cycle_duration > interval {
+        previous + 1
+    } else {
+        0
+    }
+}
+
+/// Latest rendered report, redrawn at most once per render interval so that a
+/// short probe interval doesn't make the screen flicker
+#[derive(Debug, Default)]
+pub struct RenderThrottle {
+    last_render: Option<Instant>,
+    pending: Option<String>,
+}
+
+impl RenderThrottle {
+    /// Buffer a newly rendered report, replacing any undrawn one, and return
+    /// it if it can be drawn at `now`
+    pub fn submit(
+        &mut self,
+        report: String,
+        now: Instant,
+        min_interval: Duration,
+    ) -> Option<String> {
+        self.pending = Some(report);
+        self.take_due(now, min_interval)
+    }
+
+    /// Take the buffered report if the screen was last drawn at least
+    /// `min_interval` before `now`
+    pub fn take_due(&mut self, now: Instant, min_interval: Duration) -> Option<String> {
+        let due = self.last_render.is_none_or(|at| now >= at + min_interval);
+        if self.pending.is_none() || !due {
+            return None;
+        }
+        self.last_render = Some(now);
+        self.pending.take()
+    }
+
+    /// Time the buffered report can be drawn, `None` without a buffered report
+    pub fn due_at(&self, min_interval: Duration) -> Option<Instant> {
+        self.pending.as_ref()?;
+        Some(
+            self.last_render
+                .map_or_else(Instant::now, |at| at + min_interval),
+        )
+    }
+}
+
+/// Minutes elapsed between two observations of an instance's step
+pub fn elapsed_minutes(previous: &TimeStep, current: &TimeStep) -> Option<f64> {
+    let elapsed = current.observed_at? - previous.observed_at?;
+    let minutes = elapsed.num_milliseconds() as f64 / 60_000.0;
+    (minutes > 0.0).then_some(minutes)
+}
+
+/// Start delays of `count` probes, each drawn by `delay_fn` from zero up to `max_jitter`
+pub fn start_delays(
+    count: usize,
+    max_jitter: Duration,
+    mut delay_fn: impl FnMut(Duration) -> Duration,
+) -> Vec<Duration> {
+    (0..count)
+        .map(|_| {
+            if max_jitter.is_zero() {
+                Duration::ZERO
+            } else {
+                delay_fn(max_jitter).min(max_jitter)
+            }
+        })
+        .collect()
+}
+
+/// Uniformly random delay from zero up to `max`
+pub fn random_delay(max: Duration) -> Duration {
+    let millis = max.as_millis().min(u64::MAX as u128) as u64;
+    Duration::from_millis(rand::thread_rng().gen_range(0..=millis))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn jitter_spreads_the_start_times() {
+        // Fractions of the maximum jitter handed out in turn
+        let mut fractions = [0.0, 0.25, 0.5, 0.75, 2.0].into_iter();
+        let delays = start_delays(5, Duration::from_secs(60), |max| {
+            max.mul_f64(fractions.next().unwrap())
+        });
+        assert_eq!(
+            delays,
+            [0, 15, 30, 45, 60].map(Duration::from_secs),
+            "delays are capped at the maximum jitter"
+        );
+    }
+
+    #[test]
+    fn no_jitter_starts_every_probe_at_once() {
+        let delays = start_delays(3, Duration::ZERO, |_| unreachable!());
+        assert_eq!(delays, [Duration::ZERO; 3]);
+    }
+
+    #[test]
+    fn random_delays_stay_within_the_jitter() {
+        let max = Duration::from_millis(500);
+        assert!((0..100).all(|_| random_delay(max) <= max));
+    }
+
+    #[test]
+    fn sleep_makes_up_the_rest_of_the_interval() {
+        let interval = Duration::from_secs(360);
+        assert_eq!(
+            sleep_duration(interval, Duration::from_secs(40)),
+            Duration::from_secs(320)
+        );
+        assert_eq!(sleep_duration(interval, interval), Duration::ZERO);
+        assert_eq!(
+            sleep_duration(interval, Duration::from_secs(500)),
+            Duration::ZERO
+        );
+    }
+
+    #[test]
+    fn overruns_warn_after_consecutive_slow_cycles() {
+        let interval = Duration::from_secs(360);
+        let mut overruns = 0;
+        let mut warned = Vec::new();
+        for secs in [300, 400, 380, 500, 361, 200, 700] {
+            overruns = overrun_cycles(overruns, interval, Duration::from_secs(secs));
+            warned.push(overruns == OVERRUN_WARN_CYCLES);
+        }
+        assert_eq!(overruns, 1);
+        assert_eq!(warned, [false, false, false, true, false, false, false]);
+        assert_eq!(overrun_cycles(2, interval, interval), 0);
+    }
+
+    #[test]
+    fn frequent_reports_are_drawn_at_most_once_per_render_interval() {
+        let min_interval = Duration::from_secs(30);
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+        let mut throttle = RenderThrottle::default();
+
+        assert!(throttle.due_at(min_interval).is_none());
+        assert_eq!(
+            throttle.submit("cycle 1".to_string(), at(0), min_interval),
+            Some("cycle 1".to_string())
+        );
+        assert_eq!(
+            throttle.submit("cycle 2".to_string(), at(10), min_interval),
+            None
+        );
+        assert_eq!(
+            throttle.submit("cycle 3".to_string(), at(20), min_interval),
+            None
+        );
+        assert_eq!(throttle.due_at(min_interval), Some(at(30)));
+        assert_eq!(throttle.take_due(at(29), min_interval), None);
+
+        // Only the latest buffered report is drawn once due
+        assert_eq!(
+            throttle.take_due(at(30), min_interval),
+            Some("cycle 3".to_string())
+        );
+        assert_eq!(throttle.take_due(at(90), min_interval), None);
+        assert!(throttle.due_at(min_interval).is_none());
+        assert_eq!(
+            throttle.submit("cycle 4".to_string(), at(90), min_interval),
+            Some("cycle 4".to_string())
+        );
+    }
+}
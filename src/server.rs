@@ -0,0 +1,154 @@
+//! Minimal HTTP endpoint exposing the latest fleet summary as JSON
+//!
+//! `GET /summary` returns the aggregates of the latest monitoring cycle, cheap
+//! enough to be polled from a phone.
+
+use std::net::SocketAddr;
+use std::sync::{Arc, RwLock};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tracing::{info, warn};
+
+use crate::MonitorError;
+use crate::report::FleetSummary;
+
+/// Fleet summary shared between the monitoring loop and the HTTP endpoint
+pub type SharedSummary = Arc<RwLock<FleetSummary>>;
+
+/// Serve the fleet summary on `addr` until the task is dropped
+pub async fn serve_summary(addr: SocketAddr, summary: SharedSummary) -> Result<(), MonitorError> {
+    let listener = TcpListener::bind(addr).await?;
+    info!("Serving fleet summary on http://{addr}/summary");
+
+    loop {
+        let (stream, _) = listener.accept().await?;
+        let summary = summary.clone();
+        tokio::spawn(async move {
+            if let Err(e) = handle_connection(stream, &summary).await {
+                warn!("Summary request failed: {e}");
+            }
+        });
+    }
+}
+
+/// Answer a single HTTP request
+async fn handle_connection(
+    mut stream: TcpStream,
+    summary: &SharedSummary,
+) -> Result<(), MonitorError> {
+    let mut buffer = [0u8; 1024];
+    let n = stream.read(&mut buffer).await?;
+    let request = String::from_utf8_lossy(&buffer[..n]);
+    let request_line = request.lines().next().unwrap_or_default();
+
+    let response = match route(request_line) {
+        Route::Summary => {
+            let body = {
+                let summary = summary.read().unwrap_or_else(|e| e.into_inner());
+                serde_json::to_string(&*summary).map_err(|e| MonitorError::Json {
+                    reason: e.to_string(),
+                })?
+            };
+            http_response("200 OK", "application/json", &body)
+        }
+        Route::NotFound => http_response("404 Not Found", "text/plain", "not found"),
+        Route::MethodNotAllowed => {
+            http_response("405 Method Not Allowed", "text/plain", "method not allowed")
+        }
+    };
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await?;
+    Ok(())
+}
+
+/// Requests understood by the endpoint
+#[derive(Debug, PartialEq, Eq)]
+enum Route {
+    Summary,
+    NotFound,
+    MethodNotAllowed,
+}
+
+/// Route an HTTP request line such as `GET /summary HTTP/1.1`
+fn route(request_line: &str) -> Route {
+    let mut parts = request_line.split_whitespace();
+    let (Some(method), Some(path)) = (parts.next(), parts.next()) else {
+        return Route::NotFound;
+    };
+    if method != "GET" {
+        return Route::MethodNotAllowed;
+    }
+    match path.split('?').next() {
+        Some("/summary") => Route::Summary,
+        _ => Route::NotFound,
+    }
+}
+
+/// Build a complete HTTP/1.1 response
+fn http_response(status: &str, content_type: &str, body: &str) -> String {
+    format!(
+        "HTTP/1.1 {status}\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::eta;
+    use crate::types::{InstanceResults, TimeStep};
+    use std::collections::HashMap;
+
+    fn instance(name: &str, step: usize, eta: Option<&str>) -> InstanceResults {
+        InstanceResults {
+            name: name.to_string(),
+            timestep_result: Some(TimeStep {
+                step,
+                total_step: 1000,
+                ..TimeStep::default()
+            }),
+            eta: eta.map(str::to_string),
+            ..InstanceResults::default()
+        }
+    }
+
+    #[test]
+    fn summary_json_has_the_fleet_aggregates_of_the_cycle() {
+        let results = [
+            instance("node-1", 1000, Some(eta::COMPLETE)),
+            instance("node-2", 500, Some(eta::STALLED)),
+            instance("node-3", 250, Some("1h 30m")),
+        ];
+        let instance_etas = HashMap::from([
+            ("node-2".to_string(), vec![30.0]),
+            ("node-3".to_string(), vec![60.0, 90.0, 120.0]),
+        ]);
+
+        let json =
+            serde_json::to_value(FleetSummary::from_results(&results, &instance_etas)).unwrap();
+
+        let mut keys: Vec<_> = json.as_object().unwrap().keys().cloned().collect();
+        keys.sort();
+        assert_eq!(
+            keys,
+            [
+                "complete_count",
+                "fleet_eta",
+                "fleet_progress_percent",
+                "stalled_count"
+            ]
+        );
+        assert_eq!(json["fleet_progress_percent"], 58.3);
+        assert_eq!(json["complete_count"], 1);
+        assert_eq!(json["stalled_count"], 1);
+        assert_eq!(json["fleet_eta"], "1h 30m");
+    }
+
+    #[test]
+    fn only_get_summary_is_routed_to_the_summary() {
+        assert_eq!(route("GET /summary HTTP/1.1"), Route::Summary);
+        assert_eq!(route("GET /summary?t=1 HTTP/1.1"), Route::Summary);
+        assert_eq!(route("GET / HTTP/1.1"), Route::NotFound);
+        assert_eq!(route("POST /summary HTTP/1.1"), Route::MethodNotAllowed);
+    }
+}
@@ -0,0 +1,207 @@
+//! Parsing of solver files and logs fetched from the instances
+
+use chrono::{DateTime, NaiveDateTime, Utc};
+
+/// Read a numeric entry such as `endTime 100;` from an OpenFOAM dictionary,
+/// ignoring comments and surrounding whitespace
+pub fn parse_dict_entry(contents: &str, key: &str) -> Option<f64> {
+    contents.lines().find_map(|line| {
+        let line = line.split("//").next().unwrap_or_default();
+        let line = strip_block_comments(line);
+        let mut tokens = line.split_whitespace();
+        if tokens.next()? != key {
+            return None;
+        }
+        let value = tokens.next()?.trim_end_matches(';');
+        value.parse::<f64>().ok()
+    })
+}
+
+/// Parse a simulated time such as `125`, `1.25e+02` or the locale-formatted
+/// `1,25E2`, accepting comma decimals and `e` or `E` exponents
+pub fn parse_time(text: &str) -> Result<f64, std::num::ParseFloatError> {
+    text.trim().replace(',', ".").parse::<f64>()
+}
+
+/// Value of a labelled number such as `: Time = 12.5` or `:t=12.5`, starting
+/// at its first digit, sign or decimal point, `None` without one
+pub fn labelled_value(text: &str) -> Option<&str> {
+    text.find(|c: char| c.is_ascii_digit() || matches!(c, '-' | '+' | '.'))
+        .map(|start| &text[start..])
+}
+
+/// Remove inline `/* ... */` comments from a single line
+fn strip_block_comments(line: &str) -> String {
+    let mut stripped = String::new();
+    let mut rest = line;
+    while let Some(start) = rest.find("/*") {
+        stripped.push_str(&rest[..start]);
+        rest = match rest[start..].find("*/") {
+            Some(end) => &rest[start + end + 2..],
+            None => "",
+        };
+    }
+    stripped.push_str(rest);
+    stripped
+}
+
+/// Derive the total number of steps from a controlDict
+///
+/// `endTime` is given in simulated time, so it is converted to steps with `deltaT`
+/// when available; otherwise it is taken as a step count
+pub fn total_step_from_control_dict(contents: &str) -> Option<usize> {
+    let end_time = parse_dict_entry(contents, "endTime")?;
+    let steps = match parse_dict_entry(contents, "deltaT") {
+        Some(delta_t) if delta_t > 0.0 => end_time / delta_t,
+        _ => end_time,
+    };
+    (steps.is_finite() && steps > 0.0).then(|| steps.round() as usize)
+}
+
+/// Count the log lines containing any of the warning patterns
+pub fn count_warnings(log_tail: &str, patterns: &[String]) -> usize {
+    log_tail
+        .lines()
+        .filter(|line| {
+            patterns
+                .iter()
+                .any(|pattern| line.contains(pattern.as_str()))
+        })
+        .count()
+}
+
+/// Quote a string for a POSIX shell command line
+pub fn shell_quote(text: &str) -> String {
+    format!("'{}'", text.replace('\'', "'\\''"))
+}
+
+/// Keywords of the residual and field lines whose `nan` or `inf` values reveal a divergence
+const DIVERGENCE_CONTEXTS: [&str; 6] = [
+    "residual",
+    "courant",
+    "continuity",
+    "bounding",
+    "min(",
+    "max(",
+];
+
+/// Check whether a solver log line reports a `nan` or `inf` residual or field value,
+/// ignoring other mentions such as "inflow" or "Reading info"
+pub fn is_divergence_line(line: &str) -> bool {
+    let lower = line.to_ascii_lowercase();
+    DIVERGENCE_CONTEXTS
+        .iter()
+        .any(|context| lower.contains(context))
+        && lower
+            .split(|c: char| c.is_whitespace() || matches!(c, ',' | '=' | ':' | ';' | '(' | ')'))
+            .map(|token| token.trim_start_matches(['-', '+']))
+            .any(|token| matches!(token, "nan" | "inf" | "infinity"))
+}
+
+/// Check whether a solver log tail shows a divergence
+pub fn has_diverged(log_tail: &str) -> bool {
+    log_tail.lines().any(is_divergence_line)
+}
+
+/// Parse the earliest start time of `ps -o lstart=`, one process per line such
+/// as "Thu Oct 16 10:23:45 2026", read as UTC like the clock of the instances
+pub fn parse_lstart(output: &str) -> Option<DateTime<Utc>> {
+    output
+        .lines()
+        .filter_map(|line| NaiveDateTime::parse_from_str(line.trim(), "%a %b %e %H:%M:%S %Y").ok())
+        .min()
+        .map(|start| start.and_utc())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    const CONTROL_DICT: &str = "\
+application     simpleFoam;
+startTime       0;
+endTime         120;   // seconds of simulated time
+/* deltaT 1; */
+deltaT          0.005;
+";
+
+    #[test]
+    fn control_dict_end_time_is_parsed_ignoring_comments() {
+        assert_eq!(parse_dict_entry(CONTROL_DICT, "endTime"), Some(120.0));
+        assert_eq!(parse_dict_entry(CONTROL_DICT, "deltaT"), Some(0.005));
+        assert_eq!(parse_dict_entry(CONTROL_DICT, "writeInterval"), None);
+    }
+
+    #[test]
+    fn control_dict_total_step_is_the_end_time_over_the_time_step() {
+        assert_eq!(total_step_from_control_dict(CONTROL_DICT), Some(24_000));
+        assert_eq!(total_step_from_control_dict("endTime 18000;"), Some(18_000));
+        assert_eq!(total_step_from_control_dict("deltaT 0.005;"), None);
+    }
+
+    #[test]
+    fn warnings_of_any_pattern_are_counted_once_per_line() {
+        let log_tail = "\
+Time = 12.5
+--> FOAM Warning : in file fvSchemes
+Courant Number mean: 0.2 max: 0.9
+--> FOAM Warning : Courant number exceeds 1
+smoothSolver:  Solving for Ux, Initial residual = 0.001
+";
+        let patterns = crate::MonitorConfig::default().warning_patterns;
+        assert_eq!(count_warnings(log_tail, &patterns), 2);
+        assert_eq!(count_warnings(log_tail, &[]), 0);
+    }
+
+    #[test]
+    fn nan_and_inf_residuals_and_fields_are_divergences() {
+        for line in [
+            "smoothSolver:  Solving for Ux, Initial residual = nan, Final residual = nan",
+            "GAMG:  Solving for p, Initial residual = -inf, Final residual = 1",
+            "time step continuity errors : sum local = NaN, global = 1e-5",
+            "bounding k, min: -Infinity max: 2.5 average: 0.1",
+            "Courant Number mean: inf max: inf",
+        ] {
+            assert!(is_divergence_line(line), "{line}");
+        }
+    }
+
+    #[test]
+    fn other_mentions_of_nan_and_inf_are_not_divergences() {
+        for line in [
+            "Reading info from inflow boundary",
+            "smoothSolver:  Solving for Ux, Initial residual = 0.001, Final residual = 1e-6",
+            "Warning: inf and nan checks enabled",
+            "Patch infinity_wall: min(p) = 0.5",
+        ] {
+            assert!(!is_divergence_line(line), "{line}");
+        }
+        assert!(has_diverged(
+            "Time = 1\nCourant Number mean: nan max: nan\n"
+        ));
+        assert!(!has_diverged(
+            "Time = 1\nCourant Number mean: 0.1 max: 0.9\n"
+        ));
+    }
+
+    #[test]
+    fn lstart_gives_the_earliest_solver_start() {
+        let output =
+            "Thu Oct 16 10:23:45 2026\nThu Oct 16 09:05:00 2026\nFri Oct  2 07:00:01 2026\n";
+        assert_eq!(
+            parse_lstart(output),
+            Some(Utc.with_ymd_and_hms(2026, 10, 2, 7, 0, 1).unwrap())
+        );
+        assert_eq!(parse_lstart(""), None);
+        assert_eq!(parse_lstart("not a date\n"), None);
+    }
+
+    #[test]
+    fn time_is_parsed_in_every_notation() {
+        for text in ["1.25e+02", "125", "1,25E2", " 1.25E+02\n"] {
+            assert_eq!(parse_time(text), Ok(125.0), "{text}");
+        }
+        assert!(parse_time("1.2.5").is_err());
+    }
+}
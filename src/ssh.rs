@@ -1,73 +1,409 @@
 //! SSH operations for remote instance monitoring
 
 use ssh2::Session;
-use std::env;
-use std::io::prelude::*;
-use std::net::TcpStream;
-use std::path::Path;
+use std::future::Future;
+use std::io::{self, prelude::*};
+use std::net::{Shutdown, SocketAddr, TcpStream};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
 
-use crate::{MonitorError, InstanceInfo, InstanceResults, TimeStep};
+use crate::proxy::ProxyLimits;
+use crate::retry::{self, RetryBudget};
+use crate::types::{Endpoint, IpKind, IpPreference};
+use crate::{InstanceInfo, InstanceResults, MonitorConfig, MonitorError, RawProbe, TimeStep};
+use crate::{cpu, disk, known_hosts, memory, network, notify, output, proxy, solver};
 
-/// Process a single instance by SSH connection and command execution
-pub async fn process_instance(instance: &InstanceInfo) -> Result<InstanceResults, MonitorError> {
-    let ip = match &instance.public_ip {
-        Some(ip) => ip,
-        None => {
-            return Err(MonitorError::NoPublicIp);
+/// Number of solver log lines searched for warnings
+const LOG_TAIL_LINES: usize = 2000;
+
+/// Timeout of the reachability check of the IP not used for monitoring
+const REACHABILITY_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Usernames of the Ubuntu, Amazon Linux and Debian AMIs, tried in turn by default
+pub const DEFAULT_SSH_USERNAMES: [&str; 3] = ["ubuntu", "ec2-user", "admin"];
+
+/// IPs to try, in order, to reach an instance
+pub fn candidate_endpoints(
+    instance: &InstanceInfo,
+    preference: IpPreference,
+) -> Vec<(IpKind, String)> {
+    preference
+        .order()
+        .iter()
+        .filter_map(|kind| {
+            let ip = match kind {
+                IpKind::Public => &instance.public_ip,
+                IpKind::Private => &instance.private_ip,
+            };
+            ip.clone().map(|ip| (*kind, ip))
+        })
+        .collect()
+}
+
+/// Interval in seconds passed to the SSH keepalive, 0 disabling it
+pub fn keepalive_interval_secs(interval: Duration) -> u32 {
+    u32::try_from(interval.as_secs()).unwrap_or(u32::MAX)
+}
+
+/// Check whether the SSH port of an IP accepts TCP connections
+fn is_reachable(ip: &str, port: u16) -> bool {
+    format!("{ip}:{port}")
+        .parse::<SocketAddr>()
+        .is_ok_and(|addr| TcpStream::connect_timeout(&addr, REACHABILITY_TIMEOUT).is_ok())
+}
+
+/// Try each candidate IP in turn, falling back to the next one on failure, and
+/// record the endpoint the attempt succeeded on
+pub async fn try_endpoints<T, F, Fut>(
+    candidates: &[(IpKind, String)],
+    mut attempt: F,
+) -> (Result<T, MonitorError>, Option<Endpoint>)
+where
+    F: FnMut(String) -> Fut,
+    Fut: Future<Output = Result<T, MonitorError>>,
+{
+    let mut outcome = Err(MonitorError::NoPublicIp);
+    for (index, (kind, ip)) in candidates.iter().enumerate() {
+        outcome = attempt(ip.clone()).await;
+        if outcome.is_ok() {
+            let endpoint = Endpoint {
+                kind: *kind,
+                ip: ip.clone(),
+                fallback: index > 0,
+                other_reachable: None,
+            };
+            return (outcome, Some(endpoint));
         }
-    };
+    }
+    (outcome, None)
+}
+
+/// Process a single instance by SSH connection and command execution
+pub async fn process_instance(
+    instance: &InstanceInfo,
+    config: &MonitorConfig,
+    replaced: bool,
+    budget: &RetryBudget,
+    proxy_limits: &ProxyLimits,
+) -> Result<InstanceResults, MonitorError> {
+    let candidates = candidate_endpoints(instance, config.ip_preference);
+    if candidates.is_empty() {
+        return Err(match config.ip_preference {
+            IpPreference::PrivateOnly => MonitorError::NoPrivateIp,
+            _ => MonitorError::NoPublicIp,
+        });
+    }
+
+    let (outcome, mut endpoint) = try_endpoints(&candidates, |ip| async move {
+        retry::with_retries(
+            budget,
+            config.instance_retries,
+            config.retry_delay,
+            || async {
+                let _permit = proxy_limits
+                    .acquire(proxy::route(config.socks_proxy.as_deref()))
+                    .await;
+                connect_and_execute_commands(&ip, &instance.name, config, replaced).await
+            },
+        )
+        .await
+    })
+    .await;
+
+    // Check whether the IP that was not used could have been
+    if config.show_endpoints
+        && let Some(endpoint) = &mut endpoint
+    {
+        let other = match endpoint.kind {
+            IpKind::Public => &instance.private_ip,
+            IpKind::Private => &instance.public_ip,
+        };
+        endpoint.other_reachable = other.as_deref().map(|ip| is_reachable(ip, config.ssh_port));
+    }
+
+    let probe = outcome?;
+    results_from_probe(instance, config, probe, endpoint)
+}
 
-    match connect_and_execute_commands(ip, &instance.name).await {
-        Ok((timestep, csv_count, disk_space, current_process)) => Ok(InstanceResults {
-            instance_id: instance.instance_id.clone(),
-            public_ip: instance.public_ip.clone(),
-            name: instance.name.clone(),
-            instance_type: instance.instance_type.clone(),
-            timestep_result: Some(TimeStep::new(&instance.name, &timestep)?),
-            csv_count: Some(csv_count),
-            free_disk_space: Some(disk_space),
-            current_process: Some(current_process),
-            ..Default::default()
+/// Build the results of an instance from the raw outputs of its monitoring commands
+pub fn results_from_probe(
+    instance: &InstanceInfo,
+    config: &MonitorConfig,
+    probe: RawProbe,
+    endpoint: Option<Endpoint>,
+) -> Result<InstanceResults, MonitorError> {
+    // The global override, else the TotalSteps tag, else the controlDict, else
+    // the wind speed of the name
+    let total_step = config.total_steps.or(instance.total_steps_tag).or_else(|| {
+        probe
+            .control_dict
+            .as_deref()
+            .and_then(solver::total_step_from_control_dict)
+    });
+    Ok(InstanceResults {
+        instance_id: instance.instance_id.clone(),
+        public_ip: instance.public_ip.clone(),
+        name: instance.name.clone(),
+        instance_type: instance.instance_type.clone(),
+        tags: instance.tags.clone(),
+        timestep_result: Some(if config.strict_wind_speed {
+            TimeStep::new(
+                &instance.name,
+                &probe.timestep,
+                total_step,
+                &config.wind_speed_steps,
+                config.default_total_step,
+            )?
+        } else {
+            TimeStep::new_lenient(
+                &instance.name,
+                &probe.timestep,
+                total_step,
+                &config.wind_speed_steps,
+                config.default_total_step,
+            )?
         }),
-        Err(e) => Err(e),
+        csv_count: Some(probe.csv_count),
+        free_disk_space: disk::parse_df_available(&probe.disk_space),
+        current_process: Some(probe.current_process.clone()),
+        nproc: probe.nproc.as_deref().and_then(cpu::parse_count),
+        solver_threads: probe.solver_threads.as_deref().and_then(cpu::parse_count),
+        solver_started_at: probe.solver_start.as_deref().and_then(solver::parse_lstart),
+        solver_rss_kb: probe.solver_rss.as_deref().and_then(memory::parse_rss_kb),
+        mem_total_kb: probe
+            .meminfo
+            .as_deref()
+            .and_then(memory::parse_mem_total_kb),
+        network_mb_per_sec: probe.net_dev.as_deref().and_then(network::parse_throughput),
+        ssh_latency_ms: probe.ssh_latency_ms,
+        ssh_username: probe.ssh_username.clone(),
+        diverged: probe
+            .divergence_lines
+            .as_deref()
+            .is_some_and(solver::has_diverged),
+        read_only: probe
+            .writability
+            .as_deref()
+            .and_then(disk::is_read_only)
+            .unwrap_or(false),
+        warning_count: probe
+            .log_warnings
+            .as_deref()
+            .map(|lines| solver::count_warnings(lines, &config.warning_patterns)),
+        raw_probe: Some(probe),
+        endpoint,
+        ..Default::default()
+    })
+}
+
+/// Run a shell command on an instance, trying its IPs in the preferred order,
+/// and return the command output
+pub async fn exec_on_instance(
+    instance: &InstanceInfo,
+    config: &MonitorConfig,
+    command: &str,
+    proxy_limits: &ProxyLimits,
+) -> Result<String, MonitorError> {
+    let mut outcome = Err(match config.ip_preference {
+        IpPreference::PrivateOnly => MonitorError::NoPrivateIp,
+        _ => MonitorError::NoPublicIp,
+    });
+    for (_, ip) in candidate_endpoints(instance, config.ip_preference) {
+        let _permit = proxy_limits
+            .acquire(proxy::route(config.socks_proxy.as_deref()))
+            .await;
+        outcome = open_session(&ip, config, false)
+            .await
+            .and_then(|sess| execute_ssh_command(&sess, config.remote_shell.as_deref(), command));
+        if outcome.is_ok() {
+            break;
+        }
     }
+    outcome
 }
 
-/// Connect to instance via SSH and execute monitoring commands
-async fn connect_and_execute_commands(
-    ip: &str,
-    instance_name: &str,
-) -> Result<(String, i32, String, String), MonitorError> {
-    // Connect to SSH
-    let tcp = TcpStream::connect(format!("{}:22", ip))?;
-    let mut sess = Session::new()?;
+/// Run the SSH handshake on a blocking task, giving up after `timeout`
+///
+/// Some hardened sshd configurations delay their banner, which would leave
+/// the handshake hanging. On timeout the TCP stream is shut down, unblocking
+/// the handshake task.
+pub async fn handshake_with_timeout(
+    mut sess: Session,
+    tcp: TcpStream,
+    timeout: Duration,
+) -> Result<Session, MonitorError> {
+    let stream = tcp.try_clone()?;
     sess.set_tcp_stream(tcp);
-    sess.handshake()?;
+    let handshake = tokio::task::spawn_blocking(move || sess.handshake().map(|()| sess));
+    match tokio::time::timeout(timeout, handshake).await {
+        Ok(joined) => Ok(joined??),
+        Err(_) => {
+            let _ = stream.shutdown(Shutdown::Both);
+            Err(MonitorError::HandshakeTimeout {
+                secs: timeout.as_secs(),
+            })
+        }
+    }
+}
 
-    // Authenticate with key pair
-    let keypair = env::var("AWS_KEYPAIR")?;
-    let key_path = Path::new(&keypair);
-    if !key_path.exists() {
-        return Err(MonitorError::KeyFileNotFound { path: keypair });
+/// Open an authenticated SSH session to an instance IP
+async fn open_session(
+    ip: &str,
+    config: &MonitorConfig,
+    replaced: bool,
+) -> Result<Session, MonitorError> {
+    let (sess, _, _) = login(ip, config, replaced).await?;
+    Ok(sess)
+}
+
+/// Connect and authenticate as each configured username in turn, returning the
+/// session, the latency of the first connection and the username that worked
+///
+/// Every username gets a new connection, as sshd drops a client changing its
+/// username. Fails with `AuthenticationFailed` only after every username was rejected.
+async fn login<'a>(
+    ip: &str,
+    config: &'a MonitorConfig,
+    replaced: bool,
+) -> Result<(Session, Duration, &'a str), MonitorError> {
+    let mut first_latency = None;
+    for username in &config.ssh_usernames {
+        // Time the connection and handshake, telling slow networks from slow instances
+        let (sess, latency) = timed(connect_session(ip, config)).await;
+        let latency = *first_latency.get_or_insert(latency);
+        match authenticate_session(sess?, ip, config, replaced, username) {
+            Ok(sess) => return Ok((sess, latency, username)),
+            Err(MonitorError::AuthenticationFailed) => continue,
+            Err(e) => return Err(e),
+        }
     }
+    Err(MonitorError::AuthenticationFailed)
+}
+
+/// Connect to an instance IP and run the SSH handshake, bounding the blocking
+/// reads of the session by the read timeout
+///
+/// A port blocked by the security group or an instance mid-reboot fails with
+/// `ConnectionTimeout` after the connect timeout, instead of the OS default.
+async fn connect_session(ip: &str, config: &MonitorConfig) -> Result<Session, MonitorError> {
+    let start = Instant::now();
+    let tcp = proxy::connect(
+        &format!("{}:{}", ip, config.ssh_port),
+        proxy::route(config.socks_proxy.as_deref()),
+        config.connect_timeout,
+    )
+    .map_err(|e| match e {
+        MonitorError::DirectConnect { source, .. } if source.kind() == io::ErrorKind::TimedOut => {
+            MonitorError::ConnectionTimeout {
+                ip: ip.to_string(),
+                port: config.ssh_port,
+                elapsed: start.elapsed(),
+            }
+        }
+        e => e,
+    })?;
+    let sess = handshake_with_timeout(Session::new()?, tcp, config.handshake_timeout).await?;
+    sess.set_timeout(u32::try_from(config.read_timeout.as_millis()).unwrap_or(u32::MAX));
+    Ok(sess)
+}
+
+/// Verify the host key of a connected session, if requested, and authenticate it as `username`
+fn authenticate_session(
+    sess: Session,
+    ip: &str,
+    config: &MonitorConfig,
+    replaced: bool,
+    username: &str,
+) -> Result<Session, MonitorError> {
+    sess.set_keepalive(true, keepalive_interval_secs(config.keepalive_interval));
+
+    if config.verify_host_keys {
+        known_hosts::verify_host_key(&sess, ip, config.ssh_port, replaced, config)?;
+    }
+
+    // Authenticate with the configured key pairs, else those of AWS_KEYPAIR
+    let key_paths = config.resolve_key_paths()?;
+    first_authenticating_key(&key_paths, |key_path| {
+        sess.userauth_pubkey_file(username, None, key_path, None)
+            .is_ok()
+    })?;
+    Ok(sess)
+}
 
-    // Try common usernames for different AMI types
-    let username = "ubuntu";
-    sess.userauth_pubkey_file(username, None, key_path, None)?;
+/// Run a future, measuring how long it took
+pub async fn timed<F: Future>(future: F) -> (F::Output, Duration) {
+    let start = Instant::now();
+    let output = future.await;
+    (output, start.elapsed())
+}
+
+/// Whole milliseconds of a duration, saturating at `u64::MAX`
+pub fn latency_ms(latency: Duration) -> u64 {
+    u64::try_from(latency.as_millis()).unwrap_or(u64::MAX)
+}
+
+/// Try the existing keys in turn until one authenticates, returning that key
+///
+/// Fails with `KeyFileNotFound` when none of the keys exists, and with
+/// `AuthenticationFailed` only after every existing key was rejected.
+pub fn first_authenticating_key(
+    key_paths: &[PathBuf],
+    mut authenticate: impl FnMut(&Path) -> bool,
+) -> Result<&Path, MonitorError> {
+    let existing: Vec<&Path> = key_paths
+        .iter()
+        .map(PathBuf::as_path)
+        .filter(|path| path.exists())
+        .collect();
+    if existing.is_empty() {
+        let paths: Vec<_> = key_paths
+            .iter()
+            .map(|path| path.display().to_string())
+            .collect();
+        return Err(MonitorError::KeyFileNotFound {
+            path: paths.join(":"),
+        });
+    }
+    existing
+        .into_iter()
+        .find(|path| authenticate(path))
+        .ok_or(MonitorError::AuthenticationFailed)
+}
+
+/// Connect to instance via SSH and execute monitoring commands
+async fn connect_and_execute_commands(
+    ip: &str,
+    instance_name: &str,
+    config: &MonitorConfig,
+    replaced: bool,
+) -> Result<RawProbe, MonitorError> {
+    let (sess, latency, username) = login(ip, config, replaced).await?;
+    let ssh_latency_ms = Some(latency_ms(latency));
+    let ssh_username = Some(username.to_string());
+    let shell = config.remote_shell.as_deref();
+    // Quote the remote paths, which come from the instance tags and the configuration
+    let log_path = solver::shell_quote(&config.log_path(instance_name));
+    let case_dir = solver::shell_quote(instance_name);
 
     // Execute commands
     let timestep_result = execute_ssh_command(
         &sess,
-        &format!("grep TimeStep {}/solve.out | tail -n1", instance_name),
+        shell,
+        &format!(
+            "grep -F {} {log_path} | tail -n1",
+            solver::shell_quote(&config.timestep_marker)
+        ),
     )?;
-    let csv_count_str = execute_ssh_command(&sess, &format!("ls {}/*.csv | wc -l", instance_name))?;
+    let csv_count_str = execute_ssh_command(&sess, shell, &format!("ls {case_dir}/*.csv | wc -l"))?;
     let csv_count = csv_count_str.trim().parse::<i32>().unwrap_or(0);
-    let disk_space = execute_ssh_command(&sess, "df -h / | tail -n1 | awk '{print $4}'")?;
+    let disk_space = execute_ssh_command(&sess, shell, "df -hP /")?;
 
     // Check which process is currently running (priority: s3 sync > finalize > zcsvs)
-    let s3_sync_check = execute_ssh_command(&sess, "ps aux | grep '[s]3 sync' | grep -v grep")?;
-    let finalize_check = execute_ssh_command(&sess, "ps aux | grep '[f]inalize' | grep -v grep")?;
-    let zcsvs_check = execute_ssh_command(&sess, "ps aux | grep '[z]csvs' | grep -v grep")?;
+    let s3_sync_check =
+        execute_ssh_command(&sess, shell, "ps aux | grep '[s]3 sync' | grep -v grep")?;
+    let finalize_check =
+        execute_ssh_command(&sess, shell, "ps aux | grep '[f]inalize' | grep -v grep")?;
+    let zcsvs_check = execute_ssh_command(&sess, shell, "ps aux | grep '[z]csvs' | grep -v grep")?;
 
     let current_process = if !s3_sync_check.is_empty() {
         "s3 sync".to_string()
@@ -79,30 +415,502 @@ async fn connect_and_execute_commands(
         "none".to_string()
     };
 
-    Ok((timestep_result, csv_count, disk_space, current_process))
+    // Optionally read the end time from the solver control file
+    let control_dict = if config.read_control_dict {
+        Some(execute_ssh_command(
+            &sess,
+            shell,
+            &format!(
+                "grep -E '^[[:space:]]*(endTime|deltaT)[[:space:]]' {case_dir}/system/controlDict 2>/dev/null"
+            ),
+        )?)
+    } else {
+        None
+    };
+
+    // Optionally keep the log tail lines matching the warning patterns
+    let log_warnings = if config.check_warnings && !config.warning_patterns.is_empty() {
+        let patterns: Vec<_> = config
+            .warning_patterns
+            .iter()
+            .map(|pattern| format!("-e {}", solver::shell_quote(pattern)))
+            .collect();
+        Some(execute_ssh_command(
+            &sess,
+            shell,
+            &format!(
+                "tail -n {LOG_TAIL_LINES} {log_path} | grep -F {} || true",
+                patterns.join(" ")
+            ),
+        )?)
+    } else {
+        None
+    };
+
+    // Optionally keep the log tail lines mentioning nan or inf values
+    let divergence_lines = if config.check_divergence {
+        Some(execute_ssh_command(
+            &sess,
+            shell,
+            &format!("tail -n {LOG_TAIL_LINES} {log_path} | grep -iE 'nan|inf' || true"),
+        )?)
+    } else {
+        None
+    };
+
+    // Optionally count the processors and the threads of the solver processes
+    let (nproc, solver_threads) = if config.check_threads {
+        (
+            Some(execute_ssh_command(&sess, shell, "nproc")?),
+            Some(execute_ssh_command(
+                &sess,
+                shell,
+                &format!(
+                    "ps -o nlwp= -C {} | awk '{{n += $1}} END {{print n + 0}}'",
+                    solver::shell_quote(&config.solver_process)
+                ),
+            )?),
+        )
+    } else {
+        (None, None)
+    };
+
+    // Optionally check that the case directory still accepts writes
+    let writability = if config.check_writable {
+        Some(execute_ssh_command(
+            &sess,
+            shell,
+            &disk::writability_probe(instance_name),
+        )?)
+    } else {
+        None
+    };
+
+    // Optionally read when the solver processes started
+    let solver_start = if config.read_solver_start {
+        Some(execute_ssh_command(
+            &sess,
+            shell,
+            &format!(
+                "ps -o lstart= -C {} || true",
+                solver::shell_quote(&config.solver_process)
+            ),
+        )?)
+    } else {
+        None
+    };
+
+    // Optionally read the memory of the solver processes, summed over all of them,
+    // and of the instance
+    let (solver_rss, meminfo) = if config.check_memory {
+        (
+            Some(execute_ssh_command(
+                &sess,
+                shell,
+                &format!(
+                    "ps -o rss= -C {} || true",
+                    solver::shell_quote(&config.solver_process)
+                ),
+            )?),
+            Some(execute_ssh_command(
+                &sess,
+                shell,
+                "grep MemTotal /proc/meminfo",
+            )?),
+        )
+    } else {
+        (None, None)
+    };
+
+    // Optionally sample the interface counters while an s3 sync is running
+    let net_dev = if config.check_network && current_process == "s3 sync" {
+        Some(execute_ssh_command(
+            &sess,
+            shell,
+            &network::net_dev_probe(),
+        )?)
+    } else {
+        None
+    };
+
+    // Optionally measure the output of a finalize or an s3 sync for their ETA
+    let output = if config.output_eta
+        && matches!(current_process.as_str(), output::FINALIZE | notify::S3_SYNC)
+    {
+        Some(execute_ssh_command(
+            &sess,
+            shell,
+            &output::output_probe(instance_name),
+        )?)
+    } else {
+        None
+    };
+
+    Ok(RawProbe {
+        timestep: timestep_result,
+        csv_count,
+        disk_space,
+        current_process,
+        control_dict,
+        nproc,
+        solver_threads,
+        solver_rss,
+        meminfo,
+        writability,
+        solver_start,
+        log_warnings,
+        divergence_lines,
+        net_dev,
+        output,
+        ssh_latency_ms,
+        ssh_username,
+    })
 }
 
-/// Execute a command via SSH session
-fn execute_ssh_command(sess: &Session, command: &str) -> Result<String, MonitorError> {
+/// Execute a command via SSH session, wrapped in `shell` if given
+fn execute_ssh_command(
+    sess: &Session,
+    shell: Option<&str>,
+    command: &str,
+) -> Result<String, MonitorError> {
+    // Send a keepalive if one is due, keeping the session alive between commands
+    sess.keepalive_send()?;
+
     let mut channel = sess.channel_session()?;
-    channel.exec(command)?;
+    channel.exec(&wrap_command(shell, command))?;
 
-    let mut output = String::new();
-    channel.read_to_string(&mut output)?;
+    let output = read_lossy(&mut channel)?;
 
     channel.wait_close()?;
-    let exit_status = channel.exit_status()?;
-
-    if exit_status != 0 {
-        let mut stderr = String::new();
-        channel.stderr().read_to_string(&mut stderr)?;
-        if !stderr.trim().is_empty() {
-            return Err(MonitorError::SshCommandFailed {
-                code: exit_status,
-                stderr,
-            });
+    // The exit status can be unavailable when the remote closes the channel oddly
+    let exit_status = channel.exit_status().ok();
+    let stderr = read_lossy(channel.stderr())?;
+
+    match command_outcome(exit_status, &stderr) {
+        CommandOutcome::Success => {}
+        CommandOutcome::SuccessWithStderr => tracing::warn!(
+            "`{command}` wrote to stderr: {}",
+            stderr.lines().next().unwrap_or_default().trim()
+        ),
+        CommandOutcome::Failed { code } => {
+            return Err(MonitorError::SshCommandFailed { code, stderr });
         }
     }
 
     Ok(output.trim().to_string())
 }
+
+/// Wrap a command as `<shell> -c '<command>'`, for instances whose login shell
+/// handles the pipes of the monitoring commands differently, or leave it to
+/// the login shell without `shell`
+pub fn wrap_command(shell: Option<&str>, command: &str) -> String {
+    match shell {
+        Some(shell) => format!("{shell} -c '{}'", command.replace('\'', r"'\''")),
+        None => command.to_string(),
+    }
+}
+
+/// Outcome of a remote command, from its exit status and standard error
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CommandOutcome {
+    /// The output is used as is
+    Success,
+    /// The output is used, the standard error being logged as a warning
+    SuccessWithStderr,
+    /// The command failed with an error message
+    Failed { code: i32 },
+}
+
+/// Decide the outcome of a remote command from its exit status, `None` when
+/// unavailable, and its standard error
+///
+/// A command only fails with a nonzero exit status and an error message; a
+/// nonzero status alone, e.g. `grep` without a match, keeps the output, and
+/// an error message alone is only a warning.
+pub fn command_outcome(exit_status: Option<i32>, stderr: &str) -> CommandOutcome {
+    let has_stderr = !stderr.trim().is_empty();
+    match exit_status {
+        Some(code) if code != 0 && has_stderr => CommandOutcome::Failed { code },
+        Some(0) | None if has_stderr => CommandOutcome::SuccessWithStderr,
+        _ => CommandOutcome::Success,
+    }
+}
+
+/// Read a command output to the end, replacing invalid UTF-8 sequences with
+/// U+FFFD instead of failing on them
+pub fn read_lossy<R: Read>(mut reader: R) -> Result<String, MonitorError> {
+    let mut bytes = Vec::new();
+    reader.read_to_end(&mut bytes)?;
+    Ok(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn candidates() -> Vec<(IpKind, String)> {
+        vec![
+            (IpKind::Private, "10.0.1.5".to_string()),
+            (IpKind::Public, "54.1.2.3".to_string()),
+        ]
+    }
+
+    #[tokio::test]
+    async fn endpoint_of_the_preferred_ip_is_not_a_fallback() {
+        let (outcome, endpoint) = try_endpoints(&candidates(), |ip| async move { Ok(ip) }).await;
+        assert_eq!(outcome.unwrap(), "10.0.1.5");
+        let endpoint = endpoint.unwrap();
+        assert_eq!(endpoint.kind, IpKind::Private);
+        assert_eq!(endpoint.ip, "10.0.1.5");
+        assert!(!endpoint.fallback);
+    }
+
+    #[tokio::test]
+    async fn endpoint_of_the_next_ip_is_recorded_as_a_fallback() {
+        let (outcome, endpoint) = try_endpoints(&candidates(), |ip| async move {
+            if ip.starts_with("10.") {
+                Err(MonitorError::HandshakeTimeout { secs: 10 })
+            } else {
+                Ok(ip)
+            }
+        })
+        .await;
+        assert_eq!(outcome.unwrap(), "54.1.2.3");
+        let endpoint = endpoint.unwrap();
+        assert_eq!(endpoint.kind, IpKind::Public);
+        assert!(endpoint.fallback);
+    }
+
+    #[tokio::test]
+    async fn no_endpoint_is_recorded_when_every_ip_fails() {
+        let (outcome, endpoint) = try_endpoints(&candidates(), |_| async {
+            Err::<String, _>(MonitorError::HandshakeTimeout { secs: 10 })
+        })
+        .await;
+        assert!(matches!(
+            outcome,
+            Err(MonitorError::HandshakeTimeout { .. })
+        ));
+        assert!(endpoint.is_none());
+    }
+
+    fn instance(public_ip: Option<&str>, private_ip: Option<&str>) -> InstanceInfo {
+        InstanceInfo {
+            public_ip: public_ip.map(str::to_string),
+            private_ip: private_ip.map(str::to_string),
+            ..InstanceInfo::default()
+        }
+    }
+
+    fn kinds(instance: &InstanceInfo, preference: IpPreference) -> Vec<IpKind> {
+        candidate_endpoints(instance, preference)
+            .into_iter()
+            .map(|(kind, _)| kind)
+            .collect()
+    }
+
+    #[test]
+    fn each_preference_orders_the_available_ips() {
+        use IpKind::{Private, Public};
+        let both = instance(Some("54.1.2.3"), Some("10.0.1.5"));
+        assert_eq!(kinds(&both, IpPreference::PublicOnly), [Public]);
+        assert_eq!(kinds(&both, IpPreference::PrivateOnly), [Private]);
+        assert_eq!(
+            kinds(&both, IpPreference::PublicThenPrivate),
+            [Public, Private]
+        );
+        assert_eq!(
+            kinds(&both, IpPreference::PrivateThenPublic),
+            [Private, Public]
+        );
+    }
+
+    #[test]
+    fn missing_ips_are_skipped() {
+        let private_only = instance(None, Some("10.0.1.5"));
+        assert!(kinds(&private_only, IpPreference::PublicOnly).is_empty());
+        assert_eq!(
+            candidate_endpoints(&private_only, IpPreference::PublicThenPrivate),
+            [(IpKind::Private, "10.0.1.5".to_string())]
+        );
+
+        let public_only = instance(Some("54.1.2.3"), None);
+        assert!(kinds(&public_only, IpPreference::PrivateOnly).is_empty());
+        assert_eq!(
+            kinds(&public_only, IpPreference::PrivateThenPublic),
+            [IpKind::Public]
+        );
+    }
+
+    #[test]
+    fn keepalive_interval_is_passed_through_in_seconds() {
+        assert_eq!(keepalive_interval_secs(Duration::from_secs(15)), 15);
+        assert_eq!(keepalive_interval_secs(Duration::ZERO), 0);
+        assert_eq!(
+            keepalive_interval_secs(Duration::from_secs(u64::MAX)),
+            u32::MAX
+        );
+    }
+
+    #[test]
+    fn invalid_utf8_is_decoded_lossily() {
+        let bytes: &[u8] = b"/dev/root  20G \xff\xfe used\n";
+        assert_eq!(
+            read_lossy(bytes).unwrap(),
+            "/dev/root  20G \u{FFFD}\u{FFFD} used\n"
+        );
+        assert_eq!(read_lossy("ünïcode".as_bytes()).unwrap(), "ünïcode");
+    }
+
+    #[test]
+    fn global_total_steps_override_every_name_and_tag() {
+        let config = MonitorConfig {
+            total_steps: Some(30_000),
+            strict_wind_speed: true,
+            ..MonitorConfig::default()
+        };
+        let probe = RawProbe {
+            timestep: "TimeStep 100: Time = 0.5".to_string(),
+            control_dict: Some("endTime 10;\ndeltaT 0.001;\n".to_string()),
+            ..RawProbe::default()
+        };
+        for (name, tag) in [
+            ("wake_2ms", None),
+            ("wake_U17", Some(5000)),
+            ("wake_calm", None),
+        ] {
+            let instance = InstanceInfo {
+                name: name.to_string(),
+                total_steps_tag: tag,
+                ..InstanceInfo::default()
+            };
+            let result = results_from_probe(&instance, &config, probe.clone(), None).unwrap();
+            let ts = result.timestep_result.unwrap();
+            assert_eq!((ts.step, ts.total_step), (100, 30_000), "{name}");
+        }
+    }
+
+    #[test]
+    fn keys_are_tried_in_turn_until_one_authenticates() {
+        let dir = std::env::temp_dir().join(format!("ec2_monitor_keys_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let [missing, rejected, accepted, unused] =
+            ["missing.pem", "rejected.pem", "accepted.pem", "unused.pem"].map(|key| dir.join(key));
+        for key in [&rejected, &accepted, &unused] {
+            std::fs::write(key, "key").unwrap();
+        }
+        let key_paths = [missing.clone(), rejected.clone(), accepted.clone(), unused];
+
+        let mut tried = Vec::new();
+        let key = first_authenticating_key(&key_paths, |path| {
+            tried.push(path.to_path_buf());
+            path == accepted
+        });
+        assert_eq!(key.unwrap(), accepted);
+        assert_eq!(tried, [rejected.clone(), accepted.clone()]);
+
+        assert!(matches!(
+            first_authenticating_key(&key_paths, |_| false),
+            Err(MonitorError::AuthenticationFailed)
+        ));
+        assert!(matches!(
+            first_authenticating_key(&[missing], |_| true),
+            Err(MonitorError::KeyFileNotFound { .. })
+        ));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn total_steps_tag_takes_precedence_over_the_wind_speed() {
+        let config = MonitorConfig::default();
+        let probe = RawProbe {
+            timestep: "TimeStep 100: Time = 0.5".to_string(),
+            ..RawProbe::default()
+        };
+        for (tag, total_step) in [
+            (Some("5000"), 5000),
+            (Some(" 7500 "), 7500),
+            (Some("many"), 24_000),
+            (Some("0"), 24_000),
+            (Some("-10"), 24_000),
+            (None, 24_000),
+        ] {
+            let instance = InstanceInfo {
+                name: "wake_2ms".to_string(),
+                total_steps_tag: tag.and_then(crate::aws::parse_total_steps_tag),
+                ..InstanceInfo::default()
+            };
+            let result = results_from_probe(&instance, &config, probe.clone(), None).unwrap();
+            assert_eq!(
+                result.timestep_result.unwrap().total_step,
+                total_step,
+                "{tag:?}"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn handshake_without_a_banner_times_out() {
+        // A server accepting the connection but never sending its banner
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let tcp = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+
+        let start = Instant::now();
+        let outcome =
+            handshake_with_timeout(Session::new().unwrap(), tcp, Duration::from_millis(200)).await;
+        assert!(matches!(
+            outcome,
+            Err(MonitorError::HandshakeTimeout { secs: 0 })
+        ));
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn only_a_nonzero_status_with_stderr_fails_the_command() {
+        for (exit_status, stderr, outcome) in [
+            (Some(0), "", CommandOutcome::Success),
+            (
+                Some(0),
+                "warning: deprecated\n",
+                CommandOutcome::SuccessWithStderr,
+            ),
+            (Some(1), "", CommandOutcome::Success),
+            (Some(1), "  \n", CommandOutcome::Success),
+            (Some(2), "No such file", CommandOutcome::Failed { code: 2 }),
+            (None, "", CommandOutcome::Success),
+            (None, "broken pipe", CommandOutcome::SuccessWithStderr),
+        ] {
+            assert_eq!(
+                command_outcome(exit_status, stderr),
+                outcome,
+                "{exit_status:?} {stderr:?}"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn latency_is_the_duration_of_the_timed_connection() {
+        let fake_connect = async {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            Ok::<_, MonitorError>("session")
+        };
+        let (sess, latency) = timed(fake_connect).await;
+        assert_eq!(sess.unwrap(), "session");
+        assert!(latency >= Duration::from_millis(20), "{latency:?}");
+        assert!(latency < Duration::from_secs(5), "{latency:?}");
+
+        assert_eq!(latency_ms(Duration::from_micros(42_900)), 42);
+        assert_eq!(latency_ms(Duration::MAX), u64::MAX);
+    }
+
+    #[test]
+    fn commands_are_wrapped_for_the_chosen_shell() {
+        let command = "grep -c 'TimeStep' solve.out | tail -n 1";
+        assert_eq!(
+            wrap_command(Some("bash"), command),
+            r"bash -c 'grep -c '\''TimeStep'\'' solve.out | tail -n 1'"
+        );
+        assert_eq!(wrap_command(Some("sh"), "uptime"), "sh -c 'uptime'");
+        assert_eq!(wrap_command(None, command), command);
+    }
+}
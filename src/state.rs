@@ -0,0 +1,52 @@
+//! Monitoring state carried across cycles
+
+use chrono::{DateTime, Utc};
+use std::collections::{HashMap, HashSet};
+
+use crate::anonymize::Anonymizer;
+use crate::history::HistorySample;
+use crate::ledger::CompletionLedger;
+use crate::schedule::RenderThrottle;
+use crate::server::SharedSummary;
+use crate::{InstanceResults, TimeStep};
+
+/// State kept between monitoring cycles, keyed by instance name
+#[derive(Debug, Default)]
+pub struct MonitorState {
+    /// Timestep of the previous cycle, used for step increases
+    pub previous_timesteps: HashMap<String, TimeStep>,
+    /// ETA samples in minutes, used for median calculation
+    pub instance_etas: HashMap<String, Vec<f64>>,
+    /// Number of cycles each job has been observed, used for the ETA warmup
+    pub observed_cycles: HashMap<String, usize>,
+    /// Step and time of the first observation, used for the lifetime average rate
+    pub first_observed: HashMap<String, (usize, DateTime<Utc>)>,
+    /// Completed instances, kept in the report for a while after their termination
+    pub completion_ledger: CompletionLedger,
+    /// Results of the previous cycle, used for change detection
+    pub previous_results: HashMap<String, InstanceResults>,
+    /// Instance id last seen behind each IP, used to detect replaced instances
+    pub host_instance_ids: HashMap<String, String>,
+    /// Fleet summary of the latest cycle, served by the `/summary` endpoint
+    pub summary: SharedSummary,
+    /// Progress samples of every cycle, used for the history export
+    pub history: HashMap<String, Vec<HistorySample>>,
+    /// Latest free disk space samples in GB, used for the disk space trend
+    pub disk_samples: HashMap<String, Vec<(DateTime<Utc>, f64)>>,
+    /// Instance ids the idle action was taken on, taken once per instance
+    pub idle_actioned: HashSet<String>,
+    /// Aliases of the instance names in the anonymized reports
+    pub anonymizer: Anonymizer,
+    /// Latest summary report, drawn at most once per render interval
+    pub render: RenderThrottle,
+    /// Number of monitoring cycles run so far
+    pub cycle_count: usize,
+    /// Consecutive cycles that took longer than the refresh interval
+    pub overrun_cycles: usize,
+    /// Number of cycles so far that took longer than the refresh interval
+    pub total_overruns: usize,
+    /// Whether every instance was complete in the previous cycle
+    pub campaign_complete: bool,
+    /// Whether the `CAMPAIGN_COMPLETE` sentinel line was printed
+    pub sentinel_printed: bool,
+}
@@ -0,0 +1,388 @@
+//! Interactive terminal mode with instance drill-down
+
+use chrono::Utc;
+use crossterm::cursor::{Hide, MoveTo, Show};
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use crossterm::style::Print;
+use crossterm::terminal::{self, Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen};
+use crossterm::{execute, queue};
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::time::{Duration, Instant};
+
+use crate::aws::RegionClient;
+use crate::history::HistorySample;
+use crate::logging;
+use crate::report::{self, ReportOptions};
+use crate::{InstanceResults, MonitorConfig, MonitorError, MonitorState, Notifier, monitor_cycle};
+
+/// Time waited for a key press before checking whether a cycle is due
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Number of history samples shown in the detail pane
+const DETAIL_HISTORY_LEN: usize = 10;
+
+/// Screen shown by the interactive mode
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum View {
+    #[default]
+    Table,
+    Detail,
+}
+
+/// Index of the selected table row
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Selection {
+    index: usize,
+}
+
+impl Selection {
+    /// Index of the selected row
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// Select the previous row, staying on the first one
+    pub fn up(&mut self) {
+        self.index = self.index.saturating_sub(1);
+    }
+
+    /// Select the next row, staying on the last one of `len` rows
+    pub fn down(&mut self, len: usize) {
+        if self.index + 1 < len {
+            self.index += 1;
+        }
+    }
+
+    /// Keep the selection within `len` rows after the table changed
+    pub fn clamp(&mut self, len: usize) {
+        self.index = self.index.min(len.saturating_sub(1));
+    }
+}
+
+/// Update the view and selection for a key press, returning `true` to quit
+pub fn handle_key(code: KeyCode, view: &mut View, selection: &mut Selection, len: usize) -> bool {
+    match (*view, code) {
+        (_, KeyCode::Char('q')) => return true,
+        (View::Table, KeyCode::Up | KeyCode::Char('k')) => selection.up(),
+        (View::Table, KeyCode::Down | KeyCode::Char('j')) => selection.down(len),
+        (View::Table, KeyCode::Enter) if len > 0 => *view = View::Detail,
+        (View::Detail, KeyCode::Esc) => *view = View::Table,
+        _ => {}
+    }
+    false
+}
+
+/// Restores the terminal when the interactive mode ends, even on error
+struct TerminalGuard;
+
+impl TerminalGuard {
+    fn enter() -> io::Result<Self> {
+        terminal::enable_raw_mode()?;
+        execute!(io::stdout(), EnterAlternateScreen, Hide)?;
+        Ok(Self)
+    }
+}
+
+impl Drop for TerminalGuard {
+    fn drop(&mut self) {
+        let _ = execute!(io::stdout(), Show, LeaveAlternateScreen);
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+/// Run the monitoring cycles in the interactive mode until the user quits
+pub async fn run(
+    clients: &[RegionClient],
+    config: &MonitorConfig,
+    state: &mut MonitorState,
+    notifiers: &[Box<dyn Notifier>],
+) -> Result<(), MonitorError> {
+    // Keep the logs off the screen, showing the last one in the status line
+    let _capture = logging::capture();
+    let _guard = TerminalGuard::enter()?;
+    let options = ReportOptions {
+        plain: false,
+        ..ReportOptions::from_config(config)
+    };
+    let mut view = View::default();
+    let mut selection = Selection::default();
+    let mut status = String::new();
+    let mut next_cycle = Instant::now();
+
+    loop {
+        if Instant::now() >= next_cycle {
+            let cycle_start = Instant::now();
+            draw(&["Refreshing...".to_string()])?;
+            status = match monitor_cycle(clients, config, state, notifiers).await {
+                Ok(()) => format!(
+                    "Last update: {}",
+                    report::display_time(Utc::now(), config.display_timezone)
+                        .format("%Y-%m-%d %H:%M:%S")
+                ),
+                Err(e) => format!("Update failed: {e}"),
+            };
+            next_cycle = cycle_start + config.refresh_interval;
+        }
+
+        let results = sorted_results(&state.previous_results);
+        // Redact the shown results as the reports with `--anonymize`
+        let anonymized: Vec<_> = if config.anonymize {
+            results
+                .iter()
+                .map(|r| state.anonymizer.anonymize(r))
+                .collect()
+        } else {
+            Vec::new()
+        };
+        let shown: Vec<&InstanceResults> = if config.anonymize {
+            anonymized.iter().collect()
+        } else {
+            results.clone()
+        };
+        selection.clamp(results.len());
+        let index = selection.index();
+        let lines = match (view, results.get(index), shown.get(index)) {
+            (View::Detail, Some(result), Some(shown)) => {
+                let history = state
+                    .history
+                    .get(&result.name)
+                    .map_or(&[][..], Vec::as_slice);
+                detail_lines(shown, history, &options)
+            }
+            _ => table_lines(
+                &shown,
+                selection,
+                &status_line(&status, logging::last_captured()),
+                &options.fit_to(shown.iter().copied()),
+            ),
+        };
+        draw(&lines)?;
+
+        let key = tokio::task::block_in_place(|| read_key(POLL_INTERVAL))?;
+        if let Some(key) = key {
+            if key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL) {
+                break;
+            }
+            if handle_key(key.code, &mut view, &mut selection, results.len()) {
+                break;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Results of the latest cycle, sorted by instance name
+fn sorted_results(previous_results: &HashMap<String, InstanceResults>) -> Vec<&InstanceResults> {
+    let mut results: Vec<_> = previous_results.values().collect();
+    results.sort_by(|a, b| a.name.cmp(&b.name));
+    results
+}
+
+/// Status line of the table, followed by the last log line when there is one
+fn status_line(status: &str, last_log: Option<String>) -> String {
+    match last_log {
+        Some(log) => format!("{status} | {}", log.trim()),
+        None => status.to_string(),
+    }
+}
+
+/// Wait up to `timeout` for a key press
+fn read_key(timeout: Duration) -> io::Result<Option<KeyEvent>> {
+    if event::poll(timeout)?
+        && let Event::Key(key) = event::read()?
+        && key.kind == KeyEventKind::Press
+    {
+        return Ok(Some(key));
+    }
+    Ok(None)
+}
+
+/// Replace the screen content with the given lines
+fn draw(lines: &[String]) -> io::Result<()> {
+    let mut stdout = io::stdout();
+    queue!(stdout, Clear(ClearType::All))?;
+    for (row, line) in lines.iter().enumerate() {
+        queue!(stdout, MoveTo(0, row as u16), Print(line))?;
+    }
+    stdout.flush()
+}
+
+/// Lines of the instance table, marking the selected row
+fn table_lines(
+    results: &[&InstanceResults],
+    selection: Selection,
+    status: &str,
+    options: &ReportOptions,
+) -> Vec<String> {
+    let mut lines = vec![
+        "EC2 Monitor | ↑/↓ select | Enter details | Esc back | q quit".to_string(),
+        status.to_string(),
+        String::new(),
+        format!("  {}", report::table_header(options)),
+        format!("  {}", "-".repeat(140)),
+    ];
+    lines.extend(results.iter().enumerate().map(|(index, result)| {
+        let marker = if index == selection.index() { ">" } else { " " };
+        format!("{marker} {}", report::format_result_row(result, options))
+    }));
+    lines
+}
+
+/// Lines of the detail pane of an instance
+fn detail_lines(
+    result: &InstanceResults,
+    history: &[HistorySample],
+    options: &ReportOptions,
+) -> Vec<String> {
+    let or_na = |value: Option<String>| value.unwrap_or_else(|| "N/A".to_string());
+    let mut lines = vec![
+        format!(
+            "{} ({})  |  Esc back | q quit",
+            result.name, result.instance_id
+        ),
+        "=".repeat(80),
+        format!("Instance type:    {}", result.instance_type),
+        format!("Public IP:        {}", or_na(result.public_ip.clone())),
+        format!(
+            "Endpoint:         {}",
+            report::format_endpoint(result.endpoint.as_ref(), options)
+        ),
+        format!("ETA:              {}", report::eta_display(result, options)),
+    ];
+    match &result.timestep_result {
+        Some(ts) => lines.push(format!(
+            "Step:             {} / {}{} (+{}) at time {:.2}",
+            ts.step,
+            ts.total_step,
+            if ts.assumed_total { " (assumed)" } else { "" },
+            ts.step_increase
+                .map_or("?".to_string(), |inc| inc.to_string()),
+            ts.time
+        )),
+        None => lines.push("Step:             N/A".to_string()),
+    }
+    lines.extend([
+        format!(
+            "CSV files:        {}",
+            or_na(result.csv_count.map(|count| count.to_string()))
+        ),
+        format!(
+            "Free disk space:  {}",
+            or_na(result.free_disk_space.clone())
+        ),
+        format!(
+            "Process:          {}",
+            or_na(result.current_process.clone())
+        ),
+        format!(
+            "Solver threads:   {} on {} processors",
+            or_na(result.solver_threads.map(|threads| threads.to_string())),
+            or_na(result.nproc.map(|nproc| nproc.to_string()))
+        ),
+        format!(
+            "Solver memory:    {} of {}",
+            report::format_memory(result.solver_rss_kb, result.mem_total_kb, options),
+            or_na(
+                result
+                    .mem_total_kb
+                    .map(|kb| format!("{:.1}G", crate::memory::kb_to_gb(kb)))
+            )
+        ),
+        format!(
+            "Network:          {}",
+            crate::network::format_throughput(result.network_mb_per_sec)
+        ),
+        format!(
+            "Output ETA:       {}",
+            or_na(result.output_eta_minutes.map(crate::eta::format_remaining))
+        ),
+        format!(
+            "SSH latency:      {}",
+            report::format_latency(result.ssh_latency_ms)
+        ),
+        format!("SSH user:         {}", or_na(result.ssh_username.clone())),
+        format!(
+            "Connection:       {}",
+            result.connection_error.as_deref().unwrap_or("Success")
+        ),
+        format!("Starting up:      {}", result.starting_up),
+        format!("Note:             {}", or_na(result.note.clone())),
+        String::new(),
+        "Recent history:".to_string(),
+    ]);
+
+    let recent = &history[history.len().saturating_sub(DETAIL_HISTORY_LEN)..];
+    if recent.is_empty() {
+        lines.push("  (none)".to_string());
+    }
+    lines.extend(recent.iter().map(|sample| {
+        format!(
+            "  {}  step {:>8} / {:<8} time {:>10.2}  ETA {}",
+            report::display_time(sample.timestamp, options.timezone).format("%Y-%m-%d %H:%M"),
+            sample.step,
+            sample.total_step,
+            sample.time,
+            sample
+                .eta_minutes
+                .map_or("N/A".to_string(), crate::eta::format_remaining)
+        )
+    }));
+
+    lines.push(String::new());
+    lines.push("Last command outputs:".to_string());
+    match &result.raw_probe {
+        Some(probe) => {
+            lines.push(format!("  timestep:    {}", probe.timestep.trim()));
+            lines.push(format!("  csv count:   {}", probe.csv_count));
+            lines.push("  disk space:".to_string());
+            lines.extend(probe.disk_space.lines().map(|line| format!("    {line}")));
+            lines.push(format!("  process:     {}", probe.current_process.trim()));
+            if let Some(control_dict) = &probe.control_dict {
+                lines.push("  controlDict:".to_string());
+                lines.extend(control_dict.lines().map(|line| format!("    {line}")));
+            }
+        }
+        None => lines.push("  (none)".to_string()),
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn arrows_move_the_selection_within_the_rows() {
+        let mut view = View::Table;
+        let mut selection = Selection::default();
+        for code in [KeyCode::Up, KeyCode::Down, KeyCode::Down, KeyCode::Down] {
+            assert!(!handle_key(code, &mut view, &mut selection, 3));
+        }
+        assert_eq!(selection.index(), 2);
+        handle_key(KeyCode::Char('k'), &mut view, &mut selection, 3);
+        assert_eq!(selection.index(), 1);
+
+        selection.clamp(1);
+        assert_eq!(selection.index(), 0);
+    }
+
+    #[test]
+    fn enter_opens_the_detail_and_escape_returns_to_the_table() {
+        let mut view = View::Table;
+        let mut selection = Selection::default();
+        handle_key(KeyCode::Enter, &mut view, &mut selection, 0);
+        assert_eq!(view, View::Table);
+
+        handle_key(KeyCode::Enter, &mut view, &mut selection, 2);
+        assert_eq!(view, View::Detail);
+        // The selection does not move while the detail is shown
+        handle_key(KeyCode::Down, &mut view, &mut selection, 2);
+        assert_eq!(selection.index(), 0);
+        handle_key(KeyCode::Esc, &mut view, &mut selection, 2);
+        assert_eq!(view, View::Table);
+
+        assert!(handle_key(KeyCode::Char('q'), &mut view, &mut selection, 2));
+    }
+}
@@ -1,9 +1,14 @@
 //! Data types for EC2 Monitor
 
 use crate::error::MonitorError;
+use crate::output::OutputSample;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::fmt::Display;
+use std::str::FromStr;
 
-#[derive(Debug, Default, Clone)]
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
 #[allow(dead_code)]
 pub struct InstanceInfo {
     pub instance_id: String,
@@ -11,20 +16,194 @@ pub struct InstanceInfo {
     pub instance_type: String,
     pub public_ip: Option<String>,
     pub private_ip: Option<String>,
+    pub launch_time: Option<DateTime<Utc>>,
+    /// Total number of steps of the case from the `TotalSteps` tag, if valid
+    #[serde(default)]
+    pub total_steps_tag: Option<usize>,
+    /// All the tags of the instance, by key
+    #[serde(default)]
+    pub tags: HashMap<String, String>,
+    /// Region of the instance, set by the discovery
+    #[serde(default)]
+    pub region: String,
 }
 
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Clone)]
 pub struct InstanceResults {
     pub instance_id: String,
     pub public_ip: Option<String>,
     pub name: String,
     pub instance_type: String,
+    /// Tags of the instance, used to group the report
+    pub tags: HashMap<String, String>,
     pub timestep_result: Option<TimeStep>,
     pub csv_count: Option<i32>,
     pub free_disk_space: Option<String>,
     pub current_process: Option<String>,
     pub eta: Option<String>,
+    /// Minutes left of the ETA, `Some(0.0)` once complete and `None` for a
+    /// status such as stalled or diverged
+    pub eta_minutes: Option<f64>,
     pub connection_error: Option<String>,
+    pub starting_up: bool,
+    /// Whether the instance completed and is no longer discovered
+    pub terminated: bool,
+    pub endpoint: Option<Endpoint>,
+    pub note: Option<String>,
+    /// Steps per minute since the previous observation
+    pub current_rate: Option<f64>,
+    /// Steps per minute since the instance was first observed
+    pub average_rate: Option<f64>,
+    /// Start time of the solver process, when checked
+    pub solver_started_at: Option<DateTime<Utc>>,
+    /// Number of processors of the instance, when checked
+    pub nproc: Option<usize>,
+    /// Number of threads of the solver processes, when checked
+    pub solver_threads: Option<usize>,
+    /// Resident memory in kilobytes of the solver processes, when checked
+    pub solver_rss_kb: Option<u64>,
+    /// Total memory in kilobytes of the instance, when checked
+    pub mem_total_kb: Option<u64>,
+    /// Network throughput in MB/s of the primary interfaces during an s3 sync, when checked
+    pub network_mb_per_sec: Option<f64>,
+    /// Milliseconds taken by the connection and SSH handshake
+    pub ssh_latency_ms: Option<u64>,
+    /// Username the SSH session authenticated as
+    pub ssh_username: Option<String>,
+    /// Output bytes of a finalize or an s3 sync, towards their target, when checked
+    pub output_sample: Option<OutputSample>,
+    /// Bytes transmitted when the s3 sync was first seen, carried across cycles
+    pub sync_start_tx_bytes: Option<u64>,
+    /// Minutes left of the finalize or s3 sync at the rate of their output bytes
+    pub output_eta_minutes: Option<f64>,
+    /// Whether the solver log tail shows `nan` or `inf` residuals or fields
+    pub diverged: bool,
+    /// Whether the case directory could not be written to, when checked
+    pub read_only: bool,
+    /// Number of warnings in the solver log tail, when checked
+    pub warning_count: Option<usize>,
+    /// Minutes until the disk is full at the free disk space trend of the last cycles
+    pub disk_full_minutes: Option<f64>,
+    /// Whether the step was carried forward from the previous cycle after an
+    /// empty read of the solver log
+    pub stale_timestep: bool,
+    /// When the current process was first observed, carried across cycles
+    /// until the process changes
+    pub process_since: Option<DateTime<Utc>>,
+    /// Whether the s3 sync has been running for longer than the stuck sync limit
+    pub sync_stuck: bool,
+    /// Consecutive cycles the steps advanced without the CSV count growing
+    pub csv_flat_cycles: usize,
+    /// Whether the CSV count has been flat despite progress for the CSV stall cycles
+    pub csv_stalled: bool,
+    /// Raw outputs of the last monitoring commands
+    pub raw_probe: Option<RawProbe>,
+}
+
+/// Kind of IP address used to reach an instance
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum IpKind {
+    Public,
+    Private,
+}
+
+impl Display for IpKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            IpKind::Public => write!(f, "public"),
+            IpKind::Private => write!(f, "private"),
+        }
+    }
+}
+
+/// Order in which the IPs of an instance are tried
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum IpPreference {
+    #[default]
+    PublicOnly,
+    PrivateOnly,
+    PublicThenPrivate,
+    PrivateThenPublic,
+}
+
+impl IpPreference {
+    /// IP kinds to try, in order
+    pub fn order(self) -> &'static [IpKind] {
+        match self {
+            IpPreference::PublicOnly => &[IpKind::Public],
+            IpPreference::PrivateOnly => &[IpKind::Private],
+            IpPreference::PublicThenPrivate => &[IpKind::Public, IpKind::Private],
+            IpPreference::PrivateThenPublic => &[IpKind::Private, IpKind::Public],
+        }
+    }
+}
+
+impl FromStr for IpPreference {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "public" => Ok(IpPreference::PublicOnly),
+            "private" => Ok(IpPreference::PrivateOnly),
+            "public-private" => Ok(IpPreference::PublicThenPrivate),
+            "private-public" => Ok(IpPreference::PrivateThenPublic),
+            _ => Err(format!(
+                "unknown IP preference {s:?}, expected public, private, public-private or private-public"
+            )),
+        }
+    }
+}
+
+/// Address an instance was monitored through
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Endpoint {
+    pub kind: IpKind,
+    pub ip: String,
+    /// Whether the preferred IP failed and this one was used instead
+    pub fallback: bool,
+    /// Whether the other IP of the instance accepts SSH connections, if checked
+    pub other_reachable: Option<bool>,
+}
+
+/// Raw outputs of the monitoring commands run on an instance
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct RawProbe {
+    pub timestep: String,
+    pub csv_count: i32,
+    pub disk_space: String,
+    pub current_process: String,
+    pub control_dict: Option<String>,
+    pub nproc: Option<String>,
+    pub solver_threads: Option<String>,
+    pub solver_rss: Option<String>,
+    pub meminfo: Option<String>,
+    pub writability: Option<String>,
+    pub solver_start: Option<String>,
+    pub log_warnings: Option<String>,
+    pub divergence_lines: Option<String>,
+    pub net_dev: Option<String>,
+    /// Bytes of the case directory and interface counters during an output-bound phase
+    #[serde(default)]
+    pub output: Option<String>,
+    /// Milliseconds taken by the connection and SSH handshake
+    #[serde(default)]
+    pub ssh_latency_ms: Option<u64>,
+    /// Username the SSH session authenticated as
+    #[serde(default)]
+    pub ssh_username: Option<String>,
+}
+
+impl InstanceResults {
+    /// Check whether the step, process or connection status changed since the previous cycle
+    pub fn has_changed_since(&self, previous: Option<&InstanceResults>) -> bool {
+        let Some(previous) = previous else {
+            return true;
+        };
+        let step = |r: &InstanceResults| r.timestep_result.as_ref().map(|ts| ts.step);
+        step(self) != step(previous)
+            || self.current_process != previous.current_process
+            || self.connection_error != previous.connection_error
+    }
 }
 
 #[derive(Debug, Default, Clone)]
@@ -33,67 +212,244 @@ pub struct TimeStep {
     pub time: f64,
     pub total_step: usize,
     pub step_increase: Option<usize>,
+    /// When the step was read from the instance
+    pub observed_at: Option<DateTime<Utc>>,
+    /// Minutes elapsed since the previous observation, i.e. the period of `step_increase`
+    pub interval_minutes: Option<f64>,
+    /// Whether `total_step` is the configured default, the case having no
+    /// known wind speed
+    pub assumed_total: bool,
 }
 
 impl TimeStep {
-    pub fn new(case: &str, time_step: &str) -> Result<Self, MonitorError> {
+    /// Parse a step log line, e.g. `TimeStep`, taking the total number of steps from
+    /// `total_step` when given, else from the wind speed in the case name
+    /// looked up in `wind_speed_steps`, else from `default_total_step` marked
+    /// as assumed
+    pub fn new(
+        case: &str,
+        time_step: &str,
+        total_step: Option<usize>,
+        wind_speed_steps: &WindSpeedSteps,
+        default_total_step: Option<usize>,
+    ) -> Result<Self, MonitorError> {
+        Self::parse(
+            case,
+            time_step,
+            total_step,
+            wind_speed_steps,
+            default_total_step,
+            true,
+        )
+    }
+
+    /// Parse a `TimeStep` log line like [`TimeStep::new`], but keep the step and
+    /// time with a `total_step` of 0 when the total number of steps is unknown
+    pub fn new_lenient(
+        case: &str,
+        time_step: &str,
+        total_step: Option<usize>,
+        wind_speed_steps: &WindSpeedSteps,
+        default_total_step: Option<usize>,
+    ) -> Result<Self, MonitorError> {
+        Self::parse(
+            case,
+            time_step,
+            total_step,
+            wind_speed_steps,
+            default_total_step,
+            false,
+        )
+    }
+
+    fn parse(
+        case: &str,
+        time_step: &str,
+        total_step: Option<usize>,
+        wind_speed_steps: &WindSpeedSteps,
+        default_total_step: Option<usize>,
+        strict: bool,
+    ) -> Result<Self, MonitorError> {
         let Some(i) = time_step.find(':') else {
             return Ok(Default::default());
         };
         let (a, b) = time_step.split_at(i);
-        let steps = case.split('_').find_map(|x| match x {
-            "2ms" => Some(24_000),
-            "7ms" | "12ms" | "17ms" => Some(18_000),
-            _ => None,
-        });
+        let steps = total_step.or_else(|| wind_speed_steps.total_step(case));
+        // Skip the step marker, whatever its length
+        let step = a
+            .trim_start_matches(|c: char| !c.is_ascii_digit())
+            .trim()
+            .parse::<usize>()?;
+        // Scan for the value rather than assume the `: Time` label length
+        let time_value = crate::solver::labelled_value(&b[1..]).ok_or_else(|| {
+            MonitorError::TimestepParsing {
+                reason: format!("no time value in {b:?}"),
+            }
+        })?;
+        let time = crate::solver::parse_time(time_value)?;
+        let (total_step, assumed_total) = match (steps, default_total_step) {
+            (Some(steps), _) => (steps, false),
+            (None, Some(default)) => (default, true),
+            (None, None) if strict => {
+                return Err(MonitorError::InvalidWindSpeed {
+                    known: wind_speed_steps.known_suffixes().join(", "),
+                });
+            }
+            (None, None) => (0, false),
+        };
         Ok(Self {
-            step: a[8..].trim().parse::<usize>()?,
-            time: b[6..].trim().parse::<f64>()?,
-            total_step: steps.ok_or(MonitorError::InvalidWindSpeed)?,
+            step,
+            time,
+            total_step,
             step_increase: None,
+            observed_at: Some(Utc::now()),
+            interval_minutes: None,
+            assumed_total,
         })
     }
 
+    /// Previous timestep to report again when the `TimeStep` log read came back
+    /// empty, e.g. while the solver log was rotated, unless it was already
+    /// carried forward in the previous cycle
+    pub fn carry_forward(
+        time_step: &str,
+        previous: Option<&TimeStep>,
+        was_stale: bool,
+    ) -> Option<TimeStep> {
+        if !time_step.trim().is_empty() || was_stale {
+            return None;
+        }
+        previous.cloned()
+    }
+
+    /// Check whether the simulation reached its total number of steps
+    pub fn is_complete(&self) -> bool {
+        self.total_step > 0 && self.step >= self.total_step
+    }
+
     pub fn calculate_eta(&self) -> Option<String> {
-        // Only calculate ETA if we have a step increase (not the first run)
-        if let Some(step_increase) = self.step_increase {
-            if step_increase > 0 {
-                let remaining_steps = self.total_step.saturating_sub(self.step);
-                if remaining_steps == 0 {
-                    return Some("Complete".to_string());
-                }
+        // Without a total number of steps there is nothing to count down to
+        if self.total_step == 0 {
+            return Some(crate::eta::UNKNOWN_CASE.to_string());
+        }
 
-                // Calculate minutes needed based on current rate
-                // step_increase is over 6 minutes, so minutes per step = 6 / step_increase
-                let minutes_per_step = 6.0 / step_increase as f64;
-                let total_minutes = remaining_steps as f64 * minutes_per_step;
-
-                // Convert to days, hours and minutes
-                let total_hours = total_minutes / 60.0;
-                let days = (total_hours / 24.0).floor() as u64;
-                let hours = (total_hours % 24.0).floor() as u64;
-                let minutes = (total_minutes % 60.0).round() as u64;
-
-                if days > 0 {
-                    if hours > 0 {
-                        return Some(format!("{}d {}h {}m", days, hours, minutes));
-                    } else {
-                        return Some(format!("{}d {}m", days, minutes));
-                    }
-                } else if hours > 0 {
-                    return Some(format!("{}h {}m", hours, minutes));
-                } else {
-                    return Some(format!("{}m", minutes));
-                }
+        // Only calculate ETA if we have a step increase (not the first run)
+        let step_increase = self.step_increase?;
+        // A finished job no longer advances without being stalled
+        if self.is_complete() {
+            return Some(crate::eta::COMPLETE.to_string());
+        }
+        if step_increase == 0 {
+            return Some(crate::eta::STALLED.to_string());
+        }
+        self.remaining_minutes().map(|minutes| {
+            if minutes > 0.0 {
+                crate::eta::format_remaining(minutes)
             } else {
-                return Some("Stalled".to_string());
+                crate::eta::COMPLETE.to_string()
             }
+        })
+    }
+
+    /// Minutes left until the total number of steps at the rate of the last
+    /// interval, 0 once complete, or `None` without a progressing rate
+    pub fn remaining_minutes(&self) -> Option<f64> {
+        let step_increase = self.step_increase.filter(|&increase| increase > 0)?;
+        if self.total_step == 0 {
+            return None;
         }
+        let remaining_steps = self.total_step.saturating_sub(self.step);
+
+        // Calculate minutes needed based on current rate, step_increase being
+        // over the measured interval, or the nominal refresh interval if unknown
+        let interval_minutes = self
+            .interval_minutes
+            .unwrap_or(crate::schedule::REFRESH_INTERVAL.as_secs_f64() / 60.0);
+        let minutes_per_step = interval_minutes / step_increase as f64;
+        Some(remaining_steps as f64 * minutes_per_step)
+    }
+}
+
+/// Total number of steps of the cases by the wind speed suffix of their name, e.g. `2ms`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WindSpeedSteps(HashMap<String, usize>);
+
+impl Default for WindSpeedSteps {
+    fn default() -> Self {
+        Self(HashMap::from([
+            ("2ms".to_string(), 24_000),
+            ("7ms".to_string(), 18_000),
+            ("12ms".to_string(), 18_000),
+            ("17ms".to_string(), 18_000),
+        ]))
+    }
+}
+
+impl WindSpeedSteps {
+    /// Total number of steps of a case, from the wind speed in its name
+    pub fn total_step(&self, case: &str) -> Option<usize> {
+        normalize_wind_speed(case).and_then(|speed| self.0.get(&speed).copied())
+    }
+
+    /// Add the entries of `other`, replacing those of the same wind speed
+    pub fn extend(&mut self, other: WindSpeedSteps) {
+        self.0.extend(other.0);
+    }
+
+    /// Known wind speed suffixes, in increasing order of wind speed
+    pub fn known_suffixes(&self) -> Vec<&str> {
+        let mut suffixes: Vec<&str> = self.0.keys().map(String::as_str).collect();
+        suffixes.sort_by_key(|suffix| {
+            suffix
+                .trim_end_matches("ms")
+                .parse::<usize>()
+                .unwrap_or(usize::MAX)
+        });
+        suffixes
+    }
+}
+
+impl FromStr for WindSpeedSteps {
+    type Err = String;
 
-        None // First run, no ETA available
+    /// Parse comma-separated `SPEED=STEPS` entries, e.g. `2ms=36000,22ms=18000`,
+    /// the speed with or without its `ms` suffix
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.split(',')
+            .map(|entry| {
+                let invalid =
+                    || format!("invalid wind speed steps {entry:?}, expected e.g. 2ms=36000");
+                let (speed, steps) = entry.split_once('=').ok_or_else(invalid)?;
+                let speed = speed.trim();
+                let speed = speed.strip_suffix("ms").unwrap_or(speed);
+                if speed.is_empty() || !speed.chars().all(|c| c.is_ascii_digit()) {
+                    return Err(invalid());
+                }
+                let steps = steps.trim().parse::<usize>().map_err(|_| invalid())?;
+                Ok((format!("{speed}ms"), steps))
+            })
+            .collect::<Result<HashMap<_, _>, _>>()
+            .map(Self)
     }
 }
 
+/// Find the wind speed suffix of a case name and normalize it to the "{n}ms" form,
+/// accepting the `_2ms`, `_2m_s` and `_U2` spellings
+pub fn normalize_wind_speed(case: &str) -> Option<String> {
+    let parts: Vec<&str> = case.split('_').collect();
+    parts.iter().enumerate().find_map(|(i, part)| {
+        let speed = part
+            .strip_suffix("ms")
+            .or_else(|| part.strip_prefix('U'))
+            .or_else(|| {
+                part.strip_suffix('m')
+                    .filter(|_| parts.get(i + 1) == Some(&"s"))
+            })?;
+        (!speed.is_empty() && speed.chars().all(|c| c.is_ascii_digit()))
+            .then(|| format!("{speed}ms"))
+    })
+}
+
 impl Display for TimeStep {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         if let Some(inc) = self.step_increase {
@@ -103,3 +459,199 @@ impl Display for TimeStep {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn probed(step: usize, process: &str) -> InstanceResults {
+        InstanceResults {
+            name: "node-1".to_string(),
+            timestep_result: Some(TimeStep {
+                step,
+                ..TimeStep::default()
+            }),
+            current_process: Some(process.to_string()),
+            ..InstanceResults::default()
+        }
+    }
+
+    #[test]
+    fn a_first_observation_is_a_change() {
+        assert!(probed(100, "zcsvs").has_changed_since(None));
+    }
+
+    #[test]
+    fn an_identical_observation_is_no_change() {
+        let previous = probed(100, "zcsvs");
+        let current = InstanceResults {
+            csv_count: Some(12),
+            free_disk_space: Some("20G".to_string()),
+            ..probed(100, "zcsvs")
+        };
+        assert!(!current.has_changed_since(Some(&previous)));
+    }
+
+    #[test]
+    fn step_process_and_connection_changes_are_changes() {
+        let previous = probed(100, "zcsvs");
+        assert!(probed(120, "zcsvs").has_changed_since(Some(&previous)));
+        assert!(probed(100, "s3 sync").has_changed_since(Some(&previous)));
+        let failed = InstanceResults {
+            connection_error: Some("Handshake timeout".to_string()),
+            ..probed(100, "zcsvs")
+        };
+        assert!(failed.has_changed_since(Some(&previous)));
+    }
+
+    #[test]
+    fn wind_speed_variants_are_normalized() {
+        for case in ["wake_2ms", "wake_2m_s", "wake_U2", "wake_2ms_refined"] {
+            assert_eq!(normalize_wind_speed(case).as_deref(), Some("2ms"), "{case}");
+        }
+        assert_eq!(
+            WindSpeedSteps::default().total_step("wake_U2"),
+            Some(24_000)
+        );
+    }
+
+    #[test]
+    fn unknown_wind_speed_is_rejected() {
+        assert_eq!(normalize_wind_speed("wake_fast"), None);
+        let err = TimeStep::new(
+            "wake_U5",
+            "TimeStep 100: Time = 0.5",
+            None,
+            &WindSpeedSteps::default(),
+            None,
+        )
+        .unwrap_err();
+        assert!(matches!(err, MonitorError::InvalidWindSpeed { .. }));
+    }
+
+    #[test]
+    fn step_and_time_survive_an_unknown_wind_speed() {
+        let ts = TimeStep::new_lenient(
+            "wake_U5",
+            "TimeStep 100: Time = 0.5",
+            None,
+            &WindSpeedSteps::default(),
+            None,
+        )
+        .unwrap();
+        assert_eq!(ts.step, 100);
+        assert_eq!(ts.time, 0.5);
+        assert_eq!(ts.total_step, 0);
+        assert_eq!(
+            ts.calculate_eta().as_deref(),
+            Some(crate::eta::UNKNOWN_CASE)
+        );
+    }
+
+    #[test]
+    fn numeric_and_string_etas_agree() {
+        for (step, step_increase, interval_minutes) in
+            [(1000, 100, 6.0), (17_500, 250, 6.0), (5000, 30, 10.0)]
+        {
+            let ts = TimeStep {
+                step,
+                total_step: 18_000,
+                step_increase: Some(step_increase),
+                interval_minutes: Some(interval_minutes),
+                ..TimeStep::default()
+            };
+            let minutes = ts.remaining_minutes().unwrap();
+            let eta = ts.calculate_eta().unwrap();
+            assert_eq!(eta, crate::eta::format_remaining(minutes));
+            let parsed = crate::eta::parse_eta_to_minutes(&eta).unwrap();
+            assert!((parsed - minutes).abs() <= 0.5, "{eta} vs {minutes}");
+        }
+    }
+
+    #[test]
+    fn previous_timestep_is_carried_forward_once_on_an_empty_read() {
+        let previous = TimeStep {
+            step: 420,
+            total_step: 1000,
+            ..TimeStep::default()
+        };
+        let carried = TimeStep::carry_forward("  \n", Some(&previous), false).unwrap();
+        assert_eq!(carried.step, 420);
+        assert_eq!(carried.total_step, 1000);
+
+        assert!(TimeStep::carry_forward("", Some(&previous), true).is_none());
+        assert!(TimeStep::carry_forward("", None, false).is_none());
+        assert!(TimeStep::carry_forward("TimeStep 421", Some(&previous), false).is_none());
+    }
+
+    #[test]
+    fn time_is_found_after_any_label() {
+        let parse =
+            |line: &str| TimeStep::new("wake_2ms", line, None, &WindSpeedSteps::default(), None);
+        for line in [
+            "TimeStep 42: Time = 12.5",
+            "TimeStep 42:t=12.5",
+            "TimeStep 42:12.5",
+        ] {
+            let ts = parse(line).unwrap();
+            assert_eq!((ts.step, ts.time), (42, 12.5), "{line}");
+        }
+        assert_eq!(
+            crate::solver::labelled_value(": Time = -1e-3"),
+            Some("-1e-3")
+        );
+
+        // Labels shorter than `: Time` used to be sliced past their end
+        for line in ["TimeStep 42:", "TimeStep 42:t", "TimeStep 42: T="] {
+            assert!(
+                matches!(parse(line), Err(MonitorError::TimestepParsing { .. })),
+                "{line}"
+            );
+        }
+    }
+
+    #[test]
+    fn default_total_step_is_applied_last_and_marked_assumed() {
+        let steps = WindSpeedSteps::default();
+        let line = "TimeStep 100: Time = 0.5";
+
+        let ts = TimeStep::new("wake_calm", line, None, &steps, Some(18_000)).unwrap();
+        assert_eq!(ts.total_step, 18_000);
+        assert!(ts.assumed_total);
+
+        let ts = TimeStep::new("wake_2ms", line, None, &steps, Some(18_000)).unwrap();
+        assert_eq!(ts.total_step, 24_000);
+        assert!(!ts.assumed_total);
+
+        let ts = TimeStep::new("wake_calm", line, Some(5000), &steps, Some(18_000)).unwrap();
+        assert_eq!(ts.total_step, 5000);
+        assert!(!ts.assumed_total);
+
+        assert!(matches!(
+            TimeStep::new("wake_calm", line, None, &steps, None),
+            Err(MonitorError::InvalidWindSpeed { .. })
+        ));
+    }
+
+    #[test]
+    fn complete_job_without_new_steps_is_complete_on_a_full_disk() {
+        let ts = TimeStep {
+            step: 18_000,
+            total_step: 18_000,
+            step_increase: Some(0),
+            ..TimeStep::default()
+        };
+        let eta = ts.calculate_eta().unwrap();
+        assert_eq!(eta, crate::eta::COMPLETE);
+        assert_eq!(
+            crate::eta::with_stall_cause(eta, Some("512M")),
+            crate::eta::COMPLETE
+        );
+
+        let stalled = TimeStep { step: 9000, ..ts };
+        assert_eq!(
+            crate::eta::with_stall_cause(stalled.calculate_eta().unwrap(), Some("512M")),
+            crate::eta::STALLED_DISK_FULL
+        );
+    }
+}